@@ -0,0 +1,207 @@
+//! Routing performance benchmarks on a synthetic street grid, so they run
+//! without the (large, untracked) PBF extract.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use petgraph::graph::NodeIndex;
+use routing::geo_util::haversine_meters;
+use routing::graph::{GeoNode, HighwayKind, NavigationGraph, RouteOptions, Surface, WalkEdge};
+use routing::incidents::{Incident, IncidentLayer};
+use routing::safety::{RiskBreakdown, RiskCache, SafetyConfig, SafetyMap};
+use std::collections::HashMap;
+use std::hint::black_box;
+
+/// Grid spacing in degrees (~55 m of latitude)
+const SPACING: f64 = 0.0005;
+const ORIGIN: (f64, f64) = (30.34, 76.37); // Patiala
+
+/// Builds an `n x n` grid of two-way streets with deterministic pseudo-random risk scores
+fn synthetic_grid(n: usize) -> NavigationGraph {
+    let mut seed: u64 = 0x5AFE_3A1C;
+    let mut next_risk = || {
+        // xorshift64: cheap, deterministic, good enough for benchmark data
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        0.05 + (seed % 950) as f32 / 1000.0
+    };
+
+    let nodes: Vec<GeoNode> = (0..n * n)
+        .map(|i| {
+            let (row, col) = ((i / n) as f64, (i % n) as f64);
+            GeoNode { lat: ORIGIN.0 + row * SPACING, lon: ORIGIN.1 + col * SPACING }
+        })
+        .collect();
+
+    let mut edges = Vec::new();
+    for row in 0..n {
+        for col in 0..n {
+            let here = row * n + col;
+            let neighbours = [(col + 1 < n).then_some(here + 1), (row + 1 < n).then_some(here + n)];
+            for there in neighbours.into_iter().flatten() {
+                let (a, b) = (nodes[here], nodes[there]);
+                let risk = next_risk();
+                let edge = WalkEdge {
+                    distance_meters: haversine_meters([a.lat, a.lon], [b.lat, b.lon]),
+                    safety_score: risk,
+                    base_safety: risk,
+                    is_tunnel: false,
+                    crossing: None,
+                    name: None,
+                    risk: RiskBreakdown::default(),
+                    shade: 0.0,
+                    enclosure: 0.0,
+                    busy: 0.0,
+                    flooded: false,
+                    boundary_penalty: 0.0,
+                    surface: Surface::Paved,
+                    smoothness: None,
+                    incline_percent: None,
+                    highway: HighwayKind::Footway,
+                    private: false,
+                    way_id: None,
+                    foot_closed: None,
+                };
+                edges.push((here, there, edge));
+                edges.push((there, here, edge));
+            }
+        }
+    }
+
+    NavigationGraph::from_edges(&nodes, &edges)
+}
+
+fn bench_route(c: &mut Criterion) {
+    let n = 100;
+    let nav = synthetic_grid(n);
+    let node = |row: usize, col: usize| NodeIndex::new(row * n + col);
+
+    let pairs = [
+        ("short", node(50, 50), node(55, 52)),
+        ("medium", node(20, 20), node(60, 70)),
+        ("long", node(0, 0), node(n - 1, n - 1)),
+    ];
+
+    let mut group = c.benchmark_group("route");
+    for (label, start, end) in pairs {
+        for alpha in [0.0, 2.0, 5.0] {
+            let options = RouteOptions { alpha, ..Default::default() };
+            group.bench_with_input(BenchmarkId::new(label, alpha), &options, |b, options| {
+                b.iter(|| nav.route(black_box(start), black_box(end), options))
+            });
+        }
+    }
+    group.finish();
+
+    // The same queries with precomputed per-alpha edge costs
+    let mut cached = nav.clone();
+    cached.cache_alpha_costs(1);
+    let mut group = c.benchmark_group("route_cached_alpha");
+    for (label, start, end) in pairs {
+        let options = RouteOptions { alpha: 2.0, ..Default::default() };
+        group.bench_with_input(BenchmarkId::new(label, 2.0), &options, |b, options| {
+            b.iter(|| cached.route(black_box(start), black_box(end), options))
+        });
+    }
+    group.finish();
+
+    // Weighted A*: fewer settled nodes in exchange for possibly suboptimal routes
+    let mut group = c.benchmark_group("route_weighted");
+    for (label, start, end) in pairs {
+        for heuristic_weight in [1.0, 1.5] {
+            let options = RouteOptions { alpha: 2.0, heuristic_weight, ..Default::default() };
+            group.bench_with_input(BenchmarkId::new(label, heuristic_weight), &options, |b, options| {
+                b.iter(|| nav.route(black_box(start), black_box(end), options))
+            });
+        }
+    }
+    group.finish();
+
+    // Same queries with ALT landmarks tightening the heuristic
+    let mut nav = nav;
+    nav.build_landmarks(8);
+    let mut group = c.benchmark_group("route_alt");
+    for (label, start, end) in pairs {
+        let options = RouteOptions { alpha: 2.0, ..Default::default() };
+        group.bench_with_input(BenchmarkId::new(label, 2.0), &options, |b, options| {
+            b.iter(|| nav.route(black_box(start), black_box(end), options))
+        });
+    }
+    group.finish();
+}
+
+fn bench_find_nearest_node(c: &mut Criterion) {
+    let nav = synthetic_grid(100);
+    let (lat, lon) = (ORIGIN.0 + 37.3 * SPACING, ORIGIN.1 + 61.8 * SPACING);
+
+    c.bench_function("find_nearest_node", |b| {
+        b.iter(|| nav.find_nearest_node(black_box(lat), black_box(lon)))
+    });
+}
+
+fn bench_apply_location_risk(c: &mut Criterion) {
+    let nav = synthetic_grid(100);
+    let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+    // An incident every 10 blocks in both directions
+    let incidents: Vec<Incident> = (0..100).step_by(10)
+        .flat_map(|row| (0..100).step_by(10).map(move |col| Incident {
+            lat: ORIGIN.0 + row as f64 * SPACING,
+            lon: ORIGIN.1 + col as f64 * SPACING,
+            severity: 1.0,
+            timestamp: None,
+        }))
+        .collect();
+    let layer = IncidentLayer::new(&incidents);
+    let single_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+
+    let mut group = c.benchmark_group("apply_location_risk");
+    group.bench_function("parallel", |b| {
+        b.iter_batched(|| nav.clone(), |mut nav| nav.apply_location_risk(&[&layer], &safety_map), BatchSize::LargeInput)
+    });
+    group.bench_function("sequential", |b| {
+        b.iter_batched(
+            || nav.clone(),
+            |mut nav| single_thread.install(|| nav.apply_location_risk(&[&layer], &safety_map)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+/// Scoring a city's worth of ways during a graph build, where most share a handful of
+/// tag combinations
+fn bench_edge_risk(c: &mut Criterion) {
+    let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+    let highways = ["footway", "residential", "primary", "service", "path", "living_street"];
+    let lit = ["yes", "no"];
+    let surfaces = ["asphalt", "paving_stones", "gravel", "dirt"];
+    let speeds = ["30", "50", "60 mph"];
+    let ways: Vec<HashMap<&str, &str>> = (0..100_000)
+        .map(|i: usize| {
+            let mut tags = HashMap::from([
+                ("highway", highways[i % highways.len()]),
+                ("lit", lit[i / 7 % lit.len()]),
+                ("surface", surfaces[i / 3 % surfaces.len()]),
+                ("name", "Some Street"),
+            ]);
+            if i.is_multiple_of(5) {
+                tags.insert("maxspeed", speeds[i / 5 % speeds.len()]);
+            }
+            tags
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("edge_risk");
+    group.bench_function("uncached", |b| {
+        b.iter(|| ways.iter().map(|tags| safety_map.calculate_edge_risk(tags).total()).sum::<f32>())
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            let mut cache = RiskCache::default();
+            ways.iter().map(|tags| cache.calculate_edge_risk(&safety_map, tags).total()).sum::<f32>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_route, bench_find_nearest_node, bench_apply_location_risk, bench_edge_risk);
+criterion_main!(benches);
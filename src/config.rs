@@ -0,0 +1,221 @@
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use crate::graph::WalkableHighways;
+
+/// One area served from its own OSM extract
+#[derive(Debug, Clone)]
+pub struct RegionConfig {
+    pub id: String,
+    pub pbf_path: String,
+    /// `[min_lon, min_lat, max_lon, max_lat]`, so requests can be matched to the region before it is loaded
+    pub bbox: Option<[f64; 4]>,
+}
+
+/// PEM certificate chain and private key for serving HTTPS
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// Server-level settings, read once at startup
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Safety preference for route requests that don't send an `alpha`
+    pub default_alpha: f64,
+    /// Extra cost factor for service roads and private ways in requests that don't send a
+    /// `service_penalty`
+    pub service_penalty: f64,
+    /// Coordinates farther than this from the nearest graph node are rejected
+    pub max_snap_meters: f64,
+    /// Optional GeoJSON file of zones (flood areas, ...) applied to the graph at startup
+    pub zones_path: Option<String>,
+    /// Optional CSV of incidents (`lat,lon,severity[,timestamp]`) loaded at startup
+    pub incidents_path: Option<String>,
+    /// Optional single-band GeoTIFF of location risk (crime heat, ...) applied at startup
+    pub risk_raster_path: Option<String>,
+    /// Most via points a single route request may have
+    pub max_waypoints: usize,
+    /// ALT landmarks to precompute at startup (0 = plain A*)
+    pub landmarks: usize,
+    /// Alphas to keep precomputed edge costs for, per graph (0 = none)
+    pub cached_alphas: usize,
+    /// Number of computed routes kept in the LRU cache
+    pub route_cache_size: NonZeroUsize,
+    /// Decimal places route coordinates are rounded to before snapping and caching.
+    /// 5 decimals ≈ 1.1 m of latitude; each place less is 10x coarser.
+    pub coordinate_decimals: u32,
+    /// Threads for recomputing edge risk (zones, incidents, rasters); `None` = one per CPU core
+    pub recompute_threads: Option<usize>,
+    /// Requests taking longer than this are logged at `warn`; `None` = never
+    pub slow_request_threshold: Option<Duration>,
+    /// `highway` values turned into edges
+    pub walkable: WalkableHighways,
+    /// Segments shorter than this are merged into their neighbours while loading; `None` keeps all
+    pub min_edge_length_meters: Option<f64>,
+    /// Honour `restriction:foot` turn restrictions (an extra pass over the PBF)
+    pub turn_restrictions: bool,
+    /// Refuse to load PBF files with more nodes than this (guards against OOM)
+    pub max_nodes: Option<usize>,
+    /// Bearer token for `/admin/*` endpoints; they are disabled without one
+    pub admin_token: Option<String>,
+    /// Serve HTTPS with this certificate instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// Areas to load, each into its own graph; the first is the default. Never empty.
+    pub regions: Vec<RegionConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            default_alpha: 2.0,
+            service_penalty: 4.0,
+            max_snap_meters: 500.0,
+            zones_path: None,
+            incidents_path: None,
+            risk_raster_path: None,
+            max_waypoints: 25,
+            landmarks: 0,
+            cached_alphas: 0,
+            route_cache_size: NonZeroUsize::new(1024).unwrap(),
+            coordinate_decimals: 5,
+            recompute_threads: None,
+            slow_request_threshold: None,
+            walkable: WalkableHighways::default(),
+            min_edge_length_meters: None,
+            turn_restrictions: false,
+            max_nodes: None,
+            admin_token: None,
+            tls: None,
+            regions: vec![RegionConfig { id: "default".to_string(), pbf_path: "assets/patiala.osm.pbf".to_string(), bbox: None }],
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads overrides from `SAFEWALK_*` environment variables, falling back to defaults
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        if let Some(v) = env_number("SAFEWALK_DEFAULT_ALPHA")? {
+            config.default_alpha = v;
+        }
+        if let Some(v) = env_number("SAFEWALK_SERVICE_PENALTY")? {
+            config.service_penalty = v;
+        }
+        if let Some(v) = env_number("SAFEWALK_MAX_SNAP_METERS")? {
+            config.max_snap_meters = v;
+        }
+        config.zones_path = std::env::var("SAFEWALK_ZONES_PATH").ok();
+        config.incidents_path = std::env::var("SAFEWALK_INCIDENTS_PATH").ok();
+        config.risk_raster_path = std::env::var("SAFEWALK_RISK_RASTER_PATH").ok();
+        if let Some(v) = env_number("SAFEWALK_MAX_WAYPOINTS")? {
+            config.max_waypoints = v;
+        }
+        if let Some(v) = env_number("SAFEWALK_LANDMARKS")? {
+            config.landmarks = v;
+        }
+        if let Some(v) = env_number("SAFEWALK_CACHED_ALPHAS")? {
+            config.cached_alphas = v;
+        }
+        if let Some(v) = env_number("SAFEWALK_ROUTE_CACHE_SIZE")? {
+            config.route_cache_size = v;
+        }
+        if let Some(v) = env_number("SAFEWALK_COORDINATE_DECIMALS")? {
+            config.coordinate_decimals = v;
+        }
+        config.recompute_threads = env_number("SAFEWALK_RECOMPUTE_THREADS")?;
+        config.slow_request_threshold = env_number("SAFEWALK_SLOW_REQUEST_MS")?.map(Duration::from_millis);
+        if let Ok(v) = std::env::var("SAFEWALK_WALKABLE_HIGHWAYS") {
+            config.walkable.always = parse_list(&v);
+        }
+        if let Ok(v) = std::env::var("SAFEWALK_FOOT_ACCESS_HIGHWAYS") {
+            config.walkable.with_foot_access = parse_list(&v);
+        }
+        config.min_edge_length_meters = env_number("SAFEWALK_MIN_EDGE_LENGTH_METERS")?;
+        if let Some(v) = env_flag("SAFEWALK_TURN_RESTRICTIONS")? {
+            config.turn_restrictions = v;
+        }
+        config.max_nodes = env_number("SAFEWALK_MAX_NODES")?;
+        config.admin_token = std::env::var("SAFEWALK_ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+        config.tls = match (std::env::var("SAFEWALK_TLS_CERT").ok(), std::env::var("SAFEWALK_TLS_KEY").ok()) {
+            (Some(cert_path), Some(key_path)) => Some(TlsConfig { cert_path, key_path }),
+            (None, None) => None,
+            _ => anyhow::bail!("SAFEWALK_TLS_CERT and SAFEWALK_TLS_KEY must be set together"),
+        };
+        if let Ok(v) = std::env::var("SAFEWALK_REGIONS") {
+            config.regions = parse_regions(&v)?;
+        }
+        anyhow::ensure!(
+            config.default_alpha.is_finite() && config.default_alpha >= 0.0,
+            "SAFEWALK_DEFAULT_ALPHA must be a non-negative number"
+        );
+        anyhow::ensure!(
+            config.service_penalty.is_finite() && config.service_penalty >= 0.0,
+            "SAFEWALK_SERVICE_PENALTY must be a non-negative number"
+        );
+        anyhow::ensure!(config.coordinate_decimals <= 10, "SAFEWALK_COORDINATE_DECIMALS must be at most 10");
+        anyhow::ensure!(config.recompute_threads != Some(0), "SAFEWALK_RECOMPUTE_THREADS must be positive");
+        anyhow::ensure!(config.max_snap_meters > 0.0, "SAFEWALK_MAX_SNAP_METERS must be positive");
+        anyhow::ensure!(
+            config.min_edge_length_meters.is_none_or(|m| m.is_finite() && m >= 0.0),
+            "SAFEWALK_MIN_EDGE_LENGTH_METERS must be a non-negative number"
+        );
+        Ok(config)
+    }
+}
+
+/// Splits a comma-separated list, dropping blanks
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+}
+
+/// Parses `id=path;id=path@min_lon,min_lat,max_lon,max_lat` region lists. Only the first
+/// (default) region may omit the bbox: it is loaded at startup, the others are matched by it.
+fn parse_regions(value: &str) -> anyhow::Result<Vec<RegionConfig>> {
+    let mut regions: Vec<RegionConfig> = Vec::new();
+    for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((id, rest)) = entry.split_once('=') else {
+            anyhow::bail!("SAFEWALK_REGIONS entries must look like `id=path`, got `{entry}`");
+        };
+        let (path, bbox) = match rest.split_once('@') {
+            Some((path, bbox)) => (path, Some(parse_region_bbox(bbox)?)),
+            None => (rest, None),
+        };
+        let (id, path) = (id.trim(), path.trim());
+        anyhow::ensure!(!id.is_empty() && !path.is_empty(), "SAFEWALK_REGIONS entry `{entry}` is incomplete");
+        anyhow::ensure!(regions.iter().all(|r| r.id != id), "SAFEWALK_REGIONS lists `{id}` twice");
+        anyhow::ensure!(
+            regions.is_empty() || bbox.is_some(),
+            "SAFEWALK_REGIONS region `{id}` needs a bbox (`{id}=path@min_lon,min_lat,max_lon,max_lat`); only the first may omit it"
+        );
+        regions.push(RegionConfig { id: id.to_string(), pbf_path: path.to_string(), bbox });
+    }
+    anyhow::ensure!(!regions.is_empty(), "SAFEWALK_REGIONS lists no regions");
+    Ok(regions)
+}
+
+fn parse_region_bbox(value: &str) -> anyhow::Result<[f64; 4]> {
+    let parts: Vec<f64> = value.split(',').map(|p| p.trim().parse()).collect::<Result<_, _>>()
+        .map_err(|_| anyhow::anyhow!("SAFEWALK_REGIONS bbox `{value}` is not four numbers"))?;
+    let Ok([min_lon, min_lat, max_lon, max_lat]) = <[f64; 4]>::try_from(parts) else {
+        anyhow::bail!("SAFEWALK_REGIONS bbox `{value}` must be min_lon,min_lat,max_lon,max_lat");
+    };
+    anyhow::ensure!(min_lon < max_lon && min_lat < max_lat, "SAFEWALK_REGIONS bbox `{value}` is empty");
+    Ok([min_lon, min_lat, max_lon, max_lat])
+}
+
+/// Parses an optional `true`/`false` environment variable
+fn env_flag(name: &str) -> anyhow::Result<Option<bool>> {
+    match std::env::var(name) {
+        Ok(v) => v.parse().map(Some).map_err(|_| anyhow::anyhow!("{name} must be true or false: {v}")),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Parses an optional numeric environment variable
+fn env_number<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>> {
+    match std::env::var(name) {
+        Ok(v) => v.parse().map(Some).map_err(|_| anyhow::anyhow!("{name} is not a valid number: {v}")),
+        Err(_) => Ok(None),
+    }
+}
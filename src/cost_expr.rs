@@ -0,0 +1,285 @@
+//! A tiny arithmetic language for experimenting with edge cost formulas at request time,
+//! e.g. `distance * (1 + alpha * safety) + 50 * crossings`.
+//!
+//! Supports numbers, `+ - * /`, parentheses, unary minus, `min(a, b)` / `max(a, b)` and the
+//! variables listed in [`Var::from_name`]. Flags evaluate to `1` or `0`. Unknown identifiers
+//! are rejected when parsing, so a typo never silently evaluates to zero.
+
+use crate::graph::{Surface, WalkEdge};
+
+/// Longest expression accepted, in bytes
+const MAX_LENGTH: usize = 500;
+
+/// Deepest nesting of parentheses / operators accepted
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct CostExpression {
+    root: Expr,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Var {
+    Distance,
+    Safety,
+    Alpha,
+    Tunnel,
+    Crossings,
+    Shade,
+    Enclosure,
+    Busy,
+    Incline,
+    Unpaved,
+    Private,
+    Boundary,
+}
+
+impl Var {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "distance" => Var::Distance,   // meters
+            "safety" => Var::Safety,       // risk score, 0 (safe) - 1 (risky)
+            "alpha" => Var::Alpha,         // the request's alpha
+            "tunnel" => Var::Tunnel,       // flag
+            "crossings" => Var::Crossings, // flag: edge steps onto a road crossing
+            "shade" => Var::Shade,         // 0 (exposed) - 1 (shaded)
+            "enclosure" => Var::Enclosure, // 0 (open) - 1 (hemmed in by buildings)
+            "busy" => Var::Busy,           // 0 (isolated) - 1 (footway lined with shops/stops)
+            "incline" => Var::Incline,     // grade in percent, 0 if untagged
+            "unpaved" => Var::Unpaved,     // flag
+            "private" => Var::Private,     // flag: access=private, normally a last resort
+            "boundary" => Var::Boundary,   // meters charged for leaving/entering boundary zones
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Var(Var),
+    Neg(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl CostExpression {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        anyhow::ensure!(source.len() <= MAX_LENGTH, "cost expression longer than {MAX_LENGTH} characters");
+
+        let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+        let root = parser.expression(0)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            anyhow::bail!("unexpected {token:?} in cost expression");
+        }
+        Ok(Self { root })
+    }
+
+    /// Cost of traversing `edge`. Never below the edge's length (nor NaN), which the A*
+    /// heuristic depends on.
+    pub fn eval(&self, edge: &WalkEdge, alpha: f64) -> f64 {
+        let cost = eval(&self.root, edge, alpha);
+        if cost.is_nan() { edge.distance_meters } else { cost.max(edge.distance_meters) }
+    }
+}
+
+fn eval(expr: &Expr, edge: &WalkEdge, alpha: f64) -> f64 {
+    let flag = |b: bool| if b { 1.0 } else { 0.0 };
+    match expr {
+        Expr::Num(n) => *n,
+        Expr::Var(var) => match var {
+            Var::Distance => edge.distance_meters,
+            Var::Safety => edge.safety_score as f64,
+            Var::Alpha => alpha,
+            Var::Tunnel => flag(edge.is_tunnel),
+            Var::Crossings => flag(edge.crossing.is_some()),
+            Var::Shade => edge.shade as f64,
+            Var::Enclosure => edge.enclosure as f64,
+            Var::Busy => edge.busy as f64,
+            Var::Incline => edge.incline_percent.unwrap_or(0.0) as f64,
+            Var::Unpaved => flag(edge.surface == Surface::Unpaved),
+            Var::Private => flag(edge.private),
+            Var::Boundary => edge.boundary_penalty as f64,
+        },
+        Expr::Neg(inner) => -eval(inner, edge, alpha),
+        Expr::Bin(op, a, b) => {
+            let (a, b) = (eval(a, edge, alpha), eval(b, edge, alpha));
+            match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                BinOp::Min => a.min(b),
+                BinOp::Max => a.max(b),
+            }
+        }
+    }
+}
+
+// --- Parsing ---
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek().filter(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = source[start..end].parse()
+                .map_err(|_| anyhow::anyhow!("invalid number `{}` in cost expression", &source[start..end]))?;
+            tokens.push(Token::Num(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(source[start..end].to_string()));
+        } else if "+-*/(),".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else {
+            anyhow::bail!("unexpected character `{c}` in cost expression");
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent: expression = term (('+' | '-') term)*, term = factor (('*' | '/') factor)*
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.tokens.get(self.pos) == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: char) -> anyhow::Result<()> {
+        anyhow::ensure!(self.eat(op), "expected `{op}` in cost expression");
+        Ok(())
+    }
+
+    fn expression(&mut self, depth: usize) -> anyhow::Result<Expr> {
+        anyhow::ensure!(depth <= MAX_DEPTH, "cost expression nested too deeply");
+        let mut left = self.term(depth)?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Bin(op, Box::new(left), Box::new(self.term(depth)?));
+        }
+    }
+
+    fn term(&mut self, depth: usize) -> anyhow::Result<Expr> {
+        let mut left = self.factor(depth)?;
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Bin(op, Box::new(left), Box::new(self.factor(depth)?));
+        }
+    }
+
+    fn factor(&mut self, depth: usize) -> anyhow::Result<Expr> {
+        anyhow::ensure!(depth <= MAX_DEPTH, "cost expression nested too deeply");
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Op('-')) => Ok(Expr::Neg(Box::new(self.factor(depth + 1)?))),
+            Some(Token::Op('(')) => {
+                let inner = self.expression(depth + 1)?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => {
+                let op = match name.as_str() {
+                    "min" => BinOp::Min,
+                    "max" => BinOp::Max,
+                    _ => {
+                        return Var::from_name(&name)
+                            .map(Expr::Var)
+                            .ok_or_else(|| anyhow::anyhow!("unknown identifier `{name}` in cost expression"));
+                    }
+                };
+                self.expect('(')?;
+                let a = self.expression(depth + 1)?;
+                self.expect(',')?;
+                let b = self.expression(depth + 1)?;
+                self.expect(')')?;
+                Ok(Expr::Bin(op, Box::new(a), Box::new(b)))
+            }
+            Some(token) => anyhow::bail!("unexpected {token:?} in cost expression"),
+            None => anyhow::bail!("cost expression ends unexpectedly"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::graph::NodeIndex;
+    use crate::graph::RouteOptions;
+    use crate::test_graphs::grid;
+
+    #[test]
+    fn default_formula_reproduces_the_default_route() {
+        let mut nav = grid(6);
+        // Uneven risk, so the safest route isn't one of the many equally short ones
+        for (i, edge) in nav.graph.edge_weights_mut().enumerate() {
+            edge.safety_score = ((i / 2 * 7) % 10) as f32 / 10.0;
+        }
+        let expr = CostExpression::parse("distance * (1 + alpha * safety) + boundary").unwrap();
+
+        for alpha in [0.0, 1.0, 5.0] {
+            let options = RouteOptions { alpha, ..Default::default() };
+            let (start, end) = (NodeIndex::new(0), NodeIndex::new(35));
+            let default = nav.route(start, end, &options).unwrap();
+            let custom = nav.route_with_cost(start, end, &options, &|edge| expr.eval(edge, alpha)).unwrap();
+            assert!((default.0 - custom.0).abs() < 1e-6, "alpha {alpha}: {} vs {}", default.0, custom.0);
+            assert_eq!(default.1, custom.1, "alpha {alpha}");
+        }
+    }
+}
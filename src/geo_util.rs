@@ -0,0 +1,63 @@
+//! Small geodesy helpers shared by the graph builder and the router.
+//! Coordinates are `[lat, lon]` in degrees, matching the API's request format.
+
+/// Mean Earth radius (IUGG), the same constant `geo` uses for haversine
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Great-circle distance in meters between two `[lat, lon]` points
+pub fn haversine_meters(a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (lat_a, lat_b) = (a[0].to_radians(), b[0].to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b[1] - a[1]).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * h.sqrt().asin()
+}
+
+/// Distance in meters from point `p` to the segment `a`-`b`, using a local
+/// equirectangular projection around `p` — accurate for street-length segments.
+pub fn point_segment_distance_meters(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let meters_per_degree = EARTH_RADIUS_METERS.to_radians();
+    let cos_lat = p[0].to_radians().cos();
+    let project = |q: [f64; 2]| ((q[1] - p[1]) * cos_lat * meters_per_degree, (q[0] - p[0]) * meters_per_degree);
+
+    let (ax, ay) = project(a);
+    let (bx, by) = project(b);
+    let (dx, dy) = (bx - ax, by - ay);
+    let len_sq = dx * dx + dy * dy;
+
+    // Parameter of the projection of the origin (= p) onto the segment, clamped to its ends
+    let t = if len_sq > 0.0 { (-(ax * dx + ay * dy) / len_sq).clamp(0.0, 1.0) } else { 0.0 };
+    let (cx, cy) = (ax + t * dx, ay + t * dy);
+    (cx * cx + cy * cy).sqrt()
+}
+
+/// Initial compass bearing in degrees `[0, 360)` when travelling from `a` to `b`
+pub fn bearing_degrees(a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (lat_a, lat_b) = (a[0].to_radians(), b[0].to_radians());
+    let d_lon = (b[1] - a[1]).to_radians();
+
+    let y = d_lon.sin() * lat_b.cos();
+    let x = lat_a.cos() * lat_b.sin() - lat_a.sin() * lat_b.cos() * d_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Smallest absolute difference between two bearings, in degrees `[0, 180]`
+pub fn bearing_difference(a: f64, b: f64) -> f64 {
+    let diff = (a - b).rem_euclid(360.0);
+    diff.min(360.0 - diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn london_to_paris() {
+        let london = [51.5074, -0.1278];
+        let paris = [48.8566, 2.3522];
+        let meters = haversine_meters(london, paris);
+        assert!((meters / 343_500.0 - 1.0).abs() < 0.005, "got {meters} m");
+        assert_eq!(haversine_meters(paris, london), meters);
+    }
+}
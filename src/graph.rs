@@ -1,9 +1,25 @@
-use std::collections::HashMap;
-use petgraph::graph::{Graph, NodeIndex};
-use osmpbf::{ElementReader, Element};
-use geo::prelude::*;
-use geo::Point;
-use crate::safety::SafetyMap;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use flate2::read::MultiGzDecoder;
+use petgraph::graph::{EdgeIndex, EdgeReference, Graph, NodeIndex};
+use petgraph::Direction;
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::EdgeRef;
+use osmpbf::{ElementReader, Element, RelMemberType};
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use geo::{BoundingRect, Contains, Coord, Intersects, LineString, Polygon, Rect};
+use crate::incidents::IncidentLayer;
+use crate::landmarks::Landmarks;
+use crate::geo_util::{bearing_degrees, bearing_difference, haversine_meters, point_segment_distance_meters};
+use crate::safety::{RiskBreakdown, RiskCache, RiskSource, SafetyMap};
+use crate::spatial::{PointIndex, SegmentIndex};
+use crate::zones::{ZoneKind, ZoneLayer};
 
 #[derive(Debug, Clone, Copy)]
 pub struct GeoNode {
@@ -11,47 +27,930 @@ pub struct GeoNode {
     pub lon: f64,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossingType {
+    TrafficSignals,
+    Uncontrolled,
+}
+
+/// A road crossing as tagged on its `highway=crossing` node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Crossing {
+    pub kind: CrossingType,
+    /// `crossing:island=yes`: a refuge island splits the road in two shorter crossings
+    pub island: bool,
+}
+
+/// Coarse surface class of a way, from its `surface` tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Surface {
+    Paved,
+    Unpaved,
+    /// Untagged, or a value we don't classify
+    #[default]
+    Unknown,
+}
+
+impl Surface {
+    pub fn from_tag(value: &str) -> Self {
+        match value {
+            "paved" | "asphalt" | "concrete" | "paving_stones" => Surface::Paved,
+            "unpaved" | "dirt" | "earth" | "gravel" | "mud" => Surface::Unpaved,
+            _ => Surface::Unknown,
+        }
+    }
+}
+
+/// OSM `smoothness` grades, ordered from worst to best so `a >= b` means "at least as smooth"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Smoothness {
+    Impassable,
+    VeryHorrible,
+    Horrible,
+    VeryBad,
+    Bad,
+    Intermediate,
+    Good,
+    Excellent,
+}
+
+impl Smoothness {
+    pub fn from_tag(value: &str) -> Option<Self> {
+        Some(match value {
+            "excellent" => Smoothness::Excellent,
+            "good" => Smoothness::Good,
+            "intermediate" => Smoothness::Intermediate,
+            "bad" => Smoothness::Bad,
+            "very_bad" => Smoothness::VeryBad,
+            "horrible" => Smoothness::Horrible,
+            "very_horrible" => Smoothness::VeryHorrible,
+            "impassable" => Smoothness::Impassable,
+            _ => return None,
+        })
+    }
+}
+
+/// The kind of way an edge belongs to, from its `highway` tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HighwayKind {
+    Footway,
+    Path,
+    Steps,
+    Pedestrian,
+    LivingStreet,
+    Residential,
+    Service,
+    Unclassified,
+    Tertiary,
+    Secondary,
+    Primary,
+    Trunk,
+    Motorway,
+    /// A `route=ferry` link rather than a highway
+    Ferry,
+    #[default]
+    Other,
+}
+
+impl HighwayKind {
+    pub fn from_tag(value: &str) -> Self {
+        match value {
+            "footway" => HighwayKind::Footway,
+            "path" => HighwayKind::Path,
+            "steps" => HighwayKind::Steps,
+            "pedestrian" => HighwayKind::Pedestrian,
+            "living_street" => HighwayKind::LivingStreet,
+            "residential" => HighwayKind::Residential,
+            "service" => HighwayKind::Service,
+            "unclassified" => HighwayKind::Unclassified,
+            "tertiary" => HighwayKind::Tertiary,
+            "secondary" => HighwayKind::Secondary,
+            "primary" => HighwayKind::Primary,
+            "trunk" => HighwayKind::Trunk,
+            "motorway" => HighwayKind::Motorway,
+            _ => HighwayKind::Other,
+        }
+    }
+}
+
+/// Walking speeds (km/h) per highway kind, used for ETAs. Unpaved surfaces slow walkers down further.
+#[derive(Debug, Clone)]
+pub struct SpeedTable {
+    speeds_kmh: HashMap<HighwayKind, f64>,
+}
+
+impl Default for SpeedTable {
+    fn default() -> Self {
+        let speeds_kmh = HashMap::from([
+            (HighwayKind::Steps, 2.0),
+            (HighwayKind::Path, 4.5),
+            (HighwayKind::Ferry, 15.0),
+        ]);
+        Self { speeds_kmh }
+    }
+}
+
+impl SpeedTable {
+    /// Replaces entries of the table; non-positive or non-finite speeds are ignored
+    pub fn with_overrides<'a>(mut self, overrides: impl IntoIterator<Item = (&'a HighwayKind, &'a f64)>) -> Self {
+        for (&kind, &kmh) in overrides {
+            if kmh.is_finite() && kmh > 0.0 {
+                self.speeds_kmh.insert(kind, kmh);
+            }
+        }
+        self
+    }
+
+    pub fn speed_kmh(&self, edge: &WalkEdge) -> f64 {
+        let base = self.speeds_kmh.get(&edge.highway).copied().unwrap_or(DEFAULT_WALKING_SPEED_KMH);
+        if edge.surface == Surface::Unpaved && edge.highway != HighwayKind::Ferry {
+            base * UNPAVED_SPEED_FACTOR
+        } else {
+            base
+        }
+    }
+
+    /// Time needed to cover the edge, in seconds
+    pub fn edge_seconds(&self, edge: &WalkEdge) -> f64 {
+        edge.distance_meters / (self.speed_kmh(edge) / 3.6)
+    }
+}
+
+/// Mobility profile a route is computed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Foot,
+    /// Penalizes inclines above a comfortable grade, excludes very steep ones
+    Wheelchair,
+    /// Like `Wheelchair`, but only allows ramps up to about the accessibility-code limit
+    WheelchairStrict,
+}
+
+impl Profile {
+    /// Steepest grade (in percent) this profile may use at all
+    pub fn max_incline_percent(&self) -> Option<f32> {
+        match self {
+            Profile::Foot => None,
+            Profile::Wheelchair => Some(12.0),
+            Profile::WheelchairStrict => Some(8.0),
+        }
+    }
+
+    /// Cost multiplier for an edge of the given grade (always >= 1)
+    fn incline_factor(&self, incline_percent: f32) -> f64 {
+        match self {
+            Profile::Foot => 1.0,
+            Profile::Wheelchair | Profile::WheelchairStrict => {
+                1.0 + (incline_percent - COMFORTABLE_INCLINE_PERCENT).max(0.0) as f64 * INCLINE_PENALTY_PER_PERCENT
+            }
+        }
+    }
+}
+
+/// A daily time window in minutes since midnight; wraps past midnight when `end < start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TimeWindow {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl TimeWindow {
+    /// Parses `HH:MM-HH:MM`
+    pub fn parse(value: &str) -> Option<Self> {
+        let minutes = |hh_mm: &str| {
+            let (h, m) = hh_mm.trim().split_once(':')?;
+            let (h, m): (u16, u16) = (h.parse().ok()?, m.parse().ok()?);
+            (h <= 24 && m < 60 && h * 60 + m <= MINUTES_PER_DAY).then_some(h * 60 + m)
+        };
+        let (start, end) = value.split_once('-')?;
+        Some(Self { start: minutes(start)? % MINUTES_PER_DAY, end: minutes(end)? % MINUTES_PER_DAY })
+    }
+
+    /// Whether the two windows share any time of day
+    pub fn overlaps(&self, other: &TimeWindow) -> bool {
+        self.ranges().iter().flatten().any(|a| other.ranges().iter().flatten().any(|b| a.0 < b.1 && b.0 < a.1))
+    }
+
+    /// The window as up to two non-wrapping `[start, end)` ranges
+    fn ranges(&self) -> [Option<(u16, u16)>; 2] {
+        if self.start <= self.end {
+            [Some((self.start, self.end)), None]
+        } else {
+            [Some((self.start, MINUTES_PER_DAY)), Some((0, self.end))]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct WalkEdge {
     pub distance_meters: f64,
     pub safety_score: f32, 
+    /// Tag-based score (incl. crossing adjustment) before zone multipliers; `safety_score`
+    /// is recomputed from it whenever zones are applied
+    pub base_safety: f32,
+    pub is_tunnel: bool,
+    /// Set when this edge steps onto a road crossing (`highway=crossing` node)
+    pub crossing: Option<Crossing>,
+    /// Street name, interned in the graph's `NameArena` (see `NavigationGraph::name_of`)
+    pub name: Option<u32>,
+    /// Tag-based contributions behind `safety_score` (before crossing adjustments)
+    pub risk: RiskBreakdown,
+    /// Tree cover over the edge, from 0.0 (exposed) to 1.0 (fully shaded)
+    pub shade: f32,
+    /// How hemmed in by buildings the edge is, from 0.0 (open) to 1.0 (alley-like)
+    pub enclosure: f32,
+    /// Implied foot traffic from shops and transit stops nearby, from 0.0 (isolated) to 1.0;
+    /// only set on footways and pedestrian streets
+    pub busy: f32,
+    /// Lies in a flood zone (see `NavigationGraph::apply_zones`)
+    pub flooded: bool,
+    /// Extra cost in meters for crossing a boundary zone's edge, i.e. leaving or entering it
+    /// (see `NavigationGraph::apply_zones`)
+    pub boundary_penalty: f32,
+    pub surface: Surface,
+    /// `None` if the way has no (recognized) `smoothness` tag
+    pub smoothness: Option<Smoothness>,
+    /// Absolute grade from the way's `incline` tag, in percent (direction is not tracked)
+    pub incline_percent: Option<f32>,
+    pub highway: HighwayKind,
+    /// `access=private` without an explicit foot permission: costs extra like service roads
+    /// (see `RouteOptions::service_penalty`)
+    pub private: bool,
+    /// OSM way the edge was built from (`None` for graphs not built from OSM data)
+    pub way_id: Option<i64>,
+    /// Hours during which `foot:conditional` closes the way to pedestrians
+    pub foot_closed: Option<TimeWindow>,
+}
+
+/// Cost multiplier for tunnel/underpass edges when routing at night
+const NIGHT_TUNNEL_PENALTY: f64 = 10.0;
+
+/// Extra cost (in meters) per road crossing when crossings are being minimized
+const CROSSING_PENALTY_METERS: f64 = 400.0;
+
+/// Trees within this distance of an edge count towards its shade
+const SHADE_RADIUS_METERS: f64 = 20.0;
+
+/// Number of nearby trees at which an edge counts as fully shaded
+const TREES_FOR_FULL_SHADE: f32 = 3.0;
+
+/// Buildings whose centre lies within this distance of an edge count towards its enclosure
+const ENCLOSURE_RADIUS_METERS: f64 = 25.0;
+
+/// Number of nearby buildings at which an edge counts as fully enclosed
+const BUILDINGS_FOR_FULL_ENCLOSURE: f32 = 6.0;
+
+/// Risk added to a fully enclosed edge per unit of `RouteOptions::openness`
+const ENCLOSURE_RISK: f64 = 0.3;
+
+/// Shops and transit stops within this distance of a pedestrian way count towards its foot traffic
+const BUSY_RADIUS_METERS: f64 = 40.0;
+
+/// Number of nearby shops/stops at which a pedestrian way counts as fully busy
+const POIS_FOR_FULL_BUSY: f32 = 5.0;
+
+/// Risk taken off a fully busy edge per unit of `RouteOptions::prefer_busy`
+const BUSY_RISK_RELIEF: f64 = 0.2;
+
+/// Elements between two progress callbacks while loading
+const PROGRESS_INTERVAL: u64 = 100_000;
+
+/// Walking speed for highway kinds without an entry in the `SpeedTable`
+const DEFAULT_WALKING_SPEED_KMH: f64 = 5.0;
+
+/// Speed multiplier on unpaved surfaces
+const UNPAVED_SPEED_FACTOR: f64 = 0.85;
+
+/// Assumed grade of ways tagged only `incline=up`/`down`
+const UNSPECIFIED_INCLINE_PERCENT: f32 = 5.0;
+
+/// Assumed grade of ways tagged `incline=steep`
+const STEEP_INCLINE_PERCENT: f32 = 15.0;
+
+/// Grades up to this are free for wheelchair profiles
+const COMFORTABLE_INCLINE_PERCENT: f32 = 5.0;
+
+/// Wheelchair cost increase per percent of grade above the comfortable one
+const INCLINE_PENALTY_PER_PERCENT: f64 = 0.25;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// What counts as "night" for `RouteOptions::night` when checking time-limited access
+const NIGHT_HOURS: TimeWindow = TimeWindow { start: 22 * 60, end: 5 * 60 };
+
+/// Extra cost (in meters) for setting off against the user's heading
+const U_TURN_PENALTY_METERS: f64 = 200.0;
+
+/// Penalized searches `alternative_routes` runs per route asked for, before settling for fewer
+const ALTERNATIVE_ATTEMPTS_PER_ROUTE: usize = 3;
+
+/// Alphas on a grid this fine (0.01) get precomputed edge costs; others are costed per edge
+const ALPHA_CACHE_STEPS: f64 = 100.0;
+
+/// Per-request knobs of the routing cost function
+#[derive(Debug, Clone, Copy, Default, Serialize, JsonSchema)]
+pub struct RouteOptions {
+    /// Safety preference (0.0 = fast, 5.0 = safe)
+    pub alpha: f64,
+    /// After dark: steer clear of tunnels & underpasses, and skip ways closed at night
+    pub night: bool,
+    /// Direction the user is currently moving in; departures against it are penalized
+    pub heading_degrees: Option<f64>,
+    /// Prefer routes that cross fewer roads, even if they are longer
+    pub minimize_crossings: bool,
+    /// Preference for shaded edges (0.0 = ignore shade); exposed edges cost up to `1 + prefer_shade` times more
+    pub prefer_shade: f64,
+    /// Treat edges in flood zones as impassable
+    pub avoid_flood: bool,
+    /// Treat unpaved edges as impassable (strollers, wheels); unknown surfaces stay usable
+    pub avoid_unpaved: bool,
+    /// Skip edges tagged rougher than this; untagged edges stay usable
+    pub min_smoothness: Option<Smoothness>,
+    pub profile: Profile,
+    /// Weight for open surroundings (0.0 = ignore); edges closed in by buildings count as riskier
+    pub openness: f64,
+    /// Weight for busy pedestrian ways (0.0 = ignore); footways near shops and transit stops count as safer
+    pub prefer_busy: f64,
+    /// Extra cost factor for service roads and private ways (0.0 = none); they cost `1 + service_penalty` times more
+    pub service_penalty: f64,
+    /// Weighted A*: the heuristic is multiplied by this (values below 1 count as 1, exact).
+    /// Higher weights expand fewer nodes, but the route may cost up to this factor more than
+    /// the cheapest one
+    pub heuristic_weight: f64,
+    /// Only search edges near the straight line between two points (A* only)
+    #[serde(skip)]
+    pub corridor: Option<Corridor>,
+}
+
+/// A band around the straight line between two `[lat, lon]` points; edges whose midpoint lies
+/// farther than `width_meters` from the line are left out of the search
+#[derive(Debug, Clone, Copy)]
+pub struct Corridor {
+    pub from: [f64; 2],
+    pub to: [f64; 2],
+    pub width_meters: f64,
+}
+
+impl Corridor {
+    pub fn contains(&self, point: [f64; 2]) -> bool {
+        point_segment_distance_meters(point, self.from, self.to) <= self.width_meters
+    }
+}
+
+/// A `type=restriction` relation banning walkers from turning from one way onto another at
+/// a node. Only the simple, pedestrian-specific kind is read: `restriction:foot=no_*` with a
+/// single `from` way, `via` node and `to` way. Plain `restriction` tags bind vehicles, and
+/// `only_*` restrictions are skipped because one mis-tagged relation would ban every other
+/// way out of the junction.
+struct TurnRestriction {
+    from_way: i64,
+    via_node: i64,
+    to_way: i64,
+}
+
+impl TurnRestriction {
+    fn parse(relation: &osmpbf::Relation) -> Option<Self> {
+        let mut restriction_type = false;
+        let mut bans = false;
+        for (key, value) in relation.tags() {
+            match key {
+                "type" => restriction_type = value == "restriction",
+                "restriction:foot" => bans = value.starts_with("no_"),
+                _ => {}
+            }
+        }
+        if !(restriction_type && bans) {
+            return None;
+        }
+
+        let (mut from, mut via, mut to) = (Vec::new(), Vec::new(), Vec::new());
+        for member in relation.members() {
+            match (member.role().ok()?, member.member_type) {
+                ("from", RelMemberType::Way) => from.push(member.member_id),
+                ("via", RelMemberType::Node) => via.push(member.member_id),
+                ("to", RelMemberType::Way) => to.push(member.member_id),
+                // Via ways and other complex forms
+                ("from" | "via" | "to", _) => return None,
+                _ => {}
+            }
+        }
+        match (from.as_slice(), via.as_slice(), to.as_slice()) {
+            (&[from_way], &[via_node], &[to_way]) => Some(Self { from_way, via_node, to_way }),
+            _ => None,
+        }
+    }
+}
+
+/// A node or area tagged `amenity=*` (police, hospital, pharmacy, ...); areas sit at the
+/// centre of their outline
+#[derive(Debug, Clone)]
+pub struct Amenity {
+    pub lat: f64,
+    pub lon: f64,
+    pub name: Option<String>,
+}
+
+impl RouteOptions {
+    /// The options as `edge_cost` applies them: negative (or NaN) weights count as 0
+    pub fn clamped(&self) -> Self {
+        Self {
+            alpha: self.alpha.max(0.0),
+            prefer_shade: self.prefer_shade.max(0.0),
+            openness: self.openness.max(0.0),
+            prefer_busy: self.prefer_busy.max(0.0),
+            service_penalty: self.service_penalty.max(0.0),
+            heuristic_weight: self.heuristic_weight.max(1.0),
+            ..*self
+        }
+    }
+
+    /// COST FUNCTION: Distance * (1 + alpha * SafetyScore)
+    /// If alpha is high, dangerous edges become very "expensive".
+    /// Never returns less than the edge's length, which the A* heuristic relies on.
+    pub fn edge_cost(&self, edge: &WalkEdge) -> f64 {
+        // Negative (or NaN) alpha would reward risk and make costs undercut distance
+        let alpha = self.alpha.max(0.0);
+        let enclosure_risk = self.openness.max(0.0) * ENCLOSURE_RISK * edge.enclosure as f64;
+        let busy_relief = self.prefer_busy.max(0.0) * BUSY_RISK_RELIEF * edge.busy as f64;
+        // Relief stops at zero risk, so the cost never drops below distance
+        let risk = (edge.safety_score as f64 + enclosure_risk - busy_relief).max(0.0);
+        let mut cost = edge.distance_meters * (1.0 + alpha * risk);
+        if self.night && edge.is_tunnel {
+            cost *= NIGHT_TUNNEL_PENALTY;
+        }
+        if self.minimize_crossings && edge.crossing.is_some() {
+            cost += CROSSING_PENALTY_METERS;
+        }
+        if edge.private || edge.highway == HighwayKind::Service {
+            cost *= 1.0 + self.service_penalty.max(0.0);
+        }
+        // Shade is favoured by charging for exposure, so the cost never drops below distance
+        cost *= 1.0 + self.prefer_shade.max(0.0) * (1.0 - edge.shade as f64);
+        if let Some(incline) = edge.incline_percent {
+            cost *= self.profile.incline_factor(incline);
+        }
+        // A fixed charge in meters, which the factors above must not scale
+        cost + edge.boundary_penalty as f64
+    }
+
+    /// Whether everything `edge_cost` reads besides `alpha` and `service_penalty` is at its
+    /// default, so the costs equal those of `RouteOptions { alpha, service_penalty, ..Default::default() }`.
+    /// Keep in step with `edge_cost`.
+    fn has_plain_costs(&self) -> bool {
+        let o = self.clamped();
+        !o.night
+            && !o.minimize_crossings
+            && o.prefer_shade == 0.0
+            && o.openness == 0.0
+            && o.prefer_busy == 0.0
+            && o.profile == Profile::default()
+    }
+
+    /// Whether the route may use this edge at all
+    pub fn allows(&self, edge: &WalkEdge) -> bool {
+        let flooded = self.avoid_flood && edge.flooded;
+        let unpaved = self.avoid_unpaved && edge.surface == Surface::Unpaved;
+        // `impassable` means exactly that, whatever the request asks for
+        let too_rough = match (edge.smoothness, self.min_smoothness) {
+            (Some(Smoothness::Impassable), _) => true,
+            (Some(smoothness), Some(min)) => smoothness < min,
+            _ => false,
+        };
+        let too_steep = match (edge.incline_percent, self.profile.max_incline_percent()) {
+            (Some(incline), Some(max)) => incline > max,
+            _ => false,
+        };
+        let closed = self.night && edge.foot_closed.is_some_and(|hours| hours.overlaps(&NIGHT_HOURS));
+        !(flooded || unpaved || too_rough || too_steep || closed)
+    }
+}
+
+/// Edge costs under the plain cost function (only `alpha` and `service_penalty` set), per
+/// pair of them, built on first use.
+/// A deployment serving a handful of alphas then costs edges by lookup instead of evaluating
+/// `edge_cost` on every expansion. Copies of a graph share the costs built so far; whatever
+/// changes edge scores calls `invalidate`.
+#[derive(Default)]
+struct AlphaCosts {
+    /// Most alphas kept (8 bytes per edge each); 0 disables the cache
+    limit: usize,
+    /// Cost of every edge per key, indexed by `EdgeIndex::index()`
+    costs: RwLock<HashMap<PlainCostKey, Arc<Vec<f64>>>>,
+}
+
+/// Alpha in hundredths and the bits of `service_penalty`
+type PlainCostKey = (i64, u64);
+
+impl AlphaCosts {
+    fn new(limit: usize) -> Self {
+        Self { limit, costs: RwLock::default() }
+    }
+
+    /// Drops every cost vector built so far, keeping the limit
+    fn invalidate(&mut self) {
+        self.costs.get_mut().unwrap().clear();
+    }
+}
+
+impl Clone for AlphaCosts {
+    fn clone(&self) -> Self {
+        Self { limit: self.limit, costs: RwLock::new(self.costs.read().unwrap().clone()) }
+    }
+}
+
+/// Deduplicated storage for street names; edges refer to names by id
+#[derive(Debug, Default, Clone)]
+pub struct NameArena {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl NameArena {
+    /// Returns the id for `name`, storing it on first sight
+    pub fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.names.get(id as usize).map(String::as_str)
+    }
+
+    /// All interned names with their ids
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.names.iter().enumerate().map(|(id, n)| (id as u32, n.as_str()))
+    }
+
+    /// Approximate heap usage: the strings are stored twice (vector + map key)
+    fn memory_estimate_bytes(&self) -> usize {
+        let text: usize = self.names.iter().map(|n| n.capacity() * 2).sum();
+        self.names.capacity() * std::mem::size_of::<String>()
+            + self.ids.capacity() * (std::mem::size_of::<(String, u32)>() + 1)
+            + text
+    }
 }
 
+/// A street matching a name search, with a point on it to route to
+#[derive(Debug, Clone)]
+pub struct NameMatch<'a> {
+    pub name: &'a str,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Cheapest paths between one root node and every node connected to it: from the root
+/// (`NavigationGraph::route_tree`) or to it (`NavigationGraph::route_tree_to`)
+pub struct RouteTree {
+    root: NodeIndex,
+    /// `Outgoing` if paths start at the root, `Incoming` if they end there
+    direction: Direction,
+    /// Cost of the cheapest path for each node, indexed by `NodeIndex::index()` (infinite if unreachable)
+    costs: Vec<f64>,
+    /// Edge of that path next to the node (the last one from the root, the first one to it)
+    via: Vec<Option<EdgeIndex>>,
+    /// With banned turns, the edge of the path before each edge (towards the root), indexed
+    /// by `EdgeIndex::index()`: a path may then reach a node by a turn its continuation can't
+    /// take, so it can't be followed back node by node
+    parents: Option<Vec<Option<EdgeIndex>>>,
+}
+
+impl RouteTree {
+    /// Cost of the cheapest path between the root and `target`, `None` if there is none
+    pub fn cost_to(&self, target: NodeIndex) -> Option<f64> {
+        self.costs.get(target.index()).copied().filter(|c| c.is_finite())
+    }
+
+    /// Edges of the cheapest path between the root and `target`, in walking order
+    pub fn edges_to(&self, graph: &Graph<GeoNode, WalkEdge>, target: NodeIndex) -> Option<Vec<EdgeIndex>> {
+        self.cost_to(target)?;
+        let mut edges = Vec::new();
+        let mut next = if target == self.root { None } else { Some(self.via[target.index()]?) };
+        while let Some(edge) = next {
+            edges.push(edge);
+            next = match &self.parents {
+                Some(parents) => parents[edge.index()],
+                None => {
+                    let (source, target) = graph.edge_endpoints(edge)?;
+                    let current = if self.direction == Direction::Outgoing { source } else { target };
+                    if current == self.root { None } else { Some(self.via[current.index()]?) }
+                }
+            };
+        }
+        if self.direction == Direction::Outgoing {
+            edges.reverse();
+        }
+        Some(edges)
+    }
+}
+
+/// Min-heap entry for the `route_tree` Dijkstra searches (nodes) and the A* searches (nodes
+/// with their cost so far, or edges when turns are restricted)
+#[derive(PartialEq)]
+struct Queued<T>(f64, T);
+
+impl<T: PartialEq> Eq for Queued<T> {}
+
+impl<T: PartialEq> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialEq> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Reversed, so `BinaryHeap` pops the cheapest entry first
+        other.0.total_cmp(&self.0)
+    }
+}
+
+/// Small seedable PRNG (SplitMix64) for `alternative_routes`; reproducible across platforms
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    /// Uniform in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Settings applied while loading a PBF
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Abort with an error once the file turns out to hold more nodes than this, to fail
+    /// fast instead of exhausting memory
+    pub max_nodes: Option<usize>,
+    /// Which ways become edges
+    pub walkable: WalkableHighways,
+    /// Fold segments shorter than this (GPS noise, sub-meter zigzags) into the next one by
+    /// skipping their shared node. Junctions, barriers, crossings and way ends always stay.
+    pub min_edge_length_meters: Option<f64>,
+    /// Read `restriction:foot=no_*` relations in a third pass and route around the turns they
+    /// ban (see `TurnRestriction`)
+    pub turn_restrictions: bool,
+}
+
+/// The `highway` values walked on, so deployments can follow local tagging conventions
+/// (e.g. farm tracks used as footpaths). `access` restrictions apply on top.
+#[derive(Debug, Clone)]
+pub struct WalkableHighways {
+    /// Walkable as they are
+    pub always: Vec<String>,
+    /// Walkable only with a sidewalk or explicit foot access (`foot=yes|designated|permissive`)
+    pub with_foot_access: Vec<String>,
+}
+
+impl Default for WalkableHighways {
+    fn default() -> Self {
+        let list = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        Self {
+            always: list(&["footway", "path", "steps", "pedestrian", "living_street", "residential", "tertiary", "service", "unclassified"]),
+            with_foot_access: list(&["motorway", "trunk", "primary", "secondary"]),
+        }
+    }
+}
+
+impl WalkableHighways {
+    fn admits(&self, highway: &str, foot_allowed: bool, has_sidewalk: bool) -> bool {
+        let listed = |list: &[String]| list.iter().any(|h| h == highway);
+        listed(&self.always) || ((foot_allowed || has_sidewalk) && listed(&self.with_foot_access))
+    }
+}
+
+/// Which pass over the input a `LoadProgress` refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPhase {
+    Nodes,
+    Ways,
+    Relations,
+}
+
+/// Snapshot handed to the `from_pbf_with_progress` callback
+#[derive(Debug, Clone, Copy)]
+pub struct LoadProgress {
+    pub phase: LoadPhase,
+    /// Elements (of any type) read so far in this phase
+    pub processed: u64,
+    /// Share of the input file consumed by this phase so far, 0-100
+    pub percent: f64,
+}
+
+#[derive(Clone)]
 pub struct NavigationGraph {
     pub graph: Graph<GeoNode, WalkEdge>,
+    /// Connected-component id of every node, indexed by `NodeIndex::index()`
+    component_of: Vec<usize>,
+    /// Node count of every component, indexed by component id
+    component_sizes: Vec<usize>,
+    /// OSM node id -> graph node (empty for graphs not built from OSM data)
+    osm_id_map: HashMap<i64, NodeIndex>,
+    /// Inverse of `osm_id_map`, indexed by `NodeIndex::index()`
+    osm_id_of: Vec<Option<i64>>,
+    /// Street names referenced by `WalkEdge::name`
+    names: NameArena,
+    /// Optional ALT tables tightening the A* heuristic (see `build_landmarks`)
+    landmarks: Option<Landmarks>,
+    /// `addr:*` nodes as "housenumber street" (see `addresses_near`)
+    addresses: PointIndex<String>,
+    /// Amenities by their `amenity` value (see `amenities_near`)
+    amenities: HashMap<String, PointIndex<Amenity>>,
+    /// Meters above sea level from `ele` tags, indexed by `NodeIndex::index()` (empty if none are tagged)
+    elevations: Vec<Option<f32>>,
+    /// Every graph node by position, for radius-bounded snapping
+    node_index: PointIndex<NodeIndex>,
+    /// Every edge by its segment, for `find_nearest_edge`
+    edge_index: SegmentIndex<EdgeIndex>,
+    /// (edge arriving at a node, edge leaving it) pairs walkers may not chain; when non-empty,
+    /// point-to-point routing runs over edges instead of nodes
+    banned_turns: HashSet<(EdgeIndex, EdgeIndex)>,
+    /// Precomputed edge costs for frequently requested alphas (see `alpha_costs`)
+    alpha_costs: AlphaCosts,
 }
 
 impl NavigationGraph {
+    /// Wraps a finished graph, labelling its connected components
+    fn new(graph: Graph<GeoNode, WalkEdge>, osm_id_map: HashMap<i64, NodeIndex>, names: NameArena) -> Self {
+        let mut sets = UnionFind::new(graph.node_count());
+        for edge in graph.edge_references() {
+            sets.union(edge.source().index(), edge.target().index());
+        }
+
+        // Renumber union-find representatives into dense ids 0..n
+        let mut ids = HashMap::new();
+        let mut component_sizes = Vec::new();
+        let component_of = (0..graph.node_count())
+            .map(|n| {
+                let id = *ids.entry(sets.find_mut(n)).or_insert_with(|| {
+                    component_sizes.push(0);
+                    component_sizes.len() - 1
+                });
+                component_sizes[id] += 1;
+                id
+            })
+            .collect();
+
+        let node_index = PointIndex::new(graph.node_indices().map(|n| ([graph[n].lat, graph[n].lon], n)).collect());
+        let edge_index = SegmentIndex::new(graph.edge_references()
+            .map(|e| {
+                let (a, b) = (graph[e.source()], graph[e.target()]);
+                ([a.lat, a.lon], [b.lat, b.lon], e.id())
+            })
+            .collect());
+        let mut osm_id_of = vec![None; graph.node_count()];
+        for (&id, &node) in &osm_id_map {
+            osm_id_of[node.index()] = Some(id);
+        }
+        Self {
+            graph,
+            component_of,
+            component_sizes,
+            osm_id_map,
+            osm_id_of,
+            names,
+            landmarks: None,
+            addresses: PointIndex::new(Vec::new()),
+            amenities: HashMap::new(),
+            elevations: Vec::new(),
+            node_index,
+            edge_index,
+            banned_turns: HashSet::new(),
+            alpha_costs: AlphaCosts::default(),
+        }
+    }
+
     pub fn from_pbf(path: &str, safety_map: &SafetyMap) -> anyhow::Result<Self> {
+        Self::from_pbf_with_options(path, safety_map, &LoadOptions::default(), &mut |_| {})
+    }
+
+    /// Like `from_pbf`, but calls `progress` periodically during each pass over the file
+    /// (and once at the end of each), e.g. to drive a progress bar
+    pub fn from_pbf_with_progress(
+        path: &str,
+        safety_map: &SafetyMap,
+        progress: &mut dyn FnMut(LoadProgress),
+    ) -> anyhow::Result<Self> {
+        Self::from_pbf_with_options(path, safety_map, &LoadOptions::default(), progress)
+    }
+
+    /// `from_pbf_with_progress` with limits on what may be loaded (see `LoadOptions`)
+    pub fn from_pbf_with_options(
+        path: &str,
+        safety_map: &SafetyMap,
+        options: &LoadOptions,
+        progress: &mut dyn FnMut(LoadProgress),
+    ) -> anyhow::Result<Self> {
         println!("Parsing OSM PBF: {}", path);
+        let total_bytes = std::fs::metadata(path)?.len();
         
         let mut graph = Graph::new();
         let mut osm_id_map = HashMap::new();
         let mut temp_nodes = HashMap::new(); 
+        let mut barrier_nodes = HashSet::new();
+        let mut crossing_nodes = HashMap::new();
+        let mut names = NameArena::default();
+        let mut trees = Vec::new();
+        let mut woods = Vec::new();
+        let mut buildings = Vec::new();
+        let mut pois = Vec::new();
+        let mut addresses = Vec::new();
+        let mut amenities: HashMap<String, Vec<([f64; 2], Amenity)>> = HashMap::new();
+        let mut elevations = HashMap::new();
+        // How many highway/ferry ways use each node; only needed to spot junctions when merging short segments
+        let mut way_uses: HashMap<i64, u32> = HashMap::new();
 
         // PASS 1: Nodes
-        let reader = ElementReader::from_path(path)?;
+        let pass = Arc::new(PassState::default());
+        let reader = open_reader(path, pass.clone())?;
+        let mut reporter = ProgressReporter::new(LoadPhase::Nodes, pass.clone(), total_bytes, progress);
         let mut node_count = 0;
 
-        reader.for_each(|element| {
-            match element {
-                Element::Node(node) => {
-                    temp_nodes.insert(node.id(), (node.lat(), node.lon()));
-                    node_count += 1;
-                }
-                Element::DenseNode(node) => {
-                    temp_nodes.insert(node.id(), (node.lat(), node.lon()));
-                    node_count += 1;
+        let result = reader.for_each(|element| {
+            reporter.element();
+            let (id, lat, lon, tags): (i64, f64, f64, HashMap<&str, &str>) = match element {
+                Element::Node(node) => (node.id(), node.lat(), node.lon(), node.tags().collect()),
+                Element::DenseNode(node) => (node.id(), node.lat(), node.lon(), node.tags().collect()),
+                Element::Way(way) if options.min_edge_length_meters.is_some() => {
+                    if way.tags().any(|(key, _)| key == "highway" || key == "route") {
+                        for id in way.refs() {
+                            *way_uses.entry(id).or_insert(0) += 1;
+                        }
+                    }
+                    return;
                 }
-                _ => {} 
+                _ => return,
+            };
+
+            node_count += 1;
+            if options.max_nodes.is_some_and(|max| node_count > max) {
+                // Stop buffering and make the reader fail, ending the pass early
+                pass.abort.store(true, Ordering::Relaxed);
+                return;
+            }
+            temp_nodes.insert(id, (lat, lon));
+            if tags.is_empty() {
+                return;
             }
-        })?;
 
-        println!("Loaded {} nodes. Building Edges...", node_count);        
+            if is_blocking_barrier(&tags) {
+                barrier_nodes.insert(id);
+            }
+            if let Some(crossing) = parse_crossing(&tags) {
+                crossing_nodes.insert(id, crossing);
+            }
+            if tags.get("natural") == Some(&"tree") {
+                trees.push(([lat, lon], ()));
+            }
+            if draws_foot_traffic(&tags) {
+                pois.push(([lat, lon], ()));
+            }
+            if let Some(address) = format_address(&tags) {
+                addresses.push(([lat, lon], address));
+            }
+            if let Some(kind) = tags.get("amenity") {
+                let name = tags.get("name").map(|n| n.to_string());
+                amenities.entry(kind.to_string()).or_default().push(([lat, lon], Amenity { lat, lon, name }));
+            }
+            if let Some(ele) = tags.get("ele").and_then(|e| parse_ele(e)) {
+                elevations.insert(id, ele);
+            }
+        });
+        if let Some(max) = options.max_nodes.filter(|_| pass.abort.load(Ordering::Relaxed)) {
+            anyhow::bail!(
+                "{path} has more than {max} nodes, the configured limit. Load a smaller extract \
+                 (e.g. `osmium extract --bbox ...`) or raise the limit."
+            );
+        }
+        result?;
+        reporter.finish();
+
+        println!("Loaded {} nodes ({} blocking barriers). Building Edges...", node_count, barrier_nodes.len());        
         
         // PASS 2: Ways
-        let reader_pass2 = ElementReader::from_path(path)?;
+        let pass = Arc::new(PassState::default());
+        let reader_pass2 = open_reader(path, pass.clone())?;
+        let mut reporter = ProgressReporter::new(LoadPhase::Ways, pass, total_bytes, progress);
+        let mut degenerate_ways = 0;
+        let mut merged_nodes = 0;
+        // Edges merged from overlapping ways -> the ways other than their own `way_id`
+        let mut shared_ways: HashMap<EdgeIndex, Vec<i64>> = HashMap::new();
+        let mut risk_cache = RiskCache::default();
         reader_pass2.for_each(|element| {
+            reporter.element();
             if let Element::Way(way) = element {
                 
                 let mut tags = HashMap::new();
@@ -59,28 +958,110 @@ impl NavigationGraph {
                     tags.insert(key, value);
                 }
 
+                // Woodland casts shade on whatever runs through it; remember its outline
+                if matches!((tags.get("landuse"), tags.get("natural")), (Some(&"forest"), _) | (_, Some(&"wood"))) {
+                    let outline: Vec<Coord> = way.refs()
+                        .filter_map(|id| temp_nodes.get(&id).map(|&(lat, lon)| Coord { x: lon, y: lat }))
+                        .collect();
+                    if outline.len() >= 4 && outline.first() == outline.last() {
+                        woods.push(Polygon::new(LineString::from(outline), vec![]));
+                    }
+                }
+
+                // Buildings (and shops mapped as areas) only matter as a count around each edge,
+                // so their centre is enough; the same goes for amenity areas
+                let is_building = tags.get("building").is_some_and(|b| *b != "no");
+                let is_poi = draws_foot_traffic(&tags);
+                let amenity = tags.get("amenity");
+                if is_building || is_poi || amenity.is_some() {
+                    let corners: Vec<(f64, f64)> = way.refs().filter_map(|id| temp_nodes.get(&id).copied()).collect();
+                    if !corners.is_empty() {
+                        let n = corners.len() as f64;
+                        let (lat, lon) = corners.iter().fold((0.0, 0.0), |(lat, lon), c| (lat + c.0, lon + c.1));
+                        if is_building {
+                            buildings.push(([lat / n, lon / n], ()));
+                        }
+                        if is_poi {
+                            pois.push(([lat / n, lon / n], ()));
+                        }
+                        if let Some(kind) = amenity {
+                            let (lat, lon) = (lat / n, lon / n);
+                            let name = tags.get("name").map(|n| n.to_string());
+                            amenities.entry(kind.to_string()).or_default().push(([lat, lon], Amenity { lat, lon, name }));
+                        }
+                    }
+                }
+
                 let highway = tags.get("highway").copied().unwrap_or("");
                 let foot = tags.get("foot").copied().unwrap_or("");
                 let sidewalk = tags.get("sidewalk").copied().unwrap_or("");
+                let access = tags.get("access").copied().unwrap_or("");
 
-                let is_walkable_type = matches!(highway, 
-                    "footway" | "path" | "steps" | "pedestrian" | "living_street" | 
-                    "residential" | "tertiary" | "service" | "unclassified"
-                );
-
-                let is_motor_road = matches!(highway, "motorway" | "trunk" | "primary" | "secondary");
                 let foot_allowed = matches!(foot, "yes" | "designated" | "permissive");
                 let has_sidewalk = matches!(sidewalk, "both" | "left" | "right" | "yes" | "separate");
+                // Foot ferries link otherwise separate shores (timetables are not modeled)
+                let is_foot_ferry = tags.get("route") == Some(&"ferry") && foot_allowed;
+                // Closed ways are off-limits unless pedestrians are explicitly let through;
+                // private ones stay in the graph at a cost (see `RouteOptions::service_penalty`)
+                let access_denied = access == "no" && !foot_allowed;
+                let private = access == "private" && !foot_allowed;
+
+                if !access_denied && (is_foot_ferry || options.walkable.admits(highway, foot_allowed, has_sidewalk)) {
+                    // Clipped or malformed ways may reference a single node: nothing to walk
+                    let mut refs: Vec<i64> = way.refs().collect();
+                    if refs.len() < 2 {
+                        degenerate_ways += 1;
+                        return;
+                    }
+                    if let Some(min_length) = options.min_edge_length_meters {
+                        // Nodes other ways or features depend on must stay where they are
+                        let pinned = |id: i64| {
+                            way_uses.get(&id).is_some_and(|&uses| uses > 1)
+                                || barrier_nodes.contains(&id)
+                                || crossing_nodes.contains_key(&id)
+                        };
+                        let before = refs.len();
+                        refs = merge_short_segments(&refs, min_length, &temp_nodes, pinned);
+                        merged_nodes += before - refs.len();
+                    }
+
+                    let risk = risk_cache.calculate_edge_risk(safety_map, &tags);
+                    let risk_score = safety_map.clamp_risk(risk.total());
+
+                    // Tunnels, building passages and underpasses (negative layer)
+                    let tunnel = tags.get("tunnel").copied().unwrap_or("no");
+                    let layer: i32 = tags.get("layer").and_then(|l| l.parse().ok()).unwrap_or(0);
+                    let is_tunnel = tunnel != "no" || layer < 0;
 
-                if is_walkable_type || (is_motor_road && (foot_allowed || has_sidewalk)) {
-                    let risk_score = safety_map.calculate_edge_risk(&tags);
+                    // Footpaths meeting a crossing node are the ones that actually cross the road;
+                    // the crossing's risk is charged to the edge stepping onto it
+                    let is_foot_way = tags.get("footway") == Some(&"crossing")
+                        || matches!(highway, "footway" | "path" | "pedestrian" | "steps");
+                    let crossing_at = |id: i64| if is_foot_way { crossing_nodes.get(&id).copied() } else { None };
+
+                    let name = tags.get("name").map(|n| names.intern(n));
+                    let surface = tags.get("surface").map_or(Surface::Unknown, |s| Surface::from_tag(s));
+                    let smoothness = tags.get("smoothness").and_then(|s| Smoothness::from_tag(s));
+                    let incline_percent = tags.get("incline").and_then(|i| parse_incline(i));
+                    let foot_closed = tags.get("foot:conditional").and_then(|c| parse_foot_closed(c));
+                    let highway_kind = if is_foot_ferry { HighwayKind::Ferry } else { HighwayKind::from_tag(highway) };
+
+                    // Pedestrian one-ways (some stairs, turnstile exits); plain `oneway` is for vehicles
+                    let (forward, backward) = match tags.get("oneway:foot").copied() {
+                        Some("yes" | "true" | "1") => (true, false),
+                        Some("-1") => (false, true),
+                        _ => (true, true),
+                    };
 
-                    let refs: Vec<i64> = way.refs().collect();
-                    
                     for window in refs.windows(2) {
                         let id_a = window[0];
                         let id_b = window[1];
 
+                        // A locked gate or bollard cuts the way: no edge may touch it
+                        if barrier_nodes.contains(&id_a) || barrier_nodes.contains(&id_b) {
+                            continue;
+                        }
+
                         if let (Some(&(lat_a, lon_a)), Some(&(lat_b, lon_b))) = (temp_nodes.get(&id_a), temp_nodes.get(&id_b)) {
                             
                             let idx_a = *osm_id_map.entry(id_a).or_insert_with(|| {
@@ -90,37 +1071,1571 @@ impl NavigationGraph {
                                 graph.add_node(GeoNode { lat: lat_b, lon: lon_b })
                             });
 
-                            let p1 = Point::new(lon_a, lat_a);
-                            let p2 = Point::new(lon_b, lat_b);
-                            let dist = p1.haversine_distance(&p2);
+                            let dist = haversine_meters([lat_a, lon_a], [lat_b, lon_b]);
                             
-                            let edge_data = WalkEdge {
+                            let edge_to = |crossing: Option<Crossing>| WalkEdge {
                                 distance_meters: dist,
-                                safety_score: risk_score,
+                                safety_score: safety_map.crossing_risk(risk_score, crossing),
+                                base_safety: safety_map.crossing_risk(risk_score, crossing),
+                                is_tunnel,
+                                crossing,
+                                name,
+                                risk,
+                                shade: 0.0,
+                                enclosure: 0.0,
+                                busy: 0.0,
+                                flooded: false,
+                                boundary_penalty: 0.0,
+                                surface,
+                                smoothness,
+                                incline_percent,
+                                highway: highway_kind,
+                                private,
+                                way_id: Some(way.id()),
+                                foot_closed,
                             };
 
-                            graph.add_edge(idx_a, idx_b, edge_data);
-                            graph.add_edge(idx_b, idx_a, edge_data);
+                            if forward {
+                                add_or_merge_edge(&mut graph, &mut shared_ways, idx_a, idx_b, edge_to(crossing_at(id_b)));
+                            }
+                            if backward {
+                                add_or_merge_edge(&mut graph, &mut shared_ways, idx_b, idx_a, edge_to(crossing_at(id_a)));
+                            }
                         }
                     }
                 }
             }
         })?;
+        reporter.finish();
+        if degenerate_ways > 0 {
+            tracing::debug!(degenerate_ways, "skipped walkable ways with fewer than two nodes");
+        }
+        tracing::debug!(combinations = risk_cache.len(), "distinct risk tag combinations scored");
+        if merged_nodes > 0 {
+            tracing::debug!(merged_nodes, "merged segments shorter than the minimum edge length");
+        }
+
+        // PASS 3: Relations, only for turn restrictions
+        let mut restrictions = Vec::new();
+        if options.turn_restrictions {
+            let pass = Arc::new(PassState::default());
+            let reader_pass3 = open_reader(path, pass.clone())?;
+            let mut reporter = ProgressReporter::new(LoadPhase::Relations, pass, total_bytes, progress);
+            reader_pass3.for_each(|element| {
+                reporter.element();
+                if let Element::Relation(relation) = element {
+                    restrictions.extend(TurnRestriction::parse(&relation));
+                }
+            })?;
+            reporter.finish();
+        }
+
+        // Shade, enclosure and foot traffic are judged at each edge's midpoint, against the
+        // trees, woods, buildings and shops/stops seen while loading
+        let trees = PointIndex::new(trees);
+        let woods: Vec<_> = woods.into_iter().filter_map(|w| Some((w.bounding_rect()?, w))).collect();
+        let buildings = PointIndex::new(buildings);
+        let pois = PointIndex::new(pois);
+        for edge in graph.edge_indices() {
+            let (a, b) = graph.edge_endpoints(edge).expect("edge index from this graph");
+            let (na, nb) = (graph[a], graph[b]);
+            let mid = [(na.lat + nb.lat) / 2.0, (na.lon + nb.lon) / 2.0];
+            graph[edge].shade = shade_at(mid, &trees, &woods);
+            let nearby_buildings = buildings.within(mid, ENCLOSURE_RADIUS_METERS).count();
+            graph[edge].enclosure = (nearby_buildings as f32 / BUILDINGS_FOR_FULL_ENCLOSURE).min(1.0);
+            if matches!(graph[edge].highway, HighwayKind::Footway | HighwayKind::Pedestrian) {
+                let nearby_pois = pois.within(mid, BUSY_RADIUS_METERS).count();
+                graph[edge].busy = (nearby_pois as f32 / POIS_FOR_FULL_BUSY).min(1.0);
+            }
+        }
 
-        println!("Graph built: {} nodes, {} edges", graph.node_count(), graph.edge_count());
-        Ok(Self { graph }) 
+        println!(
+            "Graph built: {} nodes, {} edges ({} trees, {} woods, {} buildings, {} shops/stops)",
+            graph.node_count(), graph.edge_count(), trees.len(), woods.len(), buildings.len(), pois.len()
+        );
+        let mut nav = Self::new(graph, osm_id_map, names);
+        nav.addresses = PointIndex::new(addresses);
+        println!("Indexed {} addresses", nav.addresses.len());
+        nav.amenities = amenities.into_iter().map(|(kind, points)| (kind, PointIndex::new(points))).collect();
+        println!("Indexed {} amenities", nav.amenities.values().map(PointIndex::len).sum::<usize>());
+        if !elevations.is_empty() {
+            nav.elevations = nav.osm_id_of.iter().map(|id| id.and_then(|id| elevations.get(&id).copied())).collect();
+        }
+        if options.turn_restrictions {
+            for restriction in &restrictions {
+                nav.ban_turn(restriction, &shared_ways);
+            }
+            println!("Applied {} turn restrictions ({} banned turns)", restrictions.len(), nav.banned_turns.len());
+        }
+        Ok(nav)
+    }
+
+    /// Builds a graph directly from nodes and directed `(from, to, edge)` triples, where
+    /// `from`/`to` index into `nodes`. Add both directions for a two-way street.
+    /// Handy for deterministic fixtures and benchmarks that shouldn't need a PBF.
+    ///
+    /// # Panics
+    /// If an edge refers to a node index outside `nodes`.
+    pub fn from_edges(nodes: &[GeoNode], edges: &[(usize, usize, WalkEdge)]) -> Self {
+        let mut graph = Graph::with_capacity(nodes.len(), edges.len());
+        let indices: Vec<NodeIndex> = nodes.iter().map(|&n| graph.add_node(n)).collect();
+
+        for &(a, b, edge) in edges {
+            graph.add_edge(indices[a], indices[b], edge);
+        }
+
+        Self::new(graph, HashMap::new(), NameArena::default())
     }
 
     pub fn find_nearest_node(&self, lat: f64, lon: f64) -> Option<NodeIndex> {
-        let target = Point::new(lon, lat);
+        let target = [lat, lon];
         
         self.graph.node_indices()
             .min_by(|&a, &b| {
                 let na = self.graph[a];
                 let nb = self.graph[b];
-                let da = Point::new(na.lon, na.lat).haversine_distance(&target);
-                let db = Point::new(nb.lon, nb.lat).haversine_distance(&target);
+                let da = haversine_meters([na.lat, na.lon], target);
+                let db = haversine_meters([nb.lat, nb.lon], target);
                 da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
             })
     }
+
+    /// Nearest node within `radius_meters`, or `None` if there is none. Unlike
+    /// `find_nearest_node` this only searches the neighbourhood, so far-off points are cheap.
+    pub fn find_nearest_node_within(&self, lat: f64, lon: f64, radius_meters: f64) -> Option<NodeIndex> {
+        self.node_index.within([lat, lon], radius_meters)
+            .min_by(|(_, da), (_, db)| da.total_cmp(db))
+            .map(|(&n, _)| n)
+    }
+
+    /// Like `find_nearest_node`, but passes over dead-end stubs (nodes with a single
+    /// neighbour) in favour of the nearest node with two or more within `radius_meters`,
+    /// since a route starting at the tip of a stub first has to walk back out of it.
+    /// Falls back to the nearest node of any kind if there is none.
+    pub fn find_nearest_through_node(&self, lat: f64, lon: f64, radius_meters: f64) -> Option<NodeIndex> {
+        let is_stub = |n: NodeIndex| {
+            let mut neighbours = self.graph.neighbors_undirected(n);
+            neighbours.next().is_none_or(|first| neighbours.all(|other| other == first))
+        };
+
+        self.node_index.within([lat, lon], radius_meters)
+            .filter(|&(&n, _)| !is_stub(n))
+            .min_by(|(_, da), (_, db)| da.total_cmp(db))
+            .or_else(|| self.node_index.nearest([lat, lon], 1).into_iter().next())
+            .map(|(&n, _)| n)
+    }
+
+    /// Weighted A* from `start` to `end`. Returns the total cost and the node path.
+    pub fn route(&self, start: NodeIndex, end: NodeIndex, options: &RouteOptions) -> Option<(f64, Vec<NodeIndex>)> {
+        match self.alpha_costs(options) {
+            Some(costs) => self.route_by_edge(start, end, options, &|id, _| costs[id.index()]),
+            None => self.route_with_cost(start, end, options, &|edge| options.edge_cost(edge)),
+        }
+    }
+
+    /// Keeps precomputed edge costs for up to `limit` alphas (see `alpha_costs`); 0, the
+    /// default, disables them. Worth it only if profiling shows edge costing matters: the plain
+    /// formula is a few multiplications on an edge the search has loaded anyway.
+    pub fn cache_alpha_costs(&mut self, limit: usize) {
+        self.alpha_costs = AlphaCosts::new(limit);
+    }
+
+    /// Every edge's `options.edge_cost`, precomputed, when only `alpha` and `service_penalty`
+    /// shape the costs and `alpha` lies on the 0.01 grid. Built on first use for as many alphas as `cache_alpha_costs`
+    /// allows; `None` means cost edges one by one.
+    pub fn alpha_costs(&self, options: &RouteOptions) -> Option<Arc<Vec<f64>>> {
+        let (alpha, service_penalty) = (options.alpha.max(0.0), options.service_penalty.max(0.0));
+        let steps = (alpha * ALPHA_CACHE_STEPS).round();
+        if !options.has_plain_costs() || !alpha.is_finite() || steps / ALPHA_CACHE_STEPS != alpha {
+            return None;
+        }
+        let key = (steps as i64, service_penalty.to_bits());
+        {
+            let cache = self.alpha_costs.costs.read().unwrap();
+            if let Some(costs) = cache.get(&key) {
+                return Some(costs.clone());
+            }
+            if cache.len() >= self.alpha_costs.limit {
+                return None;
+            }
+        }
+
+        // Built without holding the lock, so searches with cached alphas carry on meanwhile.
+        // Requests racing for the same new alpha may each build it; the first one stored stays.
+        let plain = RouteOptions { alpha, service_penalty, ..Default::default() };
+        let costs = Arc::new(self.map_edges(|edge| plain.edge_cost(&self.graph[edge])));
+        let mut cache = self.alpha_costs.costs.write().unwrap();
+        if let Some(stored) = cache.get(&key) {
+            return Some(stored.clone());
+        }
+        if cache.len() < self.alpha_costs.limit {
+            cache.insert(key, costs.clone());
+        }
+        Some(costs)
+    }
+
+    /// Like `route`, but with a custom per-edge cost in place of `RouteOptions::edge_cost`.
+    /// The options still filter edges and apply the heading penalty. `edge_cost` must never
+    /// return less than the edge's length, or the result may not be the cheapest path.
+    pub fn route_with_cost(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        options: &RouteOptions,
+        edge_cost: &dyn Fn(&WalkEdge) -> f64,
+    ) -> Option<(f64, Vec<NodeIndex>)> {
+        self.route_by_edge(start, end, options, &|_, edge| edge_cost(edge))
+    }
+
+    /// Up to `count` distinct routes, best first. Each alternative is the best route once
+    /// the edges of the routes before it cost 30-100% more (a random factor per edge, drawn
+    /// from `seed`), so it keeps to the good streets while overlapping earlier routes less.
+    /// The same `seed` always yields the same routes.
+    pub fn alternative_routes(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        options: &RouteOptions,
+        count: usize,
+        seed: u64,
+    ) -> Vec<Vec<NodeIndex>> {
+        let mut random = SplitMix64(seed);
+        let mut penalties: HashMap<EdgeIndex, f64> = HashMap::new();
+        let mut routes: Vec<Vec<EdgeIndex>> = Vec::new();
+
+        // Penalized searches may land on a route already found; give up after a few
+        for _ in 0..count * ALTERNATIVE_ATTEMPTS_PER_ROUTE {
+            if routes.len() >= count {
+                break;
+            }
+            let cost = |id: EdgeIndex, edge: &WalkEdge| options.edge_cost(edge) * penalties.get(&id).copied().unwrap_or(1.0);
+            let Some((_, path)) = self.search(start, end, options, &cost) else { break };
+
+            // The edges actually walked, even where parallel edges join the same two nodes
+            for &id in &path {
+                let factor = 1.3 + 0.7 * random.next_f64();
+                *penalties.entry(id).or_insert(1.0) *= factor;
+            }
+            if !routes.contains(&path) {
+                routes.push(path);
+            }
+        }
+        routes.iter().map(|edges| self.nodes_along(start, edges)).collect()
+    }
+
+    /// Like `route`, but returns the edges walked instead of the nodes passed, e.g. to tell
+    /// apart parallel edges between the same two nodes. Empty if `start == end`.
+    pub fn route_edges(&self, start: NodeIndex, end: NodeIndex, options: &RouteOptions) -> Option<(f64, Vec<EdgeIndex>)> {
+        match self.alpha_costs(options) {
+            Some(costs) => self.search(start, end, options, &|id, _| costs[id.index()]),
+            None => self.search(start, end, options, &|_, edge| options.edge_cost(edge)),
+        }
+    }
+
+    /// `route_edges` with a custom per-edge cost, as in `route_with_cost`
+    pub fn route_edges_with_cost(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        options: &RouteOptions,
+        edge_cost: &dyn Fn(&WalkEdge) -> f64,
+    ) -> Option<(f64, Vec<EdgeIndex>)> {
+        self.search(start, end, options, &|_, edge| edge_cost(edge))
+    }
+
+    /// `start` followed by the node each of `edges` leads to
+    pub fn nodes_along(&self, start: NodeIndex, edges: &[EdgeIndex]) -> Vec<NodeIndex> {
+        std::iter::once(start)
+            .chain(edges.iter().map(|&e| self.graph.edge_endpoints(e).expect("edge index from this graph").1))
+            .collect()
+    }
+
+    /// `search`, returning the nodes passed
+    fn route_by_edge(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        options: &RouteOptions,
+        edge_cost: &dyn Fn(EdgeIndex, &WalkEdge) -> f64,
+    ) -> Option<(f64, Vec<NodeIndex>)> {
+        let (cost, edges) = self.search(start, end, options, edge_cost)?;
+        Some((cost, self.nodes_along(start, &edges)))
+    }
+
+    /// A* core, returning the cost and the edges walked: `edge_cost` sees the edge id as
+    /// well, and must never undercut the length
+    fn search(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        options: &RouteOptions,
+        edge_cost: &dyn Fn(EdgeIndex, &WalkEdge) -> f64,
+    ) -> Option<(f64, Vec<EdgeIndex>)> {
+        let g = &self.graph;
+        let dest = g[end];
+        let in_corridor = |a: NodeIndex, b: NodeIndex| {
+            let (a, b) = (g[a], g[b]);
+            options.corridor.is_none_or(|c| c.contains([(a.lat + b.lat) / 2.0, (a.lon + b.lon) / 2.0]))
+        };
+        let allowed = |e: EdgeReference<WalkEdge>| options.allows(e.weight()) && in_corridor(e.source(), e.target());
+        let weight = options.heuristic_weight.max(1.0);
+        let cost = |e: EdgeReference<WalkEdge>| {
+            let cost = edge_cost(e.id(), e.weight());
+            match options.heading_degrees {
+                Some(heading) if e.source() == start && self.edge_bearing_differs(e.id(), heading) => {
+                    cost + U_TURN_PENALTY_METERS
+                }
+                _ => cost,
+            }
+        };
+        let estimate = |n: NodeIndex| {
+            // Heuristic: great-circle distance, or the landmark bound when it is tighter.
+            // Edge costs are never below their length, so neither overestimates (admissible)
+            // unless a heuristic weight above 1 trades that for speed
+            let node = g[n];
+            let straight = haversine_meters([node.lat, node.lon], [dest.lat, dest.lon]);
+            let estimate = match &self.landmarks {
+                Some(landmarks) => straight.max(landmarks.lower_bound(n, end)),
+                None => straight,
+            };
+            weight * estimate
+        };
+
+        if !self.banned_turns.is_empty() {
+            return self.astar_over_edges(start, end, &allowed, &cost, &estimate);
+        }
+        self.astar_over_nodes(start, end, &allowed, &cost, &estimate)
+    }
+
+    /// The A* of `search`, remembering the edge each node was reached by so the path comes
+    /// back as edges. Nodes are expanded again if a cheaper way to them turns up, which an
+    /// inflated (weight above 1) heuristic allows.
+    fn astar_over_nodes(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        allowed: &dyn Fn(EdgeReference<WalkEdge>) -> bool,
+        cost: &dyn Fn(EdgeReference<WalkEdge>) -> f64,
+        estimate: &dyn Fn(NodeIndex) -> f64,
+    ) -> Option<(f64, Vec<EdgeIndex>)> {
+        let g = &self.graph;
+        let mut costs = vec![f64::INFINITY; g.node_count()];
+        let mut via: Vec<Option<EdgeIndex>> = vec![None; g.node_count()];
+        let mut queue = BinaryHeap::new();
+        costs[start.index()] = 0.0;
+        queue.push(Queued(estimate(start), (0.0, start)));
+
+        while let Some(Queued(_, (cost_so_far, node))) = queue.pop() {
+            if cost_so_far > costs[node.index()] {
+                continue; // Stale entry, the node was reached more cheaply since
+            }
+            if node == end {
+                let mut path = Vec::new();
+                let mut current = node;
+                while let Some(edge) = via[current.index()] {
+                    path.push(edge);
+                    current = g.edge_endpoints(edge)?.0;
+                }
+                path.reverse();
+                return Some((cost_so_far, path));
+            }
+            for edge in g.edges(node).filter(|e| allowed(*e)) {
+                let next = cost_so_far + cost(edge);
+                if next < costs[edge.target().index()] {
+                    costs[edge.target().index()] = next;
+                    via[edge.target().index()] = Some(edge.id());
+                    queue.push(Queued(next + estimate(edge.target()), (next, edge.target())));
+                }
+            }
+        }
+        None
+    }
+
+    /// `search` for graphs with banned turns. Whether an edge may be taken depends on
+    /// the edge it is entered from, so the search settles edges (arriving at their target)
+    /// instead of nodes; otherwise it is the same A*.
+    fn astar_over_edges(
+        &self,
+        start: NodeIndex,
+        end: NodeIndex,
+        allowed: &dyn Fn(EdgeReference<WalkEdge>) -> bool,
+        cost: &dyn Fn(EdgeReference<WalkEdge>) -> f64,
+        estimate: &dyn Fn(NodeIndex) -> f64,
+    ) -> Option<(f64, Vec<EdgeIndex>)> {
+        if start == end {
+            return Some((0.0, Vec::new()));
+        }
+        let g = &self.graph;
+        // Cheapest cost found so far of walking up to the end of each edge, and the edge before it
+        let mut costs = vec![f64::INFINITY; g.edge_count()];
+        let mut via: Vec<Option<EdgeIndex>> = vec![None; g.edge_count()];
+        let mut settled = vec![false; g.edge_count()];
+        let mut queue = BinaryHeap::new();
+
+        for edge in g.edges(start).filter(|e| allowed(*e)) {
+            let next = cost(edge);
+            if next < costs[edge.id().index()] {
+                costs[edge.id().index()] = next;
+                queue.push(Queued(next + estimate(edge.target()), edge.id()));
+            }
+        }
+        while let Some(Queued(_, edge)) = queue.pop() {
+            if std::mem::replace(&mut settled[edge.index()], true) {
+                continue; // Stale entry, the edge was settled more cheaply before
+            }
+            let (_, node) = g.edge_endpoints(edge)?;
+            if node == end {
+                let mut path = Vec::new();
+                let mut current = Some(edge);
+                while let Some(e) = current {
+                    path.push(e);
+                    current = via[e.index()];
+                }
+                path.reverse();
+                return Some((costs[edge.index()], path));
+            }
+            for next_edge in g.edges(node) {
+                if !allowed(next_edge) || self.banned_turns.contains(&(edge, next_edge.id())) {
+                    continue;
+                }
+                let next = costs[edge.index()] + cost(next_edge);
+                if next < costs[next_edge.id().index()] {
+                    costs[next_edge.id().index()] = next;
+                    via[next_edge.id().index()] = Some(edge);
+                    queue.push(Queued(next + estimate(next_edge.target()), next_edge.id()));
+                }
+            }
+        }
+        None
+    }
+
+    /// Bans every turn `restriction` covers: from any edge of its `from` way arriving at the
+    /// `via` node onto any edge of its `to` way leaving it, counting edges merged from several
+    /// ways (`shared_ways`) as part of each. Ignored if the node isn't in the graph.
+    fn ban_turn(&mut self, restriction: &TurnRestriction, shared_ways: &HashMap<EdgeIndex, Vec<i64>>) {
+        let Some(&via) = self.osm_id_map.get(&restriction.via_node) else { return };
+        let of_way = |way: i64| move |e: &EdgeReference<WalkEdge>| {
+            e.weight().way_id == Some(way) || shared_ways.get(&e.id()).is_some_and(|ways| ways.contains(&way))
+        };
+        let arriving: Vec<EdgeIndex> = self.graph.edges_directed(via, Direction::Incoming)
+            .filter(of_way(restriction.from_way))
+            .map(|e| e.id())
+            .collect();
+        for leaving in self.graph.edges(via).filter(of_way(restriction.to_way)) {
+            for &arriving in &arriving {
+                self.banned_turns.insert((arriving, leaving.id()));
+            }
+        }
+    }
+
+    /// Dijkstra from `start` to the whole graph under `options` (the heading is ignored), for
+    /// one-to-many queries where separate A* searches would repeat most of the work
+    pub fn route_tree(&self, start: NodeIndex, options: &RouteOptions) -> RouteTree {
+        self.search_tree(start, options, Direction::Outgoing)
+    }
+
+    /// Dijkstra backwards from `end` over incoming edges, still costed in walking direction:
+    /// the cheapest path from every node to `end`, for many-to-one queries
+    pub fn route_tree_to(&self, end: NodeIndex, options: &RouteOptions) -> RouteTree {
+        self.search_tree(end, options, Direction::Incoming)
+    }
+
+    fn search_tree(&self, root: NodeIndex, options: &RouteOptions, direction: Direction) -> RouteTree {
+        let n = self.graph.node_count();
+        let mut tree = RouteTree { root, direction, costs: vec![f64::INFINITY; n], via: vec![None; n], parents: None };
+        if root.index() >= n {
+            return tree;
+        }
+        if !self.banned_turns.is_empty() {
+            self.search_tree_over_edges(&mut tree, options);
+            return tree;
+        }
+
+        let mut queue = BinaryHeap::new();
+        tree.costs[root.index()] = 0.0;
+        queue.push(Queued(0.0, root));
+        while let Some(Queued(cost, node)) = queue.pop() {
+            if cost > tree.costs[node.index()] {
+                continue; // Stale entry, the node was reached more cheaply since
+            }
+            for edge in self.graph.edges_directed(node, direction).filter(|e| options.allows(e.weight())) {
+                let next = cost + options.edge_cost(edge.weight());
+                // The far end: where the edge leads, or where it comes from when searching backwards
+                let other = if direction == Direction::Outgoing { edge.target() } else { edge.source() };
+                if next < tree.costs[other.index()] {
+                    tree.costs[other.index()] = next;
+                    tree.via[other.index()] = Some(edge.id());
+                    queue.push(Queued(next, other));
+                }
+            }
+        }
+        tree
+    }
+
+    /// `search_tree` for graphs with banned turns, settling edges like `astar_over_edges`.
+    /// A node costs as much as the cheapest edge settled at it.
+    fn search_tree_over_edges(&self, tree: &mut RouteTree, options: &RouteOptions) {
+        let (g, direction) = (&self.graph, tree.direction);
+        // Turns are banned in walking order, which is the search order reversed when searching backwards
+        let banned = |edge: EdgeIndex, next: EdgeIndex| match direction {
+            Direction::Outgoing => self.banned_turns.contains(&(edge, next)),
+            Direction::Incoming => self.banned_turns.contains(&(next, edge)),
+        };
+        let mut costs = vec![f64::INFINITY; g.edge_count()];
+        let mut parents = vec![None; g.edge_count()];
+        let mut settled = vec![false; g.edge_count()];
+        let mut queue = BinaryHeap::new();
+
+        tree.costs[tree.root.index()] = 0.0;
+        for edge in g.edges_directed(tree.root, direction).filter(|e| options.allows(e.weight())) {
+            let next = options.edge_cost(edge.weight());
+            if next < costs[edge.id().index()] {
+                costs[edge.id().index()] = next;
+                queue.push(Queued(next, edge.id()));
+            }
+        }
+        while let Some(Queued(cost, edge)) = queue.pop() {
+            if std::mem::replace(&mut settled[edge.index()], true) {
+                continue; // Stale entry, the edge was settled more cheaply before
+            }
+            // The far end: where the edge leads, or where it comes from when searching backwards
+            let (source, target) = g.edge_endpoints(edge).expect("edge index from this graph");
+            let node = if direction == Direction::Outgoing { target } else { source };
+            if cost < tree.costs[node.index()] {
+                tree.costs[node.index()] = cost;
+                tree.via[node.index()] = Some(edge);
+            }
+            for next_edge in g.edges_directed(node, direction) {
+                if !options.allows(next_edge.weight()) || banned(edge, next_edge.id()) {
+                    continue;
+                }
+                let next = cost + options.edge_cost(next_edge.weight());
+                if next < costs[next_edge.id().index()] {
+                    costs[next_edge.id().index()] = next;
+                    parents[next_edge.id().index()] = Some(edge);
+                    queue.push(Queued(next, next_edge.id()));
+                }
+            }
+        }
+        tree.parents = Some(parents);
+    }
+
+    /// Precomputes ALT landmark tables (`count` landmarks in the largest component) to speed
+    /// up routing. Costs two Dijkstra runs and `16 * count` bytes per node; 0 removes them.
+    pub fn build_landmarks(&mut self, count: usize) {
+        self.landmarks = match self.largest_component() {
+            Some(component) if count > 0 => {
+                let seed = self.component_nodes(component).next().expect("components are non-empty");
+                Some(Landmarks::build(&self.graph, seed, count))
+            }
+            _ => None,
+        };
+    }
+
+    /// Number of ALT landmarks in use
+    pub fn landmark_count(&self) -> usize {
+        self.landmarks.as_ref().map_or(0, Landmarks::len)
+    }
+
+    /// Combines the tag (and zone) based `safety_score` of every edge with the location risk
+    /// at its midpoint, summed over the `sources` with data there, see `SafetyMap::blend_risk`. Call once per graph,
+    /// after `apply_zones`; use `add_incidents` for incidents reported later.
+    pub fn apply_location_risk(&mut self, sources: &[&dyn RiskSource], safety_map: &SafetyMap) {
+        self.alpha_costs.invalidate();
+        let risks = self.map_edges(|edge| {
+            let midpoint = self.edge_midpoint(edge);
+            sources.iter().filter_map(|source| source.risk_at(midpoint)).reduce(|a, b| a + b)
+        });
+        for (edge, risk) in self.graph.edge_indices().zip(risks) {
+            let weight = &mut self.graph[edge];
+            weight.safety_score = safety_map.blend_risk(weight.safety_score, risk);
+        }
+    }
+
+    /// Raises `safety_score` of every edge by its blended share of the risk from newly
+    /// reported incidents (clamped to the configured risk bounds). Apply each incident once.
+    pub fn add_incidents(&mut self, layer: &IncidentLayer, safety_map: &SafetyMap) {
+        self.alpha_costs.invalidate();
+        if layer.is_empty() {
+            return;
+        }
+        let risks = self.map_edges(|edge| layer.risk_near(self.edge_midpoint(edge)) * safety_map.location_share());
+        for (edge, risk) in self.graph.edge_indices().zip(risks) {
+            if risk > 0.0 {
+                let weight = &mut self.graph[edge];
+                weight.safety_score = safety_map.clamp_risk(weight.safety_score + risk);
+            }
+        }
+    }
+
+    /// Evaluates `f` for every edge in parallel, returning the results in edge index order.
+    /// Recomputes collect into this buffer before writing, as edges can't be mutated while
+    /// other threads read the graph.
+    fn map_edges<T: Send>(&self, f: impl Fn(EdgeIndex) -> T + Sync) -> Vec<T> {
+        (0..self.graph.edge_count()).into_par_iter().map(|i| f(EdgeIndex::new(i))).collect()
+    }
+
+    /// `[lat, lon]` halfway along an edge
+    fn edge_midpoint(&self, edge: EdgeIndex) -> [f64; 2] {
+        let (a, b) = self.graph.edge_endpoints(edge).expect("edge index from this graph");
+        let (na, nb) = (self.graph[a], self.graph[b]);
+        [(na.lat + nb.lat) / 2.0, (na.lon + nb.lon) / 2.0]
+    }
+
+    /// Applies `layer` to every edge, replacing the effect of earlier layers: flags edges
+    /// intersecting flood zones, and rescales `safety_score` from `base_safety` by the
+    /// multipliers of all zones the edge intersects (clamped to the configured risk bounds),
+    /// and charges edges leaving or entering boundary zones their `penalty_meters`
+    pub fn apply_zones(&mut self, layer: &ZoneLayer, safety_map: &SafetyMap) {
+        self.alpha_costs.invalidate();
+        let updates = self.map_edges(|edge| {
+            let (a, b) = self.graph.edge_endpoints(edge).expect("edge index from this graph");
+            let (na, nb) = (self.graph[a], self.graph[b]);
+            let (pa, pb) = ([na.lat, na.lon], [nb.lat, nb.lon]);
+            (
+                layer.intersects_segment(ZoneKind::Flood, pa, pb),
+                layer.risk_multiplier(pa, pb),
+                layer.boundary_penalty(pa, pb),
+            )
+        });
+        for (edge, (flooded, multiplier, boundary_penalty)) in self.graph.edge_indices().zip(updates) {
+            let weight = &mut self.graph[edge];
+            weight.flooded = flooded;
+            weight.boundary_penalty = boundary_penalty;
+            weight.safety_score = if multiplier == 1.0 {
+                weight.base_safety
+            } else {
+                safety_map.clamp_risk(weight.base_safety * multiplier)
+            };
+        }
+    }
+
+    /// Street name of an edge, if its way had one
+    pub fn name_of(&self, edge: EdgeIndex) -> Option<&str> {
+        self.graph.edge_weight(edge)?.name.and_then(|id| self.names.get(id))
+    }
+
+    /// Case-insensitive substring search over street names. Exact matches rank first,
+    /// then prefix matches, then other substrings; ties go to the shorter name.
+    /// Each result carries a representative point roughly mid-way along the street.
+    pub fn search_names(&self, query: &str, limit: usize) -> Vec<NameMatch<'_>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches: Vec<(u8, u32, &str)> = self.names.iter()
+            .filter_map(|(id, name)| {
+                let lower = name.to_lowercase();
+                let quality = if lower == query {
+                    0
+                } else if lower.starts_with(&query) {
+                    1
+                } else if lower.contains(&query) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((quality, id, name))
+            })
+            .collect();
+        matches.sort_by_key(|&(quality, _, name)| (quality, name.len(), name));
+        matches.truncate(limit);
+
+        // Collect each matched street's edges in one pass over the graph
+        let mut edges_by_name: HashMap<u32, Vec<EdgeIndex>> = matches.iter().map(|&(_, id, _)| (id, Vec::new())).collect();
+        for edge in self.graph.edge_references() {
+            if let Some(list) = edge.weight().name.and_then(|id| edges_by_name.get_mut(&id)) {
+                list.push(edge.id());
+            }
+        }
+
+        matches.into_iter()
+            .filter_map(|(_, id, name)| {
+                let edges = &edges_by_name[&id];
+                let (a, b) = self.graph.edge_endpoints(*edges.get(edges.len() / 2)?)?;
+                let (na, nb) = (self.graph[a], self.graph[b]);
+                Some(NameMatch { name, lat: (na.lat + nb.lat) / 2.0, lon: (na.lon + nb.lon) / 2.0 })
+            })
+            .collect()
+    }
+
+    /// Edges touching `bounds` (every edge if `None`), one per connected pair of nodes:
+    /// the reverse direction of a two-way street and parallel edges are skipped
+    pub fn undirected_edges(&self, bounds: Option<Rect>) -> impl Iterator<Item = EdgeIndex> + '_ {
+        let inside = move |n: NodeIndex| {
+            let node = self.graph[n];
+            bounds.is_none_or(|r| r.intersects(&Coord { x: node.lon, y: node.lat }))
+        };
+        let mut seen = HashSet::new();
+        self.graph.edge_references()
+            .filter(move |e| inside(e.source()) || inside(e.target()))
+            .filter(move |e| seen.insert((e.source().min(e.target()), e.source().max(e.target()))))
+            .map(|e| e.id())
+    }
+
+    /// Addresses of OSM address nodes within `radius_meters`, nearest first, with their
+    /// distances in meters
+    pub fn addresses_near(&self, lat: f64, lon: f64, radius_meters: f64) -> Vec<(&str, f64)> {
+        let mut found: Vec<_> = self.addresses.within([lat, lon], radius_meters)
+            .map(|(address, d)| (address.as_str(), d))
+            .collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    /// The `k` amenities tagged `amenity=<kind>` nearest to the point, nearest first, with their
+    /// straight-line distances in meters. Empty if the map has none of that kind.
+    pub fn amenities_near(&self, lat: f64, lon: f64, kind: &str, k: usize) -> Vec<(&Amenity, f64)> {
+        self.amenities.get(kind).map_or_else(Vec::new, |index| index.nearest([lat, lon], k))
+    }
+
+    /// Bounding box of all nodes (x = lon, y = lat), `None` for an empty graph
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut nodes = self.graph.node_weights();
+        let first = nodes.next()?;
+        let (mut min, mut max) = (Coord { x: first.lon, y: first.lat }, Coord { x: first.lon, y: first.lat });
+        for node in nodes {
+            min = Coord { x: min.x.min(node.lon), y: min.y.min(node.lat) };
+            max = Coord { x: max.x.max(node.lon), y: max.y.max(node.lat) };
+        }
+        Some(Rect::new(min, max))
+    }
+
+    /// OSM id of a graph node; `None` for graphs not built from OSM data
+    pub fn osm_id_of(&self, node: NodeIndex) -> Option<i64> {
+        self.osm_id_of.get(node.index()).copied().flatten()
+    }
+
+    /// Elevation of a node in meters, if its OSM node carries an `ele` tag
+    pub fn elevation_of(&self, node: NodeIndex) -> Option<f32> {
+        self.elevations.get(node.index()).copied().flatten()
+    }
+
+    /// Connected-component id of a node; nodes sharing an id can reach each other
+    pub fn component_of(&self, node: NodeIndex) -> Option<usize> {
+        self.component_of.get(node.index()).copied()
+    }
+
+    /// Number of connected components
+    pub fn component_count(&self) -> usize {
+        self.component_sizes.len()
+    }
+
+    /// Id of the component with the most nodes, if the graph has any
+    pub fn largest_component(&self) -> Option<usize> {
+        (0..self.component_sizes.len()).max_by_key(|&c| self.component_sizes[c])
+    }
+
+    /// Nodes belonging to the given component
+    pub fn component_nodes(&self, component: usize) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.component_of.iter()
+            .enumerate()
+            .filter(move |&(_, &c)| c == component)
+            .map(|(n, _)| NodeIndex::new(n))
+    }
+
+    /// Number of nodes in the given component
+    pub fn component_size(&self, component: usize) -> usize {
+        self.component_sizes.get(component).copied().unwrap_or(0)
+    }
+
+    /// Rough RAM footprint of the graph in bytes: node and edge storage plus the lookup
+    /// tables, by allocated capacity. It ignores allocator overhead and `HashMap` control
+    /// bytes beyond one per slot, so treat it as an estimate, not an exact measurement.
+    pub fn memory_estimate_bytes(&self) -> usize {
+        use std::mem::size_of;
+
+        let (node_cap, edge_cap) = self.graph.capacity();
+        let nodes = node_cap * size_of::<petgraph::graph::Node<GeoNode>>();
+        let edges = edge_cap * size_of::<petgraph::graph::Edge<WalkEdge>>();
+        let osm_ids = self.osm_id_map.capacity() * (size_of::<(i64, NodeIndex)>() + 1)
+            + self.osm_id_of.capacity() * size_of::<Option<i64>>();
+        let components = (self.component_of.capacity() + self.component_sizes.capacity()) * size_of::<usize>();
+        let elevations = self.elevations.capacity() * size_of::<Option<f32>>();
+
+        let landmarks = self.landmarks.as_ref().map_or(0, Landmarks::memory_estimate_bytes);
+        // R-tree leaves only; inner nodes add a few percent
+        let node_index = self.node_index.len() * size_of::<([f64; 2], NodeIndex)>()
+            + self.edge_index.len() * size_of::<([f64; 2], [f64; 2], EdgeIndex)>();
+        let amenities = self.amenities.values().map(PointIndex::len).sum::<usize>() * size_of::<([f64; 2], Amenity)>();
+        let banned_turns = self.banned_turns.capacity() * (size_of::<(EdgeIndex, EdgeIndex)>() + 1);
+        let alpha_costs: usize = self.alpha_costs.costs.read().unwrap().values().map(|c| c.capacity() * size_of::<f64>()).sum();
+
+        size_of::<Self>() + nodes + edges + osm_ids + components + elevations + landmarks + node_index + amenities
+            + banned_turns + alpha_costs
+            + self.names.memory_estimate_bytes()
+    }
+
+    /// Nearest node together with its distance from the query point, in meters
+    pub fn snap(&self, lat: f64, lon: f64) -> Option<(NodeIndex, f64)> {
+        let node = self.find_nearest_node(lat, lon)?;
+        let n = self.graph[node];
+        Some((node, haversine_meters([lat, lon], [n.lat, n.lon])))
+    }
+
+    /// Snaps a moving user to the graph: of the nearest edge's two endpoints, picks the
+    /// one lying ahead along `heading_degrees`, so the route doesn't start behind them
+    pub fn find_departure_node(&self, lat: f64, lon: f64, heading_degrees: f64) -> Option<NodeIndex> {
+        let edge = self.find_nearest_edge(lat, lon)?;
+        let (a, b) = self.graph.edge_endpoints(edge)?;
+
+        let turn_towards = |n: NodeIndex| {
+            let node = self.graph[n];
+            bearing_difference(bearing_degrees([lat, lon], [node.lat, node.lon]), heading_degrees)
+        };
+        Some(if turn_towards(a) <= turn_towards(b) { a } else { b })
+    }
+
+    /// True if the edge heads more than 90° away from `heading_degrees`
+    fn edge_bearing_differs(&self, edge: EdgeIndex, heading_degrees: f64) -> bool {
+        let Some((a, b)) = self.graph.edge_endpoints(edge) else { return false };
+        let (na, nb) = (self.graph[a], self.graph[b]);
+        bearing_difference(bearing_degrees([na.lat, na.lon], [nb.lat, nb.lon]), heading_degrees) > 90.0
+    }
+
+    /// Finds the edge whose segment passes closest to the given point (the lowest index
+    /// among equally close ones, e.g. both directions of a street)
+    pub fn find_nearest_edge(&self, lat: f64, lon: f64) -> Option<EdgeIndex> {
+        self.edge_index.nearest([lat, lon]).map(|(&e, _)| e)
+    }
+}
+
+/// Opens an OSM PBF file for one streaming pass, transparently decompressing
+/// `.gz` and `.zst` files. `pass` tracks how far into the (raw) file the pass is.
+fn open_reader(path: &str, pass: Arc<PassState>) -> anyhow::Result<ElementReader<Box<dyn Read + Send>>> {
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("cannot open {path}: {e}"))?;
+    let file = BufReader::new(CountingReader { inner: file, pass });
+    let input: Box<dyn Read + Send> = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::with_buffer(file)?),
+        _ => Box::new(file),
+    };
+    Ok(ElementReader::new(input))
+}
+
+/// Counts the bytes passing through, for progress reporting
+struct CountingReader<R> {
+    inner: R,
+    pass: Arc<PassState>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pass.abort.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other("load aborted"));
+        }
+        let n = self.inner.read(buf)?;
+        self.pass.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Shared between one loading pass and its reader
+#[derive(Default)]
+struct PassState {
+    bytes_read: AtomicU64,
+    /// Set to make further reads fail, since `ElementReader::for_each` can't be stopped otherwise
+    abort: AtomicBool,
+}
+
+/// Throttles progress callbacks for one loading pass
+struct ProgressReporter<'a> {
+    phase: LoadPhase,
+    processed: u64,
+    pass: Arc<PassState>,
+    total_bytes: u64,
+    callback: &'a mut dyn FnMut(LoadProgress),
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(phase: LoadPhase, pass: Arc<PassState>, total_bytes: u64, callback: &'a mut dyn FnMut(LoadProgress)) -> Self {
+        Self { phase, processed: 0, pass, total_bytes, callback }
+    }
+
+    /// Counts one element, reporting every `PROGRESS_INTERVAL` elements
+    fn element(&mut self) {
+        self.processed += 1;
+        if self.processed.is_multiple_of(PROGRESS_INTERVAL) {
+            let percent = if self.total_bytes == 0 {
+                0.0
+            } else {
+                (self.pass.bytes_read.load(Ordering::Relaxed) as f64 / self.total_bytes as f64 * 100.0).min(100.0)
+            };
+            self.report(percent);
+        }
+    }
+
+    /// Reports the pass as complete
+    fn finish(mut self) {
+        self.report(100.0);
+    }
+
+    fn report(&mut self, percent: f64) {
+        (self.callback)(LoadProgress { phase: self.phase, processed: self.processed, percent });
+    }
+}
+
+/// Shops, markets and transit stops: places that keep people around on foot
+fn draws_foot_traffic(tags: &HashMap<&str, &str>) -> bool {
+    tags.contains_key("shop")
+        || matches!(tags.get("amenity"), Some(&("marketplace" | "cafe" | "restaurant" | "fast_food")))
+        || tags.get("highway") == Some(&"bus_stop")
+        || matches!(tags.get("public_transport"), Some(&("platform" | "station")))
+}
+
+/// Drops interior way nodes closer than `min_length` to the previous kept node, so a run of
+/// tiny segments becomes one edge. The way's ends, `pinned` nodes and nodes without known
+/// coordinates are always kept; a short last segment is folded into the one before it.
+fn merge_short_segments(
+    refs: &[i64],
+    min_length: f64,
+    coords: &HashMap<i64, (f64, f64)>,
+    pinned: impl Fn(i64) -> bool,
+) -> Vec<i64> {
+    let distance = |a: i64, b: i64| match (coords.get(&a), coords.get(&b)) {
+        (Some(&(lat_a, lon_a)), Some(&(lat_b, lon_b))) => Some(haversine_meters([lat_a, lon_a], [lat_b, lon_b])),
+        _ => None,
+    };
+    let movable = |i: usize, id: i64| i > 0 && i < refs.len() - 1 && coords.contains_key(&id) && !pinned(id);
+
+    // (position in `refs`, id) of the nodes kept so far
+    let mut kept: Vec<(usize, i64)> = vec![(0, refs[0])];
+    for (i, &id) in refs.iter().enumerate().skip(1) {
+        let &(_, previous) = kept.last().expect("the first node is always kept");
+        let short = distance(previous, id).is_some_and(|d| d < min_length);
+        if short && movable(i, id) {
+            continue;
+        }
+        // The way's end can't move, so a short final segment absorbs the node before it instead
+        if short && i == refs.len() - 1 && kept.len() > 1 && kept.last().is_some_and(|&(j, p)| movable(j, p)) {
+            kept.pop();
+        }
+        kept.push((i, id));
+    }
+    kept.into_iter().map(|(_, id)| id).collect()
+}
+
+/// Adds an edge, unless `from` already leads to `to` (ways drawn over each other, e.g. a
+/// footway mapped on top of a road): then the lower-risk of the two is kept, restricted as
+/// much as either (private, tunnel, closed at night), and the other's way is recorded in
+/// `shared_ways` so turn restrictions on it still apply
+fn add_or_merge_edge(
+    graph: &mut Graph<GeoNode, WalkEdge>,
+    shared_ways: &mut HashMap<EdgeIndex, Vec<i64>>,
+    from: NodeIndex,
+    to: NodeIndex,
+    edge: WalkEdge,
+) {
+    let Some(existing) = graph.find_edge(from, to) else {
+        graph.add_edge(from, to, edge);
+        return;
+    };
+    let (mut kept, other) = match edge.safety_score < graph[existing].safety_score {
+        true => (edge, graph[existing]),
+        false => (graph[existing], edge),
+    };
+    kept.private |= other.private;
+    kept.is_tunnel |= other.is_tunnel;
+    kept.foot_closed = match (kept.foot_closed, other.foot_closed) {
+        // Only closures at night matter (see `RouteOptions::allows`)
+        (Some(hours), Some(other_hours)) if !hours.overlaps(&NIGHT_HOURS) => Some(other_hours),
+        (hours, other_hours) => hours.or(other_hours),
+    };
+    if other.way_id != kept.way_id {
+        shared_ways.entry(existing).or_default().extend(other.way_id);
+    }
+    graph[existing] = kept;
+}
+
+/// Shade at a point in [0, 1]: full inside a wood or forest, otherwise growing with the
+/// number of trees within `SHADE_RADIUS_METERS`
+fn shade_at(point: [f64; 2], trees: &PointIndex<()>, woods: &[(Rect, Polygon)]) -> f32 {
+    let coord = Coord { x: point[1], y: point[0] };
+    if woods.iter().any(|(bounds, wood)| bounds.intersects(&coord) && wood.contains(&coord)) {
+        return 1.0;
+    }
+
+    let nearby = trees.within(point, SHADE_RADIUS_METERS).count();
+    (nearby as f32 / TREES_FOR_FULL_SHADE).min(1.0)
+}
+
+/// Parses an OSM `incline` value into an absolute grade in percent.
+/// Handles `8%`, `-8%`, bare numbers (percent), degrees (`5°`) and the qualitative
+/// `up`/`down`/`yes` (some slope, assumed moderate) and `steep`.
+fn parse_incline(value: &str) -> Option<f32> {
+    let value = value.trim();
+    match value {
+        "no" => return Some(0.0),
+        "up" | "down" | "yes" => return Some(UNSPECIFIED_INCLINE_PERCENT),
+        "steep" => return Some(STEEP_INCLINE_PERCENT),
+        _ => {}
+    }
+
+    let percent = if let Some(n) = value.strip_suffix('%') {
+        n.trim().parse::<f32>().ok()?
+    } else if let Some(n) = value.strip_suffix('°') {
+        n.trim().parse::<f32>().ok()?.to_radians().tan() * 100.0
+    } else {
+        value.parse::<f32>().ok()?
+    };
+    percent.is_finite().then_some(percent.abs())
+}
+
+/// Parses an OSM `ele` value in meters; tolerates a trailing `m`
+fn parse_ele(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let meters = value.strip_suffix('m').unwrap_or(value).trim().parse::<f32>().ok()?;
+    meters.is_finite().then_some(meters)
+}
+
+/// Parses the common `foot:conditional=no @ (22:00-06:00)` form into the closed hours.
+/// Anything else (other restrictions, weekdays, several windows, ...) is ignored.
+fn parse_foot_closed(value: &str) -> Option<TimeWindow> {
+    let (restriction, condition) = value.split_once('@')?;
+    if restriction.trim() != "no" {
+        return None;
+    }
+    let condition = condition.trim();
+    let condition = condition.strip_prefix('(').and_then(|c| c.strip_suffix(')')).unwrap_or(condition);
+    TimeWindow::parse(condition)
+}
+
+/// "12 Mall Road" from `addr:housenumber` plus `addr:street` (or `addr:place` for
+/// places without streets); `None` unless both parts are present
+fn format_address(tags: &HashMap<&str, &str>) -> Option<String> {
+    let number = tags.get("addr:housenumber")?;
+    let street = tags.get("addr:street").or_else(|| tags.get("addr:place"))?;
+    Some(format!("{number} {street}"))
+}
+
+/// Classifies a `highway=crossing` node. `crossing=no` marks a spot where crossing is
+/// forbidden, so it is not treated as a crossing at all.
+fn parse_crossing(tags: &HashMap<&str, &str>) -> Option<Crossing> {
+    if tags.get("highway") != Some(&"crossing") {
+        return None;
+    }
+
+    let kind = match tags.get("crossing").copied().unwrap_or("") {
+        "no" => return None,
+        "traffic_signals" => CrossingType::TrafficSignals,
+        _ => CrossingType::Uncontrolled,
+    };
+    Some(Crossing { kind, island: tags.get("crossing:island") == Some(&"yes") })
+}
+
+/// Returns true for barrier nodes (gates, bollards, ...) that pedestrians cannot pass,
+/// i.e. tagged `access=no`/`private` without an explicit foot permission, or `foot=no`.
+fn is_blocking_barrier(tags: &HashMap<&str, &str>) -> bool {
+    let barrier = tags.get("barrier").copied().unwrap_or("");
+    if !matches!(barrier, "gate" | "lift_gate" | "swing_gate" | "bollard" | "block") {
+        return false;
+    }
+
+    let access = tags.get("access").copied().unwrap_or("");
+    let foot = tags.get("foot").copied().unwrap_or("");
+
+    match foot {
+        "yes" | "designated" | "permissive" => false,
+        "no" => true,
+        _ => matches!(access, "no" | "private"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::algo::dijkstra;
+    use proptest::prelude::*;
+    use crate::incidents::Incident;
+    use crate::osm_fixture::{Member, OsmFixture};
+    use crate::safety::SafetyConfig;
+    use crate::test_graphs::{edge, grid, nodes, two_way};
+
+    fn load(fixture: &OsmFixture) -> NavigationGraph {
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        NavigationGraph::from_pbf(fixture.write().path(), &safety_map).unwrap()
+    }
+
+    #[test]
+    fn locked_gate_cuts_the_way() {
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.371, &[("barrier", "gate"), ("access", "private")])
+            .node(3, 30.34, 76.372, &[])
+            .node(4, 30.34, 76.373, &[])
+            .way(10, &[1, 2, 3, 4], &[("highway", "footway")]);
+        let nav = load(&osm);
+
+        assert!(!nav.osm_id_map.contains_key(&2));
+        assert!(!nav.osm_id_map.contains_key(&1));
+        assert_eq!(nav.graph.edge_count(), 2, "only 3-4 remains, in both directions");
+    }
+
+    #[test]
+    fn access_tags_close_or_demote_ways() {
+        let mut osm = OsmFixture::default();
+        for (i, lon) in [76.370, 76.371, 76.372, 76.373].into_iter().enumerate() {
+            osm.node(i as i64 + 1, 30.34, lon, &[]);
+        }
+        osm.way(10, &[1, 2], &[("highway", "footway"), ("access", "no")])
+            .way(11, &[2, 3], &[("highway", "footway"), ("access", "private")])
+            .way(12, &[3, 4], &[("highway", "footway"), ("access", "private"), ("foot", "yes")]);
+        let nav = load(&osm);
+
+        let private_of = |way: i64| {
+            let flags: Vec<bool> = nav.graph.edge_weights().filter(|e| e.way_id == Some(way)).map(|e| e.private).collect();
+            (!flags.is_empty()).then(|| flags.iter().all(|&p| p))
+        };
+        assert_eq!(private_of(10), None, "access=no leaves no edges");
+        // Private ways stay as a last resort; an explicit foot permission makes them public
+        assert_eq!(private_of(11), Some(true));
+        assert_eq!(private_of(12), Some(false));
+    }
+
+    #[test]
+    fn night_routes_around_tunnels() {
+        let tunnel = WalkEdge { is_tunnel: true, ..edge(100.0, 0.1) };
+        let nav = NavigationGraph::from_edges(&nodes(3), &two_way(&[(0, 1, tunnel), (0, 2, edge(75.0, 0.1)), (2, 1, edge(75.0, 0.1))]));
+        let (start, end) = (NodeIndex::new(0), NodeIndex::new(1));
+
+        let day = RouteOptions::default();
+        assert_eq!(nav.route(start, end, &day).unwrap().1, vec![start, end]);
+        let night = RouteOptions { night: true, ..Default::default() };
+        assert_eq!(nav.route(start, end, &night).unwrap().1, vec![start, NodeIndex::new(2), end]);
+    }
+
+    #[test]
+    fn nearest_edge_is_found_by_its_segment() {
+        // A long street passing right by the point, and a short one ending nearer to it
+        let nodes = [
+            GeoNode { lat: 30.340, lon: 76.360 },
+            GeoNode { lat: 30.340, lon: 76.380 },
+            GeoNode { lat: 30.3405, lon: 76.370 },
+            GeoNode { lat: 30.3410, lon: 76.370 },
+        ];
+        let nav = NavigationGraph::from_edges(&nodes, &two_way(&[(0, 1, edge(1900.0, 0.1)), (2, 3, edge(60.0, 0.1))]));
+
+        let nearest = nav.find_nearest_edge(30.3401, 76.370).unwrap();
+        assert_eq!(nearest, EdgeIndex::new(0), "the lower index of the two directions");
+        assert_eq!(nav.find_nearest_edge(30.3406, 76.3701), Some(EdgeIndex::new(2)));
+    }
+
+    /// Random connected-or-not graphs of up to 8 nodes in a ~1 km square. Edges are at least as
+    /// long as the straight line between their ends, as in graphs built from OSM data.
+    fn random_graph() -> impl Strategy<Value = (Vec<GeoNode>, Vec<(usize, usize, WalkEdge)>)> {
+        let nodes = prop::collection::vec((0.0..0.01f64, 0.0..0.01f64), 2..8);
+        nodes.prop_flat_map(|positions| {
+            let n = positions.len();
+            let edges = prop::collection::vec((0..n, 0..n, 0.0..2.0f64, 0.0..1.0f32), 0..n * 3);
+            (Just(positions), edges)
+        })
+        .prop_map(|(positions, edges)| {
+            let nodes: Vec<GeoNode> = positions.iter().map(|&(lat, lon)| GeoNode { lat: 30.34 + lat, lon: 76.37 + lon }).collect();
+            let edges = edges.into_iter()
+                .map(|(a, b, detour, risk)| {
+                    let straight = haversine_meters([nodes[a].lat, nodes[a].lon], [nodes[b].lat, nodes[b].lon]);
+                    (a, b, edge(straight * (1.0 + detour) + 1.0, risk))
+                })
+                .collect();
+            (nodes, edges)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn astar_matches_dijkstra((nodes, edges) in random_graph(), alpha in 0.0..5.0f64, landmarks in 0..3usize) {
+            let mut nav = NavigationGraph::from_edges(&nodes, &edges);
+            nav.build_landmarks(landmarks);
+            let options = RouteOptions { alpha, ..Default::default() };
+            let end = NodeIndex::new(nav.graph.node_count() - 1);
+            let reference = dijkstra(petgraph::visit::Reversed(&nav.graph), end, None, |e| options.edge_cost(e.weight()));
+
+            for start in nav.graph.node_indices() {
+                let found = nav.route(start, end, &options).map(|(cost, _)| cost);
+                match (found, reference.get(&start)) {
+                    (Some(cost), Some(&best)) => prop_assert!((cost - best).abs() <= 1e-6 * best.max(1.0), "A* {cost} vs Dijkstra {best}"),
+                    (None, None) => {}
+                    (found, best) => prop_assert!(false, "A* {found:?} vs Dijkstra {best:?}"),
+                }
+
+                // Neither heuristic may overestimate the true remaining cost
+                if let Some(&best) = reference.get(&start) {
+                    let (node, dest) = (nav.graph[start], nav.graph[end]);
+                    prop_assert!(haversine_meters([node.lat, node.lon], [dest.lat, dest.lon]) <= best + 1e-6);
+                    if let Some(landmarks) = &nav.landmarks {
+                        prop_assert!(landmarks.lower_bound(start, end) <= best + 1e-6);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_snapping_and_routing() {
+        let nav = grid(3);
+        assert_eq!(nav.find_nearest_node(30.3411, 76.3712), Some(NodeIndex::new(4)));
+        assert_eq!(nav.find_nearest_node(30.3399, 76.3689), Some(NodeIndex::new(0)));
+
+        let (cost, path) = nav.route(NodeIndex::new(0), NodeIndex::new(8), &RouteOptions::default()).unwrap();
+        assert_eq!(path.len(), 5, "four blocks, corner to corner");
+        let straight_legs: f64 = path.windows(2).map(|p| nav.graph[nav.graph.find_edge(p[0], p[1]).unwrap()].distance_meters).sum();
+        assert!((cost - straight_legs).abs() < 1e-9);
+
+        // A risky centre pushes a safety-minded route around it
+        let mut nav = nav;
+        let centre = NodeIndex::new(4);
+        for e in nav.graph.edge_indices().collect::<Vec<_>>() {
+            let (a, b) = nav.graph.edge_endpoints(e).unwrap();
+            if a == centre || b == centre {
+                nav.graph[e].safety_score = 1.0;
+            }
+        }
+        let (_, path) = nav.route(NodeIndex::new(1), NodeIndex::new(7), &RouteOptions { alpha: 5.0, ..Default::default() }).unwrap();
+        assert!(!path.contains(&centre), "{path:?}");
+    }
+
+    #[test]
+    fn heuristic_weight_expands_less_and_stays_valid() {
+        let nav = grid(20);
+        let (start, end) = (NodeIndex::new(0), NodeIndex::new(399));
+        let search = |heuristic_weight: f64| {
+            let options = RouteOptions { alpha: 2.0, heuristic_weight, ..Default::default() };
+            let evaluated = std::cell::Cell::new(0);
+            let route = nav.route_with_cost(start, end, &options, &|edge| {
+                evaluated.set(evaluated.get() + 1);
+                options.edge_cost(edge)
+            });
+            (route.unwrap(), evaluated.get())
+        };
+
+        let ((exact_cost, _), exact_work) = search(1.0);
+        let ((cost, path), work) = search(2.0);
+        assert!(work < exact_work, "{work} edges costed, {exact_work} without a weight");
+        assert!(cost >= exact_cost - 1e-9 && cost <= 2.0 * exact_cost);
+        assert_eq!((path.first(), path.last()), (Some(&start), Some(&end)));
+        assert!(path.windows(2).all(|p| nav.graph.find_edge(p[0], p[1]).is_some()));
+    }
+
+    #[test]
+    fn node_cap_fails_the_load() {
+        let mut osm = OsmFixture::default();
+        for id in 1..=5 {
+            osm.node(id, 30.34, 76.37 + id as f64 * 0.001, &[]);
+        }
+        osm.way(10, &[1, 2, 3, 4, 5], &[("highway", "footway")]);
+        let file = osm.write();
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let load = |max_nodes| {
+            let options = LoadOptions { max_nodes: Some(max_nodes), ..Default::default() };
+            NavigationGraph::from_pbf_with_options(file.path(), &safety_map, &options, &mut |_| {})
+        };
+
+        let error = load(4).err().expect("5 nodes are over the cap");
+        assert!(error.to_string().contains("more than 4 nodes"), "{error}");
+        assert_eq!(load(5).unwrap().graph.node_count(), 5);
+    }
+
+    #[test]
+    fn custom_allowlist_walks_tracks() {
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.371, &[])
+            .node(3, 30.34, 76.372, &[])
+            .way(10, &[1, 2], &[("highway", "track")])
+            .way(11, &[2, 3], &[("highway", "footway")]);
+        let file = osm.write();
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let edges_with = |walkable: WalkableHighways| {
+            let options = LoadOptions { walkable, ..Default::default() };
+            NavigationGraph::from_pbf_with_options(file.path(), &safety_map, &options, &mut |_| {}).unwrap().graph.edge_count()
+        };
+
+        assert_eq!(edges_with(WalkableHighways::default()), 2, "tracks aren't walkable by default");
+        let mut with_tracks = WalkableHighways::default();
+        with_tracks.always.push("track".to_string());
+        assert_eq!(edges_with(with_tracks), 4);
+    }
+
+    #[test]
+    fn short_segment_is_merged() {
+        // Node 2 sits 0.2 m after node 1 (0.0000018° of latitude)
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.3400018, 76.370, &[])
+            .node(3, 30.341, 76.370, &[])
+            .way(10, &[1, 2, 3], &[("highway", "footway")]);
+        let file = osm.write();
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let load = |min_edge_length_meters| {
+            let options = LoadOptions { min_edge_length_meters, ..Default::default() };
+            NavigationGraph::from_pbf_with_options(file.path(), &safety_map, &options, &mut |_| {}).unwrap()
+        };
+
+        let kept = load(None);
+        assert_eq!((kept.graph.node_count(), kept.graph.edge_count()), (3, 4));
+        let merged = load(Some(1.0));
+        assert_eq!((merged.graph.node_count(), merged.graph.edge_count()), (2, 2));
+        let length = haversine_meters([30.34, 76.370], [30.341, 76.370]);
+        assert!(merged.graph.edge_weights().all(|e| (e.distance_meters - length).abs() < 1e-6));
+    }
+
+    #[test]
+    fn nearest_police_comes_first() {
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.380, &[])
+            .node(3, 30.3401, 76.3701, &[("amenity", "hospital")])
+            .node(4, 30.342, 76.372, &[("amenity", "police"), ("name", "Civil Lines")])
+            .node(5, 30.3405, 76.3705, &[("amenity", "police"), ("name", "Mall Road")])
+            .way(10, &[1, 2], &[("highway", "footway")]);
+        let nav = load(&osm);
+
+        let police = nav.amenities_near(30.34, 76.370, "police", 5);
+        let names: Vec<_> = police.iter().map(|(amenity, _)| amenity.name.as_deref()).collect();
+        assert_eq!(names, [Some("Mall Road"), Some("Civil Lines")]);
+        assert!(police[0].1 < police[1].1);
+        assert_eq!(nav.amenities_near(30.34, 76.370, "police", 1).len(), 1);
+        assert!(nav.amenities_near(30.34, 76.370, "pharmacy", 5).is_empty());
+    }
+
+    #[test]
+    fn banned_turn_is_walked_around() {
+        // 1-2-3 turns left at 2; the detour 1-4-3 is longer. Way 13 doubles 11 and, being lit,
+        // replaces it on the merged edge, which the restriction on 11 must still cover.
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.340, 76.370, &[])
+            .node(2, 30.340, 76.371, &[])
+            .node(3, 30.341, 76.371, &[])
+            .node(4, 30.341, 76.3695, &[])
+            .way(10, &[1, 2], &[("highway", "footway")])
+            .way(11, &[2, 3], &[("highway", "footway")])
+            .way(13, &[2, 3], &[("highway", "footway"), ("lit", "yes")])
+            .way(12, &[1, 4, 3], &[("highway", "footway")])
+            .relation(
+                20,
+                &[(Member::Way, 10, "from"), (Member::Node, 2, "via"), (Member::Way, 11, "to")],
+                &[("type", "restriction"), ("restriction:foot", "no_left_turn")],
+            );
+        let file = osm.write();
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let load = |turn_restrictions| {
+            let options = LoadOptions { turn_restrictions, ..Default::default() };
+            NavigationGraph::from_pbf_with_options(file.path(), &safety_map, &options, &mut |_| {}).unwrap()
+        };
+        let osm_ids = |nav: &NavigationGraph, path: &[NodeIndex]| {
+            let ids: HashMap<NodeIndex, i64> = nav.osm_id_map.iter().map(|(&id, &n)| (n, id)).collect();
+            path.iter().map(|n| ids[n]).collect::<Vec<_>>()
+        };
+        let options = RouteOptions::default();
+
+        let free = load(false);
+        let (from, to) = (free.osm_id_map[&1], free.osm_id_map[&3]);
+        assert_eq!(osm_ids(&free, &free.route(from, to, &options).unwrap().1), [1, 2, 3]);
+
+        let nav = load(true);
+        let (from, to) = (nav.osm_id_map[&1], nav.osm_id_map[&3]);
+        let merged = nav.graph.find_edge(nav.osm_id_map[&2], to).unwrap();
+        assert_eq!(nav.graph[merged].way_id, Some(13));
+        let (cost, path) = nav.route(from, to, &options).unwrap();
+        assert_eq!(osm_ids(&nav, &path), [1, 4, 3]);
+        assert_eq!(osm_ids(&nav, &nav.route(to, from, &options).unwrap().1), [3, 2, 1], "the turn back is allowed");
+
+        // The one-to-many and many-to-one trees honour the ban as well
+        let forward = nav.route_tree(from, &options);
+        let backward = nav.route_tree_to(to, &options);
+        for (tree, target) in [(&forward, to), (&backward, from)] {
+            assert!((tree.cost_to(target).unwrap() - cost).abs() < 1e-9);
+            assert_eq!(osm_ids(&nav, &nav.nodes_along(from, &tree.edges_to(&nav.graph, target).unwrap())), [1, 4, 3]);
+        }
+        assert_eq!(backward.edges_to(&nav.graph, to), Some(Vec::new()));
+    }
+
+    #[test]
+    fn undirected_edges_are_one_per_pair() {
+        // 0-1 twice in each direction, 1-2 one way only, 2-3 both ways
+        let edges = [two_way(&[(0, 1, edge(1.0, 0.1)), (0, 1, edge(1.0, 0.2)), (2, 3, edge(1.0, 0.1))]), vec![(1, 2, edge(1.0, 0.1))]].concat();
+        let nav = NavigationGraph::from_edges(&nodes(4), &edges);
+
+        let mut pairs: Vec<_> = nav.undirected_edges(None)
+            .map(|e| nav.graph.edge_endpoints(e).unwrap())
+            .map(|(a, b)| (a.index().min(b.index()), a.index().max(b.index())))
+            .collect();
+        pairs.sort();
+        assert_eq!(pairs, [(0, 1), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn snapping_passes_over_dead_end_stubs() {
+        // A street 0-1-2 with a short stub from 1 north to 3
+        let nodes = [
+            GeoNode { lat: 30.34, lon: 76.370 },
+            GeoNode { lat: 30.34, lon: 76.371 },
+            GeoNode { lat: 30.34, lon: 76.372 },
+            GeoNode { lat: 30.3403, lon: 76.371 },
+        ];
+        let nav = NavigationGraph::from_edges(&nodes, &two_way(&[(0, 1, edge(96.0, 0.1)), (1, 2, edge(96.0, 0.1)), (1, 3, edge(33.0, 0.1))]));
+
+        assert_eq!(nav.find_nearest_node(30.3404, 76.371), Some(NodeIndex::new(3)));
+        assert_eq!(nav.find_nearest_through_node(30.3404, 76.371, 100.0), Some(NodeIndex::new(1)));
+        assert_eq!(nav.find_nearest_through_node(30.3404, 76.371, 10.0), Some(NodeIndex::new(3)), "no through node in reach");
+    }
+
+    #[test]
+    fn night_closed_way_is_open_by_day() {
+        // A direct path closed at night, and a detour to the north
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.372, &[])
+            .node(3, 30.34, 76.371, &[])
+            .node(4, 30.341, 76.370, &[])
+            .node(5, 30.341, 76.372, &[])
+            .way(10, &[1, 3, 2], &[("highway", "footway"), ("foot:conditional", "no @ (22:00-06:00)")])
+            .way(11, &[1, 4, 5, 2], &[("highway", "footway")]);
+        let nav = load(&osm);
+        let (start, end) = (nav.osm_id_map[&1], nav.osm_id_map[&2]);
+        let via = |night| {
+            let (_, path) = nav.route(start, end, &RouteOptions { night, ..Default::default() }).unwrap();
+            path.iter().filter_map(|&n| nav.osm_id_of(n)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(via(false), [1, 3, 2]);
+        assert_eq!(via(true), [1, 4, 5, 2]);
+    }
+
+    #[test]
+    fn service_shortcut_is_a_last_resort() {
+        // A service road straight from 1 to 2, and a footway detour to the north
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.371, &[])
+            .node(3, 30.341, 76.370, &[])
+            .node(4, 30.341, 76.371, &[])
+            .way(10, &[1, 2], &[("highway", "service")]);
+        let shortcut_only = load(&osm);
+        osm.way(11, &[1, 3, 4, 2], &[("highway", "footway")]);
+        let with_detour = load(&osm);
+
+        let options = RouteOptions { service_penalty: 4.0, ..Default::default() };
+        let via = |nav: &NavigationGraph| {
+            let (start, end) = (nav.osm_id_map[&1], nav.osm_id_map[&2]);
+            let (_, path) = nav.route(start, end, &options).unwrap();
+            path.iter().filter_map(|&n| nav.osm_id_of(n)).collect::<Vec<_>>()
+        };
+        assert_eq!(via(&with_detour), [1, 3, 4, 2]);
+        assert_eq!(via(&shortcut_only), [1, 2]);
+    }
+
+    #[test]
+    fn single_node_way_adds_no_edges() {
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.371, &[])
+            .way(10, &[1], &[("highway", "footway")])
+            .way(11, &[1, 2], &[("highway", "footway")]);
+        let nav = load(&osm);
+
+        assert_eq!(nav.graph.edge_weights().filter(|e| e.way_id == Some(10)).count(), 0);
+        assert_eq!(nav.graph.edge_weights().filter(|e| e.way_id == Some(11)).count(), 2);
+    }
+
+    #[test]
+    fn overlapping_ways_merge_restrictively() {
+        // A private footway drawn over a residential underpass closed at night
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.370, &[])
+            .node(2, 30.34, 76.371, &[])
+            .way(10, &[1, 2], &[("highway", "residential"), ("tunnel", "yes"), ("foot:conditional", "no @ (22:00-06:00)")])
+            .way(11, &[1, 2], &[("highway", "footway"), ("access", "private")]);
+        let nav = load(&osm);
+
+        assert_eq!(nav.graph.edge_count(), 2, "one edge per direction");
+        for edge in nav.graph.edge_weights() {
+            assert_eq!(edge.highway, HighwayKind::Footway, "the lower-risk way is kept");
+            assert!(edge.private && edge.is_tunnel);
+            assert_eq!(edge.foot_closed, Some(TimeWindow { start: 22 * 60, end: 6 * 60 }));
+        }
+    }
+
+    #[test]
+    fn alternatives_are_seeded() {
+        let nav = grid(6);
+        let (start, end) = (NodeIndex::new(0), NodeIndex::new(35));
+        let options = RouteOptions::default();
+        let routes = nav.alternative_routes(start, end, &options, 3, 42);
+
+        assert_eq!(routes.len(), 3);
+        assert_eq!(routes[0], nav.route(start, end, &options).unwrap().1, "the best route comes first");
+        assert!(routes.iter().all(|r| r.first() == Some(&start) && r.last() == Some(&end)));
+        assert!(routes[0] != routes[1] && routes[1] != routes[2] && routes[0] != routes[2]);
+        assert_eq!(nav.alternative_routes(start, end, &options, 3, 42), routes, "same seed, same routes");
+    }
+
+    #[test]
+    fn osm_id_maps_are_inverses() {
+        let mut osm = OsmFixture::default();
+        for id in 1..=4 {
+            osm.node(id * 10, 30.34, 76.37 + id as f64 * 0.001, &[]);
+        }
+        osm.way(100, &[10, 20, 30], &[("highway", "footway")])
+            .way(101, &[30, 40], &[("highway", "path")]);
+        let nav = load(&osm);
+
+        assert_eq!(nav.osm_id_map.len(), 4);
+        for (&id, &node) in &nav.osm_id_map {
+            assert_eq!(nav.osm_id_of(node), Some(id));
+        }
+        assert_eq!(nav.graph.node_indices().filter_map(|n| nav.osm_id_of(n)).count(), nav.osm_id_map.len());
+        assert_eq!(NavigationGraph::from_edges(&nodes(2), &[]).osm_id_of(NodeIndex::new(0)), None);
+    }
+
+    #[test]
+    fn precomputed_costs_match_edge_cost() {
+        let mut nav = grid(4);
+        nav.cache_alpha_costs(8);
+        for (i, edge) in nav.graph.edge_weights_mut().enumerate() {
+            edge.safety_score = 0.05 + (i % 5) as f32 * 0.2;
+            if i % 3 == 0 {
+                edge.highway = HighwayKind::Service;
+            }
+        }
+        let check = |nav: &NavigationGraph| {
+            for (alpha, service_penalty) in [(0.0, 0.0), (0.25, 0.0), (1.0, 4.0), (2.5, 4.0), (10.0, 1.5)] {
+                let options = RouteOptions { alpha, service_penalty, ..Default::default() };
+                let costs = nav.alpha_costs(&options).expect("plain options on the 0.01 grid");
+                for (edge, &cost) in nav.graph.edge_weights().zip(costs.iter()) {
+                    assert_eq!(cost, options.edge_cost(edge), "alpha {alpha}, service penalty {service_penalty}");
+                }
+            }
+        };
+        check(&nav);
+        assert!(nav.alpha_costs(&RouteOptions { alpha: 0.333, ..Default::default() }).is_none(), "off the grid");
+        assert!(nav.alpha_costs(&RouteOptions { night: true, ..Default::default() }).is_none());
+        assert!(nav.alpha_costs(&RouteOptions { alpha: 3.0, ..Default::default() }).is_some(), "over the limit, still costed");
+
+        // Rescoring edges drops the costs built from the old scores
+        let incidents = [Incident { lat: 30.3415, lon: 76.3715, severity: 1.0, timestamp: None }];
+        nav.add_incidents(&IncidentLayer::new(&incidents), &SafetyMap::new(SafetyConfig::default()).unwrap());
+        check(&nav);
+        check(&nav.clone());
+    }
+
+    #[test]
+    fn parallel_scores_match_sequential_ones() {
+        let mut nav = grid(12);
+        let incidents: Vec<Incident> = (0..20)
+            .map(|i| Incident { lat: 30.34 + i as f64 * 0.0005, lon: 76.37 + (i % 7) as f64 * 0.0013, severity: 0.5 + (i % 3) as f32 * 0.2, timestamp: None })
+            .collect();
+        let layer = IncidentLayer::new(&incidents);
+        let safety_map = SafetyMap::new(SafetyConfig { tag_weight: Some(0.6), ..SafetyConfig::default() }).unwrap();
+
+        let expected: Vec<f32> = nav.graph.edge_indices()
+            .map(|edge| safety_map.blend_risk(nav.graph[edge].safety_score, layer.risk_at(nav.edge_midpoint(edge))))
+            .collect();
+        nav.apply_location_risk(&[&layer], &safety_map);
+
+        let scores: Vec<f32> = nav.graph.edge_weights().map(|weight| weight.safety_score).collect();
+        assert_eq!(scores, expected);
+        assert!(scores.iter().any(|&score| score != scores[0]), "only some edges are near incidents");
+    }
 }
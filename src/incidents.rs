@@ -0,0 +1,129 @@
+//! Reported incidents (harassment, muggings, ...) raising the risk of nearby streets.
+//!
+//! Incidents come as CSV rows of `lat,lon,severity[,timestamp]`, with `severity` from 0
+//! (minor) to 1 (serious) and an optional Unix timestamp in seconds. Blank lines and a
+//! leading `lat,...` header are skipped.
+
+use std::path::Path;
+use serde::Serialize;
+use crate::safety::RiskSource;
+use crate::spatial::PointIndex;
+
+/// How far from an incident streets are affected
+pub const INCIDENT_RADIUS_METERS: f64 = 50.0;
+
+/// Risk added right at a severity-1 incident; fades linearly to 0 at the radius
+const INCIDENT_RISK: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Incident {
+    pub lat: f64,
+    pub lon: f64,
+    pub severity: f32,
+    pub timestamp: Option<i64>,
+}
+
+/// A CSV row that couldn't be parsed, by 1-based line number
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub line: usize,
+    pub error: String,
+}
+
+/// Parses incident CSV, keeping the rows that parse and reporting the ones that don't
+pub fn parse_csv(text: &str) -> (Vec<Incident>, Vec<RowError>) {
+    let mut incidents = Vec::new();
+    let mut errors = Vec::new();
+    for (i, row) in text.lines().enumerate() {
+        let row = row.trim();
+        if row.is_empty() || (i == 0 && row.starts_with("lat")) {
+            continue;
+        }
+        match parse_row(row) {
+            Ok(incident) => incidents.push(incident),
+            Err(e) => errors.push(RowError { line: i + 1, error: e.to_string() }),
+        }
+    }
+    (incidents, errors)
+}
+
+fn parse_row(row: &str) -> anyhow::Result<Incident> {
+    let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+    anyhow::ensure!(
+        (3..=4).contains(&fields.len()),
+        "expected `lat,lon,severity[,timestamp]`, got {} fields", fields.len()
+    );
+    let number = |name: &str, value: &str| value.parse::<f64>()
+        .map_err(|_| anyhow::anyhow!("{name} `{value}` is not a number"));
+
+    let (lat, lon, severity) = (number("lat", fields[0])?, number("lon", fields[1])?, number("severity", fields[2])?);
+    anyhow::ensure!((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon), "coordinates out of range");
+    anyhow::ensure!((0.0..=1.0).contains(&severity), "severity must be between 0 and 1");
+    let timestamp = match fields.get(3).filter(|t| !t.is_empty()) {
+        Some(t) => Some(t.parse().map_err(|_| anyhow::anyhow!("timestamp `{t}` is not Unix seconds"))?),
+        None => None,
+    };
+    Ok(Incident { lat, lon, severity: severity as f32, timestamp })
+}
+
+/// Incidents indexed for radius lookups
+pub struct IncidentLayer {
+    index: PointIndex<f32>,
+}
+
+impl IncidentLayer {
+    pub fn new(incidents: &[Incident]) -> Self {
+        Self { index: PointIndex::new(incidents.iter().map(|i| ([i.lat, i.lon], i.severity)).collect()) }
+    }
+
+    /// Loads a CSV file, failing on any malformed row
+    pub fn load_csv(path: impl AsRef<Path>) -> anyhow::Result<Vec<Incident>> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read incident file {}: {e}", path.display()))?;
+        let (incidents, errors) = parse_csv(&text);
+        if let Some(first) = errors.first() {
+            anyhow::bail!("{} line {}: {}", path.display(), first.line, first.error);
+        }
+        Ok(incidents)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Extra risk at `point` (`[lat, lon]`) from all incidents within `INCIDENT_RADIUS_METERS`
+    pub fn risk_near(&self, point: [f64; 2]) -> f32 {
+        self.index.within(point, INCIDENT_RADIUS_METERS)
+            .map(|(&severity, d)| severity * INCIDENT_RISK * (1.0 - (d / INCIDENT_RADIUS_METERS) as f32))
+            // Not `sum()`: an empty float sum is -0.0
+            .fold(0.0, |total, risk| total + risk)
+    }
+}
+
+impl RiskSource for IncidentLayer {
+    fn risk_at(&self, point: [f64; 2]) -> Option<f32> {
+        Some(self.risk_near(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_rows_are_reported_by_line() {
+        let csv = "lat,lon,severity,timestamp\n30.34,76.37,0.5\n30.35,76.38,1.5\n30.36,76.39,0.2,1700000000\n";
+        let (incidents, errors) = parse_csv(csv);
+
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[1].timestamp, Some(1_700_000_000));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+        assert!(errors[0].error.contains("severity"), "{}", errors[0].error);
+    }
+}
@@ -0,0 +1,138 @@
+//! ALT (A*, Landmarks, Triangle inequality) preprocessing.
+//!
+//! Walking distances from and to a few far-apart landmark nodes give, via the triangle
+//! inequality, lower bounds on the distance between any two nodes that are usually much
+//! tighter than the straight line. Every routing cost is at least the walked distance
+//! (see `RouteOptions::edge_cost`), so the bounds stay admissible for any request.
+
+use petgraph::algo::dijkstra;
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::{EdgeRef, Reversed};
+use crate::graph::{GeoNode, WalkEdge};
+
+#[derive(Clone)]
+pub struct Landmarks {
+    nodes: Vec<NodeIndex>,
+    /// Distance from each landmark to node `v`, at `v * count + i` (infinite if unreachable)
+    from: Vec<f64>,
+    /// Distance from node `v` to each landmark, same layout
+    to: Vec<f64>,
+}
+
+impl Landmarks {
+    /// Picks up to `count` landmarks by farthest-point selection within the component of
+    /// `seed`, then runs two Dijkstra searches per landmark over edge lengths
+    pub fn build(graph: &Graph<GeoNode, WalkEdge>, seed: NodeIndex, count: usize) -> Self {
+        let n = graph.node_count();
+        let mut landmarks = Self { nodes: Vec::new(), from: Vec::new(), to: Vec::new() };
+        if count == 0 || n == 0 {
+            return landmarks;
+        }
+
+        let distances_from = |start| dijkstra(graph, start, None, |e| e.weight().distance_meters);
+        let distances_to = |start| dijkstra(Reversed(graph), start, None, |e| e.weight().distance_meters);
+
+        // Distance of every node to its nearest landmark so far; the next landmark is the
+        // node farthest from all of them. The first one is simply the farthest from `seed`.
+        let mut nearest = vec![f64::INFINITY; n];
+        let Some(mut next) = distances_from(seed).into_iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|(v, _)| v) else {
+            return landmarks;
+        };
+
+        let mut from = vec![Vec::new(); count];
+        let mut to = vec![Vec::new(); count];
+        for i in 0..count {
+            let forward = distances_from(next);
+            let backward = distances_to(next);
+
+            let mut row_from = vec![f64::INFINITY; n];
+            let mut row_to = vec![f64::INFINITY; n];
+            for (v, d) in forward {
+                row_from[v.index()] = d;
+                nearest[v.index()] = nearest[v.index()].min(d);
+            }
+            for (v, d) in backward {
+                row_to[v.index()] = d;
+            }
+            landmarks.nodes.push(next);
+            from[i] = row_from;
+            to[i] = row_to;
+
+            // Farthest reachable node from the current set; stop once nothing is left to gain
+            match (0..n).filter(|&v| nearest[v].is_finite()).max_by(|&a, &b| nearest[a].total_cmp(&nearest[b])) {
+                Some(v) if nearest[v] > 0.0 => next = NodeIndex::new(v),
+                _ => break,
+            }
+        }
+
+        // Interleave per node so one lookup touches one contiguous slice
+        let k = landmarks.nodes.len();
+        landmarks.from = (0..n).flat_map(|v| from[..k].iter().map(move |row| row[v])).collect();
+        landmarks.to = (0..n).flat_map(|v| to[..k].iter().map(move |row| row[v])).collect();
+        landmarks
+    }
+
+    /// Number of landmarks selected
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Lower bound on the walking distance from `v` to `target`, 0 if none is known
+    pub fn lower_bound(&self, v: NodeIndex, target: NodeIndex) -> f64 {
+        let k = self.nodes.len();
+        let (v, t) = (v.index() * k, target.index() * k);
+
+        let mut best: f64 = 0.0;
+        for i in 0..k {
+            // d(L, t) <= d(L, v) + d(v, t)  and  d(v, L) <= d(v, t) + d(t, L)
+            let forward = self.from[t + i] - self.from[v + i];
+            let backward = self.to[v + i] - self.to[t + i];
+            for bound in [forward, backward] {
+                if bound.is_finite() {
+                    best = best.max(bound);
+                }
+            }
+        }
+        best
+    }
+
+    /// Heap usage of the distance tables
+    pub fn memory_estimate_bytes(&self) -> usize {
+        (self.from.capacity() + self.to.capacity()) * std::mem::size_of::<f64>()
+            + self.nodes.capacity() * std::mem::size_of::<NodeIndex>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use petgraph::graph::NodeIndex;
+    use crate::graph::{NavigationGraph, RouteOptions};
+    use crate::test_graphs::grid;
+
+    #[test]
+    fn alt_finds_the_same_cost_with_fewer_expansions() {
+        let mut nav = grid(20);
+        let (start, end) = (NodeIndex::new(20 * 3 + 1), NodeIndex::new(20 * 17 + 18));
+        let options = RouteOptions::default();
+        let search = |nav: &NavigationGraph| {
+            let evaluated = Cell::new(0);
+            let (cost, _) = nav.route_with_cost(start, end, &options, &|edge| {
+                evaluated.set(evaluated.get() + 1);
+                options.edge_cost(edge)
+            }).unwrap();
+            (cost, evaluated.get())
+        };
+
+        let (plain_cost, plain_work) = search(&nav);
+        nav.build_landmarks(4);
+        assert_eq!(nav.landmark_count(), 4);
+        let (alt_cost, alt_work) = search(&nav);
+        assert!((plain_cost - alt_cost).abs() < 1e-6, "{plain_cost} vs {alt_cost}");
+        assert!(alt_work < plain_work, "ALT costed {alt_work} edges, plain A* {plain_work}");
+    }
+}
@@ -0,0 +1,17 @@
+pub mod config;
+pub mod cost_expr;
+pub mod geo_util;
+pub mod graph;
+pub mod incidents;
+pub mod landmarks;
+pub mod polyline;
+pub mod raster;
+pub mod safety;
+pub mod spatial;
+pub mod tiles;
+pub mod zones;
+
+#[cfg(test)]
+mod osm_fixture;
+#[cfg(test)]
+mod test_graphs;
@@ -1,158 +1,3162 @@
-mod safety;
-mod graph;
-
-use axum::{routing::{get, post}, Router, Json, extract::State};
+use axum::{routing::{get, post}, Router, Json, body::Body, extract::{Query, Request, State}, http::{header, HeaderMap, StatusCode}, middleware::{self, Next}, response::{IntoResponse, Response}};
+use schemars::{JsonSchema, generate::SchemaSettings};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use petgraph::algo::astar;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use geo::{Coord, Intersects, Rect};
+use axum_server::tls_rustls::RustlsConfig;
+use lru::LruCache;
+use petgraph::graph::{EdgeIndex, NodeIndex};
 use tower_http::cors::CorsLayer;
-use crate::graph::NavigationGraph;
-use crate::safety::SafetyMap;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+use routing::config::{RegionConfig, ServerConfig, TlsConfig};
+use routing::cost_expr::CostExpression;
+use routing::geo_util::{bearing_degrees, haversine_meters};
+use routing::incidents::{self, Incident, IncidentLayer, RowError};
+use routing::graph::{Corridor, HighwayKind, LoadOptions, LoadPhase, LoadProgress, NavigationGraph, Profile, RouteOptions, Smoothness, SpeedTable, WalkEdge};
+use routing::polyline;
+use routing::raster::RasterRiskSource;
+use routing::tiles::{self, TileGeometry};
+use routing::safety::{RiskBreakdown, RiskSource, SafetyConfig, SafetyMap};
+use routing::zones::ZoneLayer;
+
+// How long a deep health check result is reused before probing again
+const DEEP_HEALTH_TTL: Duration = Duration::from_secs(30);
+
+// How long a region whose graph failed to build answers 503 before the next attempt
+const FAILED_BUILD_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// Response header telling whether a route came from the cache ("HIT") or was computed ("MISS")
+const CACHE_HEADER: &str = "x-cache";
+
+// Upper bound on destinations per /safest_of request (each one is a full route computation)
+const MAX_SAFEST_OF_CANDIDATES: usize = 50;
+
+// Largest graph /graph.geojson exports without a bbox
+const MAX_EXPORT_EDGES_WITHOUT_BBOX: usize = 20_000;
+
+// Search range and number of re-routes when /route/auto looks for the safest acceptable alpha
+const MAX_AUTO_ALPHA: f64 = 10.0;
+const AUTO_ALPHA_STEPS: usize = 10;
+
+// How far `avoid_dead_ends` may move a snap to get off a dead-end stub
+const DEAD_END_SNAP_RADIUS_METERS: f64 = 50.0;
+
+// Upper bound on sources and on targets per /matrix request
+const MAX_MATRIX_POINTS: usize = 25;
+
+// Upper bound on origins per /many_to_one request; they all share one search
+const MAX_MANY_TO_ONE_ORIGINS: usize = 1000;
+
+// Routes /routes returns when the request doesn't say, and the most it may ask for
+const DEFAULT_ALTERNATIVES: usize = 3;
+const MAX_ALTERNATIVES: usize = 5;
+
+// Alphas /pareto routes with; the frontier is drawn from these routes
+const PARETO_ALPHAS: [f64; 10] = [0.0, 0.25, 0.5, 1.0, 1.5, 2.0, 3.0, 5.0, 7.5, 10.0];
+
+// Turn-by-turn steps: a bearing change this sharp starts a new step even on the same street,
+// and edges shorter than the minimum (e.g. duplicate nodes) don't count towards bearings
+const TURN_THRESHOLD_DEGREES: f64 = 30.0;
+const MIN_BEARING_EDGE_METERS: f64 = 1.0;
+
+// Most samples one /risk/grid request may ask for
+const MAX_RISK_GRID_CELLS: usize = 10_000;
+
+// Times a /route corridor is doubled when no route fits inside it
+const CORRIDOR_RETRIES: i32 = 3;
+
+// Search radius of /address when none is given, and the largest one accepted
+const DEFAULT_ADDRESS_RADIUS_METERS: f64 = 50.0;
+const MAX_ADDRESS_RADIUS_METERS: f64 = 500.0;
+
+// Amenities /nearby returns when `k` is not given, and the most it returns
+const DEFAULT_NEARBY_COUNT: usize = 3;
+const MAX_NEARBY_COUNT: usize = 50;
+
+// Correlation id: taken from the incoming request if present, otherwise a fresh UUID
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 // Shared State for concurrency
 struct AppState {
-    nav_graph: NavigationGraph,
+    regions: RegionRegistry,
+    safety_map: SafetyMap,
+    config: ServerConfig,
+    deep_health: Mutex<Option<(Instant, DeepHealth)>>,
+    route_cache: Mutex<LruCache<String, RouteResponse>>,
+    graph_generation: AtomicUsize, // Bumped when graphs change; part of every route cache key
+    reloading: AtomicBool, // Guards against overlapping reloads and incident imports
+    incidents: RwLock<Vec<Incident>>, // Append-only; graphs record how many they include
+    incident_layer: RwLock<Arc<IncidentLayer>>, // Index over `incidents`, for point risk queries
+    risk_raster: Option<RasterRiskSource>, // SAFEWALK_RISK_RASTER_PATH, read once at startup
+}
+
+struct LoadedGraph {
+    nav: NavigationGraph,
+    etag: String,         // ETag for responses derived only from the graph
+    bounds: Option<Rect>, // Extent of the graph, for picking the region of a request
+    incidents_applied: usize, // Leading entries of `AppState::incidents` reflected in edge risk
+}
+
+impl LoadedGraph {
+    fn new(nav: NavigationGraph, incidents_applied: usize) -> Self {
+        let etag = graph_etag(&nav);
+        let bounds = nav.bounds();
+        Self { nav, etag, bounds, incidents_applied }
+    }
+}
+
+/// One area (e.g. a city) routed on its own graph, built on first use
+struct Region {
+    id: String,
+    pbf_path: String,
+    bbox: Option<Rect>,                      // Configured extent, to match requests before the graph is loaded
+    graph: RwLock<Option<Arc<LoadedGraph>>>, // Swapped out whole by /admin/reload
+    building: tokio::sync::Mutex<()>,        // Held while building, so concurrent first requests build once
+    builds: AtomicUsize,                     // Graphs built so far, including reloads
+    failed_build: Mutex<Option<(Instant, String)>>, // Last failed first-use build, retried after FAILED_BUILD_RETRY_AFTER
+}
+
+impl Region {
+    fn new(config: &RegionConfig) -> Self {
+        Self {
+            id: config.id.clone(),
+            pbf_path: config.pbf_path.clone(),
+            bbox: config.bbox.map(|[min_lon, min_lat, max_lon, max_lat]| {
+                Rect::new(Coord { x: min_lon, y: min_lat }, Coord { x: max_lon, y: max_lat })
+            }),
+            graph: RwLock::new(None),
+            building: tokio::sync::Mutex::new(()),
+            builds: AtomicUsize::new(0),
+            failed_build: Mutex::new(None),
+        }
+    }
+
+    /// The graph currently being served, if built yet; requests hold on to it even if a
+    /// reload swaps it out
+    fn loaded(&self) -> Option<Arc<LoadedGraph>> {
+        self.graph.read().unwrap().clone()
+    }
+
+    /// Swaps in a graph freshly built from the PBF
+    fn install(&self, nav: NavigationGraph, incidents_applied: usize) -> Arc<LoadedGraph> {
+        self.builds.fetch_add(1, Ordering::SeqCst);
+        *self.failed_build.lock().unwrap() = None;
+        self.replace(nav, incidents_applied)
+    }
+
+    /// Swaps in a graph, e.g. an updated copy of the current one
+    fn replace(&self, nav: NavigationGraph, incidents_applied: usize) -> Arc<LoadedGraph> {
+        let graph = Arc::new(LoadedGraph::new(nav, incidents_applied));
+        *self.graph.write().unwrap() = Some(graph.clone());
+        graph
+    }
+
+    /// Extent of the loaded graph, or the configured one before loading
+    fn bounds(&self) -> Option<Rect> {
+        self.loaded().and_then(|g| g.bounds).or(self.bbox)
+    }
+}
+
+/// The configured regions, in configuration order; the first one is the default
+struct RegionRegistry {
+    regions: Vec<Region>,
+}
+
+impl RegionRegistry {
+    /// The region named `id` (`400` if there is none); without a name, the first region
+    /// whose extent contains `point`, falling back to the default region. Regions that
+    /// aren't loaded yet are only matched by their configured bbox.
+    fn select(&self, id: Option<&str>, point: Option<[f64; 2]>) -> Result<&Region, SafewalkError> {
+        if let Some(id) = id {
+            return self.regions.iter()
+                .find(|r| r.id == id)
+                .ok_or_else(|| SafewalkError::InvalidInput(format!("unknown region `{id}`")));
+        }
+        let inside = |region: &&Region| {
+            let (Some(point), Some(bounds)) = (point, region.bounds()) else { return false };
+            bounds.intersects(&Coord { x: point[1], y: point[0] })
+        };
+        Ok(self.regions.iter().find(inside).unwrap_or(&self.regions[0]))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter()
+    }
+}
+
+impl AppState {
+    /// Graph of the region `RegionRegistry::select` picks, building it on first use
+    async fn graph_for(self: &Arc<Self>, id: Option<&str>, point: Option<[f64; 2]>) -> Result<Arc<LoadedGraph>, SafewalkError> {
+        let region = self.regions.select(id, point)?;
+        if let Some(graph) = region.loaded() {
+            return Ok(graph);
+        }
+
+        let _building = region.building.lock().await;
+        // Another request may have finished the build while we waited
+        if let Some(graph) = region.loaded() {
+            return Ok(graph);
+        }
+        // A broken extract fails the same way every time; don't rebuild it for every request
+        if let Some((_, error)) = region.failed_build.lock().unwrap().as_ref().filter(|(at, _)| at.elapsed() < FAILED_BUILD_RETRY_AFTER) {
+            return Err(SafewalkError::GraphNotLoaded(error.clone()));
+        }
+        tracing::info!(region = %region.id, "loading region on first use");
+        let (worker, pbf_path) = (self.clone(), region.pbf_path.clone());
+        let incidents = self.incidents.read().unwrap().clone();
+        let applied = incidents.len();
+        let loaded = tokio::task::spawn_blocking(move || worker.load_graph(&pbf_path, &incidents))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+        match loaded {
+            Ok(nav) => Ok(region.install(nav, applied)),
+            Err(e) => {
+                let error = format!("region `{}` failed to load: {e}", region.id);
+                *region.failed_build.lock().unwrap() = Some((Instant::now(), error.clone()));
+                Err(SafewalkError::GraphNotLoaded(error))
+            }
+        }
+    }
+
+    fn load_graph(&self, pbf_path: &str, incidents: &[Incident]) -> anyhow::Result<NavigationGraph> {
+        load_graph(&self.config, &self.safety_map, pbf_path, incidents, self.risk_raster.as_ref())
+    }
+
+    /// Route cache key of `request` on the current graphs
+    fn route_key(&self, request: &RouteRequest) -> String {
+        cache_key(request, self.graph_generation.load(Ordering::SeqCst))
+    }
+
+    /// Drops the cached routes once graphs were swapped. Routes still being computed on the
+    /// old graphs are cached under the old generation, where no request looks them up.
+    fn invalidate_routes(&self) {
+        self.graph_generation.fetch_add(1, Ordering::SeqCst);
+        self.route_cache.lock().unwrap().clear();
+    }
+
+    /// Location-based risk at `point` (`[lat, lon]`), as blended into the scores of edges there
+    fn location_risk(&self, point: [f64; 2]) -> PointRiskResponse {
+        let incidents = self.incident_layer.read().unwrap().risk_near(point);
+        let raster = self.risk_raster.as_ref().and_then(|raster| raster.risk_at(point));
+        PointRiskResponse { risk: incidents + raster.unwrap_or(0.0), incidents, raster }
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Log level via RUST_LOG, e.g. RUST_LOG=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    let config = ServerConfig::from_env()?;
+    if let Some(threads) = config.recompute_threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    }
+    // Check the certificate before spending time on the graph
+    let tls = match &config.tls {
+        Some(tls) => Some(load_tls(tls).await?),
+        None => None,
+    };
+
     // 1. Initialize Safety Data
-    let safety_map = SafetyMap::new();
+    let safety_map = SafetyMap::new(SafetyConfig::from_env()?)?;
+
+    // 2. Load OSM Data for the default region; the others are loaded on first use
+    let shared_state = Arc::new(build_state(config, safety_map)?);
+
+    let app = app(shared_state);
+
+    match tls {
+        Some(tls) => {
+            println!("🚀 API Server running on https://0.0.0.0:3000");
+            axum_server::bind_rustls(SocketAddr::from(([0, 0, 0, 0], 3000)), tls)
+                .serve(app.into_make_service())
+                .await?;
+        }
+        None => {
+            println!("🚀 API Server running on http://0.0.0.0:3000");
+            let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+            axum::serve(listener, app).await?;
+        }
+    }
 
-    // 2. Load OSM Data
-    // Ensure "assets/patiala.osm.pbf" exists!
-    let pbf_path = "assets/patiala.osm.pbf"; 
-    let nav_graph = NavigationGraph::from_pbf(pbf_path, &safety_map)
-        .expect("Failed to load PBF file. Check if assets/patiala.osm.pbf exists.");
+    Ok(())
+}
 
-    let shared_state = Arc::new(AppState { nav_graph });
+/// Reads the certificate chain and key of `tls`, naming both files if either can't be used
+async fn load_tls(tls: &TlsConfig) -> anyhow::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path).await
+        .map_err(|e| anyhow::anyhow!("cannot load TLS certificate {} with key {}: {e}", tls.cert_path, tls.key_path))
+}
 
-    // 3. Setup CORS (Allows your local HTML file to talk to this API)
+/// Every endpoint, behind CORS, request ids, tracing and the slow-request warning
+fn app(state: Arc<AppState>) -> Router {
+    // Allows your local HTML file to talk to this API
     let cors = CorsLayer::new()
         .allow_methods(tower_http::cors::Any)
         .allow_origin(tower_http::cors::Any)
         .allow_headers(tower_http::cors::Any);
 
-    // 4. Setup Router
-    let app = Router::new()
+    Router::new()
         .route("/health", get(|| async { "OK" }))
-        .route("/route", post(calculate_route))
+        .route("/health/deep", get(deep_health))
+        .route("/selftest", get(self_test))
+        .route("/version", get(version))
+        .route("/openapi.json", get(openapi))
+        .route("/route", post(calculate_route).get(calculate_route_get))
+        .route("/route/auto", post(calculate_auto_route))
+        .route("/routes", post(calculate_alternatives))
+        .route("/pareto", post(pareto_routes))
+        .route("/route/debug", post(debug_route))
+        .route("/warmup", post(warmup_routes))
+        .route("/safest_of", post(safest_of))
+        .route("/matrix", post(route_matrix))
+        .route("/many_to_one", post(many_to_one))
+        .route("/validate", post(validate_points))
+        .route("/stats", get(graph_stats))
+        .route("/component", get(component_of_point))
+        .route("/search", get(search_streets))
+        .route("/address", get(nearby_addresses))
+        .route("/nearby", get(nearby_amenities))
+        .route("/edge/explain", get(explain_edge))
+        .route("/risk", get(point_risk))
+        .route("/risk/grid", post(risk_grid))
+        .route("/graph.geojson", get(export_graph))
+        .route("/regions", get(list_regions))
+        .route("/admin/reload", post(reload_graph))
+        .route("/incidents/import", post(import_incidents))
         .layer(cors)
-        .with_state(shared_state);
+        .layer(middleware::from_fn_with_state(state.config.slow_request_threshold, log_slow_requests))
+        // Layers run outermost-last: assign the id, then open the request span, then echo the id
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(|request: &Request<Body>| {
+            let request_id = request.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()).unwrap_or("-");
+            tracing::info_span!(
+                "request",
+                method = %request.method(),
+                uri = %request.uri(),
+                request_id = %request_id,
+                origin = tracing::field::Empty,
+                destination = tracing::field::Empty,
+                alpha = tracing::field::Empty,
+            )
+        }).on_response(DefaultOnResponse::new().level(Level::INFO)))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state)
+}
+
+/// Loads the default region (the others wait for their first request) along with the
+/// incidents, raster and caches all requests share
+fn build_state(config: ServerConfig, safety_map: SafetyMap) -> anyhow::Result<AppState> {
+    let incidents = match &config.incidents_path {
+        Some(path) => IncidentLayer::load_csv(path)?,
+        None => Vec::new(),
+    };
+    let risk = safety_map.config();
+    let risk_raster = config.risk_raster_path.as_ref()
+        .map(|path| RasterRiskSource::load(path, risk.min_risk, risk.max_risk))
+        .transpose()?;
+    if let (Some(raster), Some(path)) = (&risk_raster, &config.risk_raster_path) {
+        println!("Loaded {}x{} risk raster from {}", raster.width(), raster.height(), path);
+    }
+    let regions: Vec<Region> = config.regions.iter().map(Region::new).collect();
+    println!("Loading region `{}`", regions[0].id);
+    let nav = load_graph(&config, &safety_map, &regions[0].pbf_path, &incidents, risk_raster.as_ref())?;
+    regions[0].install(nav, incidents.len());
 
-    println!("🚀 API Server running on http://0.0.0.0:3000");
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    axum::serve(listener, app).await?;
+    let route_cache = Mutex::new(LruCache::new(config.route_cache_size));
+    Ok(AppState {
+        regions: RegionRegistry { regions },
+        safety_map,
+        config,
+        deep_health: Mutex::new(None),
+        route_cache,
+        graph_generation: AtomicUsize::new(0),
+        reloading: AtomicBool::new(false),
+        incident_layer: RwLock::new(Arc::new(IncidentLayer::new(&incidents))),
+        incidents: RwLock::new(incidents),
+        risk_raster,
+    })
+}
 
-    Ok(())
+/// Builds a navigation graph from `pbf_path`, with zones, `incidents` and landmarks as
+/// configured. Used at startup, on first use of a region and by `/admin/reload`.
+fn load_graph(
+    config: &ServerConfig,
+    safety_map: &SafetyMap,
+    pbf_path: &str,
+    incidents: &[Incident],
+    risk_raster: Option<&RasterRiskSource>,
+) -> anyhow::Result<NavigationGraph> {
+    let mut last_logged = (LoadPhase::Nodes, 0);
+    let mut log_progress = |p: LoadProgress| {
+        // One line per 10% step of each pass
+        let step = (p.percent / 10.0) as u32;
+        if (p.phase, step) != last_logged {
+            last_logged = (p.phase, step);
+            println!("  {:?}: {:.0}% ({} elements)", p.phase, p.percent, p.processed);
+        }
+    };
+    let load_options = LoadOptions {
+        max_nodes: config.max_nodes,
+        walkable: config.walkable.clone(),
+        min_edge_length_meters: config.min_edge_length_meters,
+        turn_restrictions: config.turn_restrictions,
+    };
+    let mut nav_graph = NavigationGraph::from_pbf_with_options(pbf_path, safety_map, &load_options, &mut log_progress)
+        .map_err(|e| anyhow::anyhow!("failed to load {pbf_path}: {e}"))?;
+
+    if let Some(path) = &config.zones_path {
+        let zones = ZoneLayer::load(path)?;
+        nav_graph.apply_zones(&zones, safety_map);
+        println!("Applied {} zones from {}", zones.zones().len(), path);
+    }
+
+    // Even without any: a tag weight below 1 blends in (zero) location risk
+    let incident_layer = IncidentLayer::new(incidents);
+    let mut sources: Vec<&dyn RiskSource> = vec![&incident_layer];
+    sources.extend(risk_raster.map(|raster| raster as &dyn RiskSource));
+    nav_graph.apply_location_risk(&sources, safety_map);
+    if !incidents.is_empty() {
+        println!("Applied {} incidents", incidents.len());
+    }
+
+    if config.landmarks > 0 {
+        nav_graph.build_landmarks(config.landmarks);
+        println!("Precomputed {} ALT landmarks", nav_graph.landmark_count());
+    }
+    nav_graph.cache_alpha_costs(config.cached_alphas);
+    Ok(nav_graph)
 }
 
 // --- API DTOs ---
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Default, Clone, JsonSchema)]
 struct RouteRequest {
     origin: [f64; 2],      // [lat, lon]
     destination: [f64; 2], // [lat, lon]
-    alpha: f64,            // Safety preference (0.0 = fast, 5.0 = safe)
+    alpha: Option<f64>,    // Safety preference (0.0 = fast, 5.0 = safe); SAFEWALK_DEFAULT_ALPHA if omitted
+    #[serde(default)]
+    night: bool,           // After dark: steer clear of tunnels, underpasses & ways closed at night
+    heading_degrees: Option<f64>, // Current direction of travel (0 = north, clockwise)
+    #[serde(default)]
+    minimize_crossings: bool, // Accept a longer walk to cross fewer roads
+    #[serde(default)]
+    prefer_shade: f64,     // Weight for tree-shaded streets (0.0 = ignore, 1.0 = strong)
+    #[serde(default)]
+    openness: f64,         // Weight against alleys hemmed in by buildings (0.0 = ignore)
+    #[serde(default)]
+    prefer_busy: f64,      // Weight for footways near shops & transit stops (0.0 = ignore, 1.0 = strong)
+    service_penalty: Option<f64>, // Extra cost factor for service roads & private ways (0.0 = none); SAFEWALK_SERVICE_PENALTY if omitted
+    corridor_width_meters: Option<f64>, // Only use edges this close to the straight line of each leg
+    heuristic_weight: Option<f64>, // Weighted A* (>= 1.0): faster, possibly slightly worse routes (default 1.0)
+    #[serde(default)]
+    avoid_flood: bool,     // Never enter flood zones (needs SAFEWALK_ZONES_PATH)
+    #[serde(default)]
+    avoid_unpaved: bool,   // Never use dirt/gravel/... surfaces (strollers)
+    min_smoothness: Option<Smoothness>, // Skip ways tagged rougher than this (e.g. "good")
+    #[serde(default)]
+    profile: Profile,      // "foot" (default), "wheelchair" or "wheelchair_strict"
+    #[serde(default)]
+    walking_speeds_kmh: BTreeMap<HighwayKind, f64>, // ETA speed overrides, e.g. {"steps": 1.5}
+    waypoints_polyline: Option<String>, // Via points as an encoded polyline (precision 5)
+    cost_expression: Option<String>, // Custom edge cost, e.g. "distance * (1 + alpha*safety)"
+    #[serde(default)]
+    avoid_dead_ends: bool, // Snap past dead-end stubs to a nearby through node
+    quantize: Option<QuantizeOptions>, // Also return the geometry on a vector-tile grid
+    #[serde(default)]
+    return_route: bool,    // Also route back from destination to origin (round trips)
+    region: Option<String>, // Graph to route on; inferred from the origin if omitted
+    geometry: Option<bool>, // false: totals only, with empty coordinates (default true)
+    max_extra_meters: Option<f64>, // Longest acceptable detour over the alpha = 0 route
+    #[serde(default)]
+    geometry_format: GeometryFormat, // "geojson" (default) or "wkt"
+    #[serde(default)]
+    steps: bool,           // Also return turn-by-turn steps
+    language: Option<String>, // Language of step instructions: "en" (default) or "hi"
+    #[serde(default)]
+    elevation_profile: bool, // Also return (distance, elevation) samples for a chart
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum GeometryFormat {
+    #[default]
+    GeoJson,
+    Wkt,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, JsonSchema)]
+struct QuantizeOptions {
+    #[serde(default = "default_tile_extent")]
+    extent: NonZeroU32,    // Grid cells per side
+}
+
+fn default_tile_extent() -> NonZeroU32 {
+    NonZeroU32::new(tiles::DEFAULT_EXTENT).expect("default extent is positive")
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct AlternativesRequest {
+    #[serde(flatten)]
+    route: RouteRequest,
+    #[serde(default = "default_alternatives")]
+    alternatives: usize,   // How many distinct routes to return, at most
+    seed: Option<u64>,     // Same seed, same routes; random if omitted
+}
+
+fn default_alternatives() -> usize {
+    DEFAULT_ALTERNATIVES
+}
+
+#[derive(Serialize, JsonSchema)]
+struct AlternativesResponse {
+    seed: u64,             // Send it back to get the same routes again
+    routes: Vec<RouteResponse>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ParetoRoute {
+    alpha: f64,            // Lowest swept alpha that produced this route
+    total_distance: f64,   // Meters
+    total_risk: f64,       // Sum of distance × safety_score over the route's edges
+    geometry: RouteGeometry,
+}
+
+#[derive(Deserialize)]
+struct RouteQuery {
+    from: String,          // "lat,lon"
+    to: String,            // "lat,lon"
+    alpha: Option<f64>,
+    region: Option<String>,
+}
+
+#[derive(Serialize, Clone, JsonSchema)]
 struct RouteResponse {
-    geometry: GeoJsonLineString,
+    geometry: RouteGeometry,
     total_distance: f64,
+    straight_line_distance: f64, // Meters as the crow flies from origin to destination, at most total_distance
+    detour_meters: f64,          // total_distance - straight_line_distance
     average_safety: f32,
+    total_risk: f64,             // Sum of distance × safety_score over the edges: exposure, grows with length
+    eta_seconds: f64,
+    crossings: usize,            // Road crossings (`highway=crossing` nodes) stepped onto along the way
+    streets: Vec<String>,        // Named streets along the route in order, without repeats in a row
+    waypoint_indices: Vec<usize>, // Position of each via point and the destination in the coordinates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tile_geometry: Option<TileGeometry>, // Only if the request asked to `quantize`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<Vec<RouteStep>>, // Only if the request asked for `steps`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elevation_profile: Option<Vec<(f64, f32)>>, // (meters from the start, meters above sea level) per node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    return_route: Option<Box<RouteResponse>>, // Way back, only if the request asked for it and there is one
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    fell_back_to_fastest: bool, // The safer route exceeded `max_extra_meters`
+    applied: AppliedOptions,     // Routing parameters in effect, after defaults and clamping
+}
+
+/// One instruction: a stretch along the same street without sharp bends
+#[derive(Serialize, Clone, JsonSchema)]
+struct RouteStep {
+    maneuver: Maneuver,    // How the step is entered
+    street: Option<String>, // None for unnamed paths
+    instruction: String,   // Maneuver and street as a sentence, in the request's `language`
+    distance_meters: f64,
+    location: [f64; 2],    // [lat, lon] where the step starts
+}
+
+#[derive(Serialize, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Maneuver {
+    Depart,
+    Continue,              // Onto a differently named street, no real turn
+    SlightLeft,
+    SlightRight,
+    Left,
+    Right,
+    SharpLeft,
+    SharpRight,
+    UTurn,
+}
+
+impl Maneuver {
+    /// From the signed change of bearing in degrees, clockwise (right) positive
+    fn from_turn(turn: f64) -> Self {
+        let right = turn > 0.0;
+        match turn.abs() {
+            a if a < TURN_THRESHOLD_DEGREES => Maneuver::Continue,
+            a if a < 60.0 => if right { Maneuver::SlightRight } else { Maneuver::SlightLeft },
+            a if a < 135.0 => if right { Maneuver::Right } else { Maneuver::Left },
+            a if a < 170.0 => if right { Maneuver::SharpRight } else { Maneuver::SharpLeft },
+            _ => Maneuver::UTurn,
+        }
+    }
+}
+
+/// Languages step instructions are written in
+#[derive(Clone, Copy)]
+enum Language {
+    English,
+    Hindi,
+}
+
+impl Language {
+    /// From an ISO 639-1 code, optionally with a region (`hi-IN`); English for anything unknown
+    fn from_code(code: &str) -> Self {
+        let primary = code.split(['-', '_']).next().unwrap_or_default();
+        if primary.eq_ignore_ascii_case("hi") { Language::Hindi } else { Language::English }
+    }
+
+    /// The instruction for entering a step with `maneuver`, naming the street if it has one
+    fn instruction(self, maneuver: Maneuver, street: Option<&str>) -> String {
+        // (onto a named street with `{}` for its name, onto an unnamed path)
+        let (named, unnamed) = match (self, maneuver) {
+            (Language::English, Maneuver::Depart) => ("Head out on {}", "Head out"),
+            (Language::English, Maneuver::Continue) => ("Continue onto {}", "Continue"),
+            (Language::English, Maneuver::SlightLeft) => ("Bear left onto {}", "Bear left"),
+            (Language::English, Maneuver::SlightRight) => ("Bear right onto {}", "Bear right"),
+            (Language::English, Maneuver::Left) => ("Turn left onto {}", "Turn left"),
+            (Language::English, Maneuver::Right) => ("Turn right onto {}", "Turn right"),
+            (Language::English, Maneuver::SharpLeft) => ("Turn sharp left onto {}", "Turn sharp left"),
+            (Language::English, Maneuver::SharpRight) => ("Turn sharp right onto {}", "Turn sharp right"),
+            (Language::English, Maneuver::UTurn) => ("Make a U-turn onto {}", "Make a U-turn"),
+            (Language::Hindi, Maneuver::Depart) => ("{} पर चलना शुरू करें", "चलना शुरू करें"),
+            (Language::Hindi, Maneuver::Continue) => ("{} पर आगे बढ़ें", "सीधे आगे बढ़ें"),
+            (Language::Hindi, Maneuver::SlightLeft) => ("{} पर हल्का बाएँ मुड़ें", "हल्का बाएँ मुड़ें"),
+            (Language::Hindi, Maneuver::SlightRight) => ("{} पर हल्का दाएँ मुड़ें", "हल्का दाएँ मुड़ें"),
+            (Language::Hindi, Maneuver::Left) => ("{} पर बाएँ मुड़ें", "बाएँ मुड़ें"),
+            (Language::Hindi, Maneuver::Right) => ("{} पर दाएँ मुड़ें", "दाएँ मुड़ें"),
+            (Language::Hindi, Maneuver::SharpLeft) => ("{} पर तेज़ बाएँ मुड़ें", "तेज़ बाएँ मुड़ें"),
+            (Language::Hindi, Maneuver::SharpRight) => ("{} पर तेज़ दाएँ मुड़ें", "तेज़ दाएँ मुड़ें"),
+            (Language::Hindi, Maneuver::UTurn) => ("{} पर यू-टर्न लें", "यू-टर्न लें"),
+        };
+        match street {
+            Some(street) => named.replace("{}", street),
+            None => unnamed.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, JsonSchema)]
+struct AppliedOptions {
+    #[serde(flatten)]
+    options: RouteOptions,
+    max_incline_percent: Option<f32>, // Implied by `profile`
+    custom_cost: bool,     // A `cost_expression` replaced the built-in cost formula
+}
+
+/// Why a route request failed; handlers return it directly and it answers with the matching
+/// status code and an `ErrorResponse` body
+enum SafewalkError {
+    /// Both points are on the same part of the network, but no path satisfies the request's options
+    NoRoute { origin: SnappedPoint, destination: SnappedPoint },
+    /// A point is farther than `SAFEWALK_MAX_SNAP_METERS` from any node (or the graph is empty)
+    SnapFailed { origin: SnappedPoint, destination: SnappedPoint },
+    /// The points snapped to unconnected parts of the network
+    DisconnectedEndpoints { origin: SnappedPoint, destination: SnappedPoint },
+    InvalidInput(String),
+    /// The region's graph couldn't be built
+    GraphNotLoaded(String),
+    /// The search itself failed (e.g. a blocking task panicked)
+    Internal(String),
+}
+
+impl SafewalkError {
+    fn status(&self) -> StatusCode {
+        match self {
+            // All "can't connect these points" flavours stay `404`, as before the split
+            SafewalkError::NoRoute { .. } | SafewalkError::SnapFailed { .. } | SafewalkError::DisconnectedEndpoints { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            SafewalkError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            SafewalkError::GraphNotLoaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            SafewalkError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            SafewalkError::NoRoute { .. } => "no_route",
+            SafewalkError::SnapFailed { .. } => "snap_failed",
+            SafewalkError::DisconnectedEndpoints { .. } => "disconnected_endpoints",
+            SafewalkError::InvalidInput(_) => "invalid_input",
+            SafewalkError::GraphNotLoaded(_) => "graph_not_loaded",
+            SafewalkError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl std::fmt::Display for SafewalkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SafewalkError::NoRoute { .. } => f.write_str("no route between origin and destination"),
+            SafewalkError::SnapFailed { .. } => f.write_str("origin or destination is too far from the road network"),
+            SafewalkError::DisconnectedEndpoints { .. } => f.write_str("origin and destination are on unconnected parts of the road network"),
+            SafewalkError::InvalidInput(message) | SafewalkError::GraphNotLoaded(message) | SafewalkError::Internal(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl IntoResponse for SafewalkError {
+    fn into_response(self) -> Response {
+        let (status, error, code) = (self.status(), self.to_string(), self.code());
+        let (origin, destination) = match self {
+            SafewalkError::NoRoute { origin, destination }
+            | SafewalkError::SnapFailed { origin, destination }
+            | SafewalkError::DisconnectedEndpoints { origin, destination } => (Some(origin), Some(destination)),
+            SafewalkError::InvalidInput(_) | SafewalkError::GraphNotLoaded(_) | SafewalkError::Internal(_) => (None, None),
+        };
+        (status, Json(ErrorResponse { error, code, origin, destination })).into_response()
+    }
+}
+
+/// For handlers that still answer errors in plain text
+impl From<SafewalkError> for (StatusCode, String) {
+    fn from(error: SafewalkError) -> Self {
+        (error.status(), error.to_string())
+    }
+}
+
+/// Error body. When two points can't be connected it says where each snapped, to tell "too
+/// far from any road" apart from "on unconnected parts of the network"
+#[derive(Serialize, JsonSchema)]
+struct ErrorResponse {
+    error: String,
+    code: &'static str,    // SafewalkError variant, e.g. "snap_failed"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<SnappedPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination: Option<SnappedPoint>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SnappedPoint {
+    node: Option<usize>,   // Nearest graph node; None if the graph is empty
+    component: Option<usize>, // Points in different components can't reach each other
+    beyond_max_snap: bool, // Farther than SAFEWALK_MAX_SNAP_METERS from that node
+}
+
+#[derive(Deserialize)]
+struct AutoRouteRequest {
+    origin: [f64; 2],      // [lat, lon]
+    destination: [f64; 2], // [lat, lon]
+    max_detour_ratio: f64, // Longest acceptable route relative to the shortest (1.2 = 20% longer)
+    corridor_width_meters: Option<f64>, // As for /route: only use edges this close to the straight line
+    region: Option<String>, // Inferred from the origin if omitted
+}
+
+#[derive(Serialize)]
+struct AutoRouteResponse {
+    alpha: f64,            // Safety preference that was settled on
+    detour_ratio: f64,     // Route length relative to the shortest route
+    route: RouteResponse,
+}
+
+#[derive(Deserialize)]
+struct SafestOfRequest {
+    origin: [f64; 2],      // [lat, lon]
+    candidates: Vec<[f64; 2]>, // [lat, lon] each
+    alpha: Option<f64>,    // SAFEWALK_DEFAULT_ALPHA if omitted
+}
+
+#[derive(Serialize)]
+struct SafestOfResponse {
+    index: usize,          // Position of the winner in `candidates`
+    destination: [f64; 2],
+    route: RouteResponse,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct MatrixRequest {
+    sources: Vec<[f64; 2]>, // [lat, lon] each
+    targets: Vec<[f64; 2]>, // [lat, lon] each
+    alpha: Option<f64>,     // SAFEWALK_DEFAULT_ALPHA if omitted
+    #[serde(default)]
+    include_geometry: bool, // Also return every path (large: sources x targets line strings)
+}
+
+#[derive(Serialize, JsonSchema)]
+struct MatrixResponse {
+    distances: Vec<Vec<Option<f64>>>, // Meters walked, [source][target]; null if unreachable or off the graph
+    #[serde(skip_serializing_if = "Option::is_none")]
+    geometries: Option<Vec<Vec<Option<GeoJsonLineString>>>>, // Same layout
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ManyToOneRequest {
+    origins: Vec<[f64; 2]>, // [lat, lon] each
+    destination: [f64; 2], // [lat, lon]
+    alpha: Option<f64>,    // SAFEWALK_DEFAULT_ALPHA if omitted
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ManyToOneResponse {
+    costs: Vec<Option<f64>>, // Route cost from each origin, as /route minimizes it; null if unreachable
+    distances: Vec<Option<f64>>, // Meters walked along that route
 }
 
 #[derive(Serialize)]
+struct WarmupResponse {
+    requested: usize,
+    computed: usize,       // Routes that were not cached yet and had to be computed
+    cached: usize,         // Requests now served from the cache (unroutable ones are not cached)
+}
+
+#[derive(Serialize, Clone, JsonSchema)]
 struct GeoJsonLineString {
     r#type: String,
     coordinates: Vec<[f64; 2]>, // [lon, lat] standard for GeoJSON
 }
 
-// --- Handler ---
+/// Route geometry in the request's `geometry_format`
+#[derive(Serialize, Clone, JsonSchema)]
+#[serde(untagged)]
+enum RouteGeometry {
+    GeoJson(GeoJsonLineString),
+    Wkt(String),           // "LINESTRING(lon lat, ...)", same axis order as GeoJSON
+}
 
-async fn calculate_route(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<RouteRequest>,
-) -> Json<RouteResponse> {
-    let g = &state.nav_graph.graph;
+#[derive(Deserialize)]
+struct PointQuery {
+    lat: f64,
+    lon: f64,
+}
 
-    // 1. Snap input coordinates to nearest Graph Nodes
-    let start_node = state.nav_graph.find_nearest_node(payload.origin[0], payload.origin[1]);
-    let end_node = state.nav_graph.find_nearest_node(payload.destination[0], payload.destination[1]);
-
-    if start_node.is_none() || end_node.is_none() {
-        return Json(RouteResponse {
-            geometry: GeoJsonLineString { r#type: "LineString".to_string(), coordinates: vec![] },
-            total_distance: 0.0,
-            average_safety: 0.0,
-        });
-    }
+#[derive(Serialize)]
+struct PointRiskResponse {
+    risk: f32,             // Sum of the parts below
+    incidents: f32,        // From reported incidents nearby
+    raster: Option<f32>,   // From SAFEWALK_RISK_RASTER_PATH, if configured and covering the point
+}
 
-    let start_node = start_node.unwrap();
-    let end_node = end_node.unwrap();
+#[derive(Deserialize)]
+struct RiskGridRequest {
+    bbox: [f64; 4],        // [min_lon, min_lat, max_lon, max_lat]
+    resolution_meters: f64, // Approximate cell size
+}
 
-    // 2. Calculate Route (Weighted A*)
-    let path_result = astar(
-        g,
-        start_node,
-        |finish| finish == end_node,
-        |e| {
-            let edge = e.weight();
-            // COST FUNCTION: Distance * (1 + alpha * SafetyScore)
-            // If alpha is high, dangerous edges become very "expensive"
-            edge.distance_meters * (1.0 + payload.alpha * edge.safety_score as f64)
-        },
-        |n| {
-            // Heuristic: Euclidean distance
-            let node = g[n];
-            let dest = g[end_node];
-            let d_lat = node.lat - dest.lat;
-            let d_lon = node.lon - dest.lon;
-            (d_lat * d_lat + d_lon * d_lon).sqrt() * 111_000.0 
-        },
-    );
+#[derive(Serialize)]
+struct RiskGridResponse {
+    rows: usize,
+    cols: usize,
+    risk: Vec<Vec<f32>>,   // [row][col], row 0 along the north edge; sampled at cell centres
+}
 
-    // 3. Format Response
-    match path_result {
-        Some((_cost, nodes)) => {
-            let mut coordinates = Vec::new();
-            let mut real_distance = 0.0;
-            let mut total_safety_score = 0.0;
-            let mut edge_count = 0;
-
-            // Reconstruct path geometry and stats
-            for (i, &node_idx) in nodes.iter().enumerate() {
-                let node_data = g[node_idx];
-                coordinates.push([node_data.lon, node_data.lat]); // GeoJSON is [Lon, Lat]
-
-                // Calculate distance and safety from edges
-                if i > 0 {
-                    let prev_idx = nodes[i-1];
-                    if let Some(edge) = g.find_edge(prev_idx, node_idx) {
-                        let weight = g[edge];
-                        real_distance += weight.distance_meters;
-                        total_safety_score += weight.safety_score;
-                        edge_count += 1;
-                    }
-                }
-            }
-            
-            let avg_safety = if edge_count > 0 { total_safety_score / edge_count as f32 } else { 0.0 };
+#[derive(Serialize)]
+struct EdgeExplainResponse {
+    geometry: GeoJsonLineString,
+    distance_meters: f64,
+    safety_score: f32,     // Final score used for routing (incl. crossing adjustment)
+    breakdown: RiskBreakdown,
+    way_id: Option<i64>,   // Source OSM way, e.g. to open it in an editor
+}
 
-            Json(RouteResponse {
-                geometry: GeoJsonLineString {
-                    r#type: "LineString".to_string(),
-                    coordinates,
-                },
-                total_distance: real_distance,
-                average_safety: avg_safety,
-            })
-        }
-        None => Json(RouteResponse {
-            geometry: GeoJsonLineString { r#type: "LineString".to_string(), coordinates: vec![] },
-            total_distance: 0.0,
-            average_safety: 0.0,
-        })
+#[derive(Deserialize)]
+struct ValidateRequest {
+    origin: [f64; 2],      // [lat, lon]
+    destination: [f64; 2], // [lat, lon]
+}
+
+#[derive(Serialize)]
+struct SnapCheck {
+    snap_distance_meters: Option<f64>, // None if the graph is empty
+    within_radius: bool,
+}
+
+#[derive(Serialize)]
+struct ValidateResponse {
+    valid: bool,
+    origin: SnapCheck,
+    destination: SnapCheck,
+    same_component: bool,  // Whether a route between the two can exist at all
+}
+
+#[derive(Serialize)]
+struct ComponentResponse {
+    component: usize,      // Points with equal ids can reach each other
+    size: usize,           // Node count of the component
+    is_largest: bool,
+    snap_distance_meters: f64,
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    nodes: usize,
+    edges: usize,
+    components: usize,
+    memory_estimate_bytes: usize, // Approximate graph RAM footprint
+}
+
+#[derive(Serialize, JsonSchema)]
+struct VersionResponse {
+    version: &'static str,
+    default_alpha: f64,    // Used when a route request omits `alpha`
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    name: String,
+    limit: Option<usize>,
+    region: Option<String>, // Default region if omitted
+}
+
+#[derive(Deserialize)]
+struct RegionQuery {
+    region: Option<String>, // Default region if omitted
+}
+
+#[derive(Serialize)]
+struct RegionSummary {
+    id: String,
+    bounds: Option<[f64; 4]>, // [min_lon, min_lat, max_lon, max_lat]; configured bbox until loaded
+    loaded: bool,
+    nodes: Option<usize>,     // None until loaded
+    edges: Option<usize>,
+    builds: usize,            // Times the graph was built, including reloads
+}
+
+#[derive(Serialize)]
+struct RouteDebugResponse {
+    nodes: Vec<usize>,          // Graph node indices in travel order
+    osm_ids: Vec<Option<i64>>,  // OSM node id of each node (None if not from OSM data)
+    edges: Vec<WalkEdge>,       // The edge taken between each pair of consecutive nodes
+}
+
+#[derive(Serialize)]
+struct ImportResponse {
+    imported: usize,
+    errors: Vec<RowError>, // Rows skipped, by 1-based line number
+}
+
+#[derive(Deserialize)]
+struct AddressQuery {
+    lat: f64,
+    lon: f64,
+    radius_meters: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct AddressResult {
+    address: String,       // "housenumber street"
+    distance_meters: f64,
+}
+
+#[derive(Deserialize)]
+struct NearbyQuery {
+    lat: f64,
+    lon: f64,
+    #[serde(rename = "type")]
+    kind: String, // `amenity` value, e.g. police
+    k: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct NearbyResult {
+    name: Option<String>,
+    location: [f64; 2], // [lat, lon]
+    distance_meters: f64, // Straight line, not walked
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    name: String,
+    location: [f64; 2],    // [lat, lon], ready to use as a route origin/destination
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    bbox: Option<String>,  // "min_lon,min_lat,max_lon,max_lat" (GeoJSON order)
+    region: Option<String>, // Default region if omitted
+}
+
+#[derive(Serialize)]
+struct GeoJsonFeatureCollection {
+    r#type: String,
+    features: Vec<GeoJsonFeature>,
+}
+
+#[derive(Serialize)]
+struct GeoJsonFeature {
+    r#type: String,
+    geometry: GeoJsonLineString,
+    properties: EdgeProperties,
+}
+
+#[derive(Serialize)]
+struct EdgeProperties {
+    safety_score: f32,
+    highway: HighwayKind,
+}
+
+#[derive(Serialize, Clone)]
+struct DeepHealth {
+    healthy: bool,
+    detail: String,
+}
+
+// --- Handler ---
+
+async fn calculate_route(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RouteRequest>,
+) -> Result<impl IntoResponse, SafewalkError> {
+    serve_route(&state, payload).await
+}
+
+/// `GET /route?from=lat,lon&to=lat,lon&alpha=2` for quick manual testing; same logic as the POST
+async fn calculate_route_get(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RouteQuery>,
+) -> Result<impl IntoResponse, SafewalkError> {
+    let bad_request = |e: anyhow::Error| SafewalkError::InvalidInput(e.to_string());
+    let payload = RouteRequest {
+        origin: parse_lat_lon(&query.from).map_err(bad_request)?,
+        destination: parse_lat_lon(&query.to).map_err(bad_request)?,
+        alpha: query.alpha,
+        region: query.region,
+        ..Default::default()
+    };
+    serve_route(&state, payload).await
+}
+
+/// Picks `alpha` for the caller: binary-searches for the highest one whose route stays
+/// within `max_detour_ratio` of the shortest route. Longer routes generally come with higher
+/// alphas, but not strictly, so this finds a good alpha rather than provably the best.
+async fn calculate_auto_route(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AutoRouteRequest>,
+) -> Result<Response, SafewalkError> {
+    if !(payload.max_detour_ratio >= 1.0 && payload.max_detour_ratio.is_finite()) {
+        return Err(SafewalkError::InvalidInput("max_detour_ratio must be a finite number of at least 1".to_string()));
+    }
+    check_corridor_width(payload.corridor_width_meters).map_err(|e| SafewalkError::InvalidInput(e.to_string()))?;
+
+    let mut request = RouteRequest {
+        origin: payload.origin,
+        destination: payload.destination,
+        corridor_width_meters: payload.corridor_width_meters,
+        ..Default::default()
+    };
+    normalize_request(&mut request, &state.config);
+    record_route_fields(&request);
+    let graph = state.graph_for(payload.region.as_deref(), Some(request.origin)).await?;
+    let (max_snap_meters, max_detour_ratio) = (state.config.max_snap_meters, payload.max_detour_ratio);
+    let (loaded, trip) = (graph.clone(), request.clone());
+    // Up to AUTO_ALPHA_STEPS + 1 searches: off the async workers
+    let best = tokio::task::spawn_blocking(move || auto_route(&loaded.nav, &trip, max_detour_ratio, max_snap_meters))
+        .await
+        .map_err(|e| SafewalkError::Internal(format!("route search failed: {e}")))?;
+    match best {
+        Some(best) => Ok(Json(best).into_response()),
+        None => Err(no_route(&graph.nav, &state.config, request.origin, request.destination)),
+    }
+}
+
+/// The alpha search of `calculate_auto_route`; `None` if there is no route at all
+fn auto_route(nav: &NavigationGraph, request: &RouteRequest, max_detour_ratio: f64, max_snap_meters: f64) -> Option<AutoRouteResponse> {
+    let route_with = |alpha| compute_route(nav, &RouteRequest { alpha: Some(alpha), ..request.clone() }, &[], None, max_snap_meters);
+
+    let shortest = route_with(0.0)?;
+    let detour_ratio = |route: &RouteResponse| {
+        if shortest.total_distance > 0.0 { route.total_distance / shortest.total_distance } else { 1.0 }
+    };
+
+    let mut best = AutoRouteResponse { alpha: 0.0, detour_ratio: 1.0, route: shortest.clone() };
+    let (mut low, mut high) = (0.0, MAX_AUTO_ALPHA);
+    for step in 0..AUTO_ALPHA_STEPS {
+        // Try the top of the range first: often the safest route is already short enough
+        let alpha = if step == 0 { high } else { (low + high) / 2.0 };
+        let Some(route) = route_with(alpha) else { break };
+        let ratio = detour_ratio(&route);
+        if ratio <= max_detour_ratio {
+            best = AutoRouteResponse { alpha, detour_ratio: ratio, route };
+            if step == 0 {
+                break;
+            }
+            low = alpha;
+        } else {
+            high = alpha;
+        }
+    }
+    Some(best)
+}
+
+/// Several distinct routes between two points, best first, for the user to choose from
+async fn calculate_alternatives(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AlternativesRequest>,
+) -> Result<Response, SafewalkError> {
+    if !(1..=MAX_ALTERNATIVES).contains(&payload.alternatives) {
+        return Err(SafewalkError::InvalidInput(format!("alternatives must be between 1 and {MAX_ALTERNATIVES}")));
+    }
+    let mut request = payload.route;
+    if request.waypoints_polyline.is_some() || request.cost_expression.is_some() || request.return_route || request.max_extra_meters.is_some() {
+        return Err(SafewalkError::InvalidInput(
+            "/routes doesn't support waypoints_polyline, cost_expression, return_route or max_extra_meters".to_string(),
+        ));
+    }
+    check_corridor_width(request.corridor_width_meters).map_err(|e| SafewalkError::InvalidInput(e.to_string()))?;
+
+    normalize_request(&mut request, &state.config);
+    record_route_fields(&request);
+    let graph = state.graph_for(request.region.as_deref(), Some(request.origin)).await?;
+    let nav = &graph.nav;
+    let seed = payload.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64);
+    let paths = match snap_stops(nav, &request, &[], state.config.max_snap_meters) {
+        Some(stops) => nav.alternative_routes(stops[0], stops[1], &route_options(&request), payload.alternatives, seed),
+        None => Vec::new(),
+    };
+    if paths.is_empty() {
+        return Err(no_route(nav, &state.config, request.origin, request.destination));
+    }
+
+    let routes = paths.iter().map(|path| summarize_route(nav, &request, path)).collect();
+    Ok(Json(AlternativesResponse { seed, routes }).into_response())
+}
+
+/// The distance/risk tradeoff between origin and destination: routes for a sweep of alphas,
+/// keeping only those no other route beats on both distance and risk, shortest first
+async fn pareto_routes(
+    State(state): State<Arc<AppState>>,
+    Json(mut payload): Json<RouteRequest>,
+) -> Result<Response, SafewalkError> {
+    if payload.waypoints_polyline.is_some() || payload.cost_expression.is_some() || payload.return_route || payload.max_extra_meters.is_some() {
+        return Err(SafewalkError::InvalidInput(
+            "/pareto doesn't support waypoints_polyline, cost_expression, return_route or max_extra_meters".to_string(),
+        ));
+    }
+    check_corridor_width(payload.corridor_width_meters).map_err(|e| SafewalkError::InvalidInput(e.to_string()))?;
+
+    normalize_request(&mut payload, &state.config);
+    record_route_fields(&payload);
+    let graph = state.graph_for(payload.region.as_deref(), Some(payload.origin)).await?;
+    let (worker, trip, max_snap_meters) = (graph.clone(), payload.clone(), state.config.max_snap_meters);
+    // One search per alpha: off the async workers
+    let frontier = tokio::task::spawn_blocking(move || pareto_frontier(&worker.nav, &trip, max_snap_meters))
+        .await
+        .map_err(|e| SafewalkError::Internal(format!("route search failed: {e}")))?;
+    if frontier.is_empty() {
+        return Err(no_route(&graph.nav, &state.config, payload.origin, payload.destination));
+    }
+    Ok(Json(frontier).into_response())
+}
+
+/// The searches behind `pareto_routes`; empty if there is no route at all
+fn pareto_frontier(nav: &NavigationGraph, payload: &RouteRequest, max_snap_meters: f64) -> Vec<ParetoRoute> {
+    let mut candidates: Vec<(f64, NodeIndex, Vec<EdgeIndex>, f64, f64)> = Vec::new();
+    for alpha in PARETO_ALPHAS {
+        let request = RouteRequest { alpha: Some(alpha), ..payload.clone() };
+        let Some((start, edges, _)) = route_path(nav, &request, &[], None, max_snap_meters) else { continue };
+        let (distance, risk) = path_distance_and_risk(nav, &edges);
+        candidates.push((alpha, start, edges, distance, risk));
+    }
+
+    // Shortest first (lowest risk, then lowest alpha among equals): each route that lowers
+    // the risk seen so far is on the frontier, everything else is matched or beaten
+    candidates.sort_by(|a, b| a.3.total_cmp(&b.3).then(a.4.total_cmp(&b.4)).then(a.0.total_cmp(&b.0)));
+    let mut frontier = Vec::new();
+    let mut lowest_risk = f64::INFINITY;
+    for (alpha, start, edges, total_distance, total_risk) in candidates {
+        if total_risk < lowest_risk {
+            lowest_risk = total_risk;
+            let request = RouteRequest { alpha: Some(alpha), ..payload.clone() };
+            let geometry = summarize_route(nav, &request, &nav.nodes_along(start, &edges)).geometry;
+            frontier.push(ParetoRoute { alpha, total_distance, total_risk, geometry });
+        }
+    }
+    frontier
+}
+
+/// Length of the edges walked and their risk, the distance-weighted sum of `safety_score`
+fn path_distance_and_risk(nav: &NavigationGraph, edges: &[EdgeIndex]) -> (f64, f64) {
+    edges.iter()
+        .map(|&edge| &nav.graph[edge])
+        .fold((0.0, 0.0), |(distance, risk), e| (distance + e.distance_meters, risk + e.distance_meters * e.safety_score as f64))
+}
+
+/// The raw path of a `/route` request, for reproducing issues against the OSM data. Only the
+/// outbound route, without the `max_extra_meters` fallback. Needs `SAFEWALK_ADMIN_TOKEN`.
+async fn debug_route(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(mut payload): Json<RouteRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    check_admin_token(&state.config, &headers)?;
+    normalize_request(&mut payload, &state.config);
+    record_route_fields(&payload);
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    check_corridor_width(payload.corridor_width_meters).map_err(bad_request)?;
+    let waypoints = decode_waypoints(&payload, state.config.max_waypoints).map_err(bad_request)?;
+    let cost = parse_cost_expression(&payload).map_err(bad_request)?;
+
+    let graph = state.graph_for(payload.region.as_deref(), Some(payload.origin)).await?;
+    let nav = &graph.nav;
+    let Some((start, edges, _)) = route_path(nav, &payload, &waypoints, cost.as_ref(), state.config.max_snap_meters) else {
+        return Ok(no_route(nav, &state.config, payload.origin, payload.destination).into_response());
+    };
+
+    let nodes = nav.nodes_along(start, &edges);
+    Ok(Json(RouteDebugResponse {
+        osm_ids: nodes.iter().map(|&node| nav.osm_id_of(node)).collect(),
+        nodes: nodes.iter().map(|node| node.index()).collect(),
+        edges: edges.iter().map(|&edge| nav.graph[edge]).collect(),
+    }).into_response())
+}
+
+/// Parses a `lat,lon` pair as used in query strings
+fn parse_lat_lon(value: &str) -> anyhow::Result<[f64; 2]> {
+    let parse = |part: Option<&str>| part.and_then(|p| p.trim().parse::<f64>().ok());
+    let mut parts = value.split(',');
+    match (parse(parts.next()), parse(parts.next()), parts.next()) {
+        (Some(lat), Some(lon), None) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) => Ok([lat, lon]),
+        _ => anyhow::bail!("expected `lat,lon`, got `{value}`"),
+    }
+}
+
+/// Shared by the POST and GET handlers: validates, consults the cache, routes
+async fn serve_route(state: &Arc<AppState>, mut payload: RouteRequest) -> Result<Response, SafewalkError> {
+    normalize_request(&mut payload, &state.config);
+    record_route_fields(&payload);
+    let invalid = |e: anyhow::Error| SafewalkError::InvalidInput(e.to_string());
+    check_corridor_width(payload.corridor_width_meters).map_err(invalid)?;
+    let waypoints = decode_waypoints(&payload, state.config.max_waypoints).map_err(invalid)?;
+    let cost = parse_cost_expression(&payload).map_err(invalid)?;
+
+    let key = state.route_key(&payload);
+    if let Some(hit) = state.route_cache.lock().unwrap().get(&key) {
+        return Ok(([(CACHE_HEADER, "HIT")], Json(hit.clone())).into_response());
+    }
+
+    let graph = state.graph_for(payload.region.as_deref(), Some(payload.origin)).await?;
+    let Some(response) = compute_route(&graph.nav, &payload, &waypoints, cost.as_ref(), state.config.max_snap_meters) else {
+        return Err(no_route(&graph.nav, &state.config, payload.origin, payload.destination));
+    };
+    state.route_cache.lock().unwrap().put(key, response.clone());
+    Ok(([(CACHE_HEADER, "MISS")], Json(response)).into_response())
+}
+
+/// Warns about requests slower than `SAFEWALK_SLOW_REQUEST_MS`. Runs inside the request span,
+/// which carries the method, URI and, for routes, origin, destination and alpha.
+async fn log_slow_requests(State(threshold): State<Option<Duration>>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started.elapsed();
+    if threshold.is_some_and(|threshold| elapsed > threshold) {
+        tracing::warn!(elapsed_ms = elapsed.as_millis() as u64, status = response.status().as_u16(), "slow request");
+    }
+    response
+}
+
+/// Why the two points couldn't be connected, with where each one snapped to
+fn no_route(nav: &NavigationGraph, config: &ServerConfig, origin: [f64; 2], destination: [f64; 2]) -> SafewalkError {
+    let snapped = |point: [f64; 2]| {
+        let snap = nav.snap(point[0], point[1]);
+        SnappedPoint {
+            node: snap.map(|(node, _)| node.index()),
+            component: snap.and_then(|(node, _)| nav.component_of(node)),
+            beyond_max_snap: snap.is_none_or(|(_, d)| d > config.max_snap_meters),
+        }
+    };
+    let (origin, destination) = (snapped(origin), snapped(destination));
+    if origin.beyond_max_snap || destination.beyond_max_snap {
+        SafewalkError::SnapFailed { origin, destination }
+    } else if origin.component != destination.component {
+        SafewalkError::DisconnectedEndpoints { origin, destination }
+    } else {
+        SafewalkError::NoRoute { origin, destination }
+    }
+}
+
+/// Computes and caches a batch of routes ahead of time, e.g. popular trips right after a deploy
+async fn warmup_routes(
+    State(state): State<Arc<AppState>>,
+    Json(mut requests): Json<Vec<RouteRequest>>,
+) -> Result<Json<WarmupResponse>, (StatusCode, String)> {
+    // Each via point is another search; a batch of long tours is rejected as a whole, like one on /route
+    let max_waypoints = state.config.max_waypoints;
+    if let Some(index) = requests.iter().position(|r| waypoint_count(r) > max_waypoints) {
+        return Err((StatusCode::BAD_REQUEST, format!("request {index}: at most {max_waypoints} waypoints are allowed")));
+    }
+
+    let mut summary = WarmupResponse { requested: requests.len(), computed: 0, cached: 0 };
+
+    for request in &mut requests {
+        normalize_request(request, &state.config);
+        let key = state.route_key(request);
+        if state.route_cache.lock().unwrap().contains(&key) {
+            summary.cached += 1;
+            continue;
+        }
+
+        // Malformed waypoints, cost expressions, corridors or regions can't be routed; /route would reject them with 400
+        if check_corridor_width(request.corridor_width_meters).is_err() {
+            continue;
+        }
+        let (Ok(waypoints), Ok(cost)) = (decode_waypoints(request, max_waypoints), parse_cost_expression(request)) else { continue };
+        let Ok(graph) = state.graph_for(request.region.as_deref(), Some(request.origin)).await else { continue };
+        summary.computed += 1;
+        if let Some(response) = compute_route(&graph.nav, request, &waypoints, cost.as_ref(), state.config.max_snap_meters) {
+            state.route_cache.lock().unwrap().put(key, response);
+            summary.cached += 1;
+        }
+    }
+    Ok(Json(summary))
+}
+
+/// `corridor_width_meters` must be positive if given: nothing fits in a narrower band
+fn check_corridor_width(width: Option<f64>) -> anyhow::Result<()> {
+    anyhow::ensure!(width.is_none_or(|w| w > 0.0 && w.is_finite()), "corridor_width_meters must be a positive number");
+    Ok(())
+}
+
+/// Fills in the server's default `alpha` and `service_penalty` and rounds origin and destination to
+/// `coordinate_decimals` places, so near-identical requests share a cache entry (and snap identically)
+fn normalize_request(request: &mut RouteRequest, config: &ServerConfig) {
+    request.alpha.get_or_insert(config.default_alpha);
+    request.service_penalty.get_or_insert(config.service_penalty);
+    let factor = 10f64.powi(config.coordinate_decimals as i32);
+    for point in [&mut request.origin, &mut request.destination] {
+        for value in point.iter_mut() {
+            *value = (*value * factor).round() / factor;
+        }
+    }
+}
+
+/// Notes the route's ends and alpha on the request span, so the request's log lines
+/// (e.g. the slow-request warning) say which route it was
+fn record_route_fields(request: &RouteRequest) {
+    let span = tracing::Span::current();
+    span.record("origin", tracing::field::debug(request.origin));
+    span.record("destination", tracing::field::debug(request.destination));
+    if let Some(alpha) = request.alpha {
+        span.record("alpha", alpha);
+    }
+}
+
+/// Requests are cached by their canonical JSON form, so only identical requests share an entry,
+/// prefixed with the graph generation they were routed on
+fn cache_key(request: &RouteRequest, generation: usize) -> String {
+    format!("{generation}:{}", serde_json::to_string(request).expect("route requests always serialize"))
+}
+
+/// Decodes the optional via-point polyline, rejecting anything that isn't a valid coordinate
+/// list or has more than `max_waypoints` points (each one costs another A* search)
+fn decode_waypoints(request: &RouteRequest, max_waypoints: usize) -> anyhow::Result<Vec<[f64; 2]>> {
+    let Some(encoded) = &request.waypoints_polyline else { return Ok(Vec::new()) };
+    let points = polyline::decode(encoded).map_err(|e| anyhow::anyhow!("invalid waypoints_polyline: {e}"))?;
+    anyhow::ensure!(
+        points.iter().all(|p| (-90.0..=90.0).contains(&p[0]) && (-180.0..=180.0).contains(&p[1])),
+        "invalid waypoints_polyline: coordinates out of range"
+    );
+    anyhow::ensure!(points.len() <= max_waypoints, "at most {max_waypoints} waypoints are allowed");
+    Ok(points)
+}
+
+/// Via points in the request, or 0 if its polyline doesn't decode (rejected elsewhere)
+fn waypoint_count(request: &RouteRequest) -> usize {
+    request.waypoints_polyline.as_deref().and_then(|encoded| polyline::decode(encoded).ok()).map_or(0, |points| points.len())
+}
+
+fn parse_cost_expression(request: &RouteRequest) -> anyhow::Result<Option<CostExpression>> {
+    request.cost_expression.as_deref().map(CostExpression::parse).transpose()
+}
+
+/// Snaps, routes and summarizes one request, passing through `waypoints` in order; `None`
+/// if a point is farther than `max_snap_meters` from the graph or some leg has no path.
+/// `cost` replaces the default cost formula.
+fn compute_route(
+    nav: &NavigationGraph,
+    payload: &RouteRequest,
+    waypoints: &[[f64; 2]],
+    cost: Option<&CostExpression>,
+    max_snap_meters: f64,
+) -> Option<RouteResponse> {
+    // Safer, but not at any price: past the detour budget, take the alpha = 0 route instead
+    if let Some(max_extra) = payload.max_extra_meters {
+        let unbounded = RouteRequest { max_extra_meters: None, ..payload.clone() };
+        let safer = compute_route(nav, &unbounded, waypoints, cost, max_snap_meters)?;
+        let fastest_request = RouteRequest { alpha: Some(0.0), cost_expression: None, ..unbounded };
+        let fastest = compute_route(nav, &fastest_request, waypoints, None, max_snap_meters)?;
+        return Some(if safer.total_distance - fastest.total_distance <= max_extra {
+            safer
+        } else {
+            RouteResponse { fell_back_to_fastest: true, ..fastest }
+        });
+    }
+
+    // The way back may differ (one-way paths, crossings charged on the side stepped onto),
+    // so it is routed separately, through the via points in reverse order. Without a way
+    // back the outbound route is still returned, just without `return_route`.
+    let return_route = if payload.return_route {
+        let back = RouteRequest {
+            origin: payload.destination,
+            destination: payload.origin,
+            heading_degrees: None,
+            return_route: false,
+            ..payload.clone()
+        };
+        let waypoints_back: Vec<_> = waypoints.iter().rev().copied().collect();
+        compute_route(nav, &back, &waypoints_back, cost, max_snap_meters).map(Box::new)
+    } else {
+        None
+    };
+
+    let (nodes, waypoint_indices) = route_nodes(nav, payload, waypoints, cost, max_snap_meters)?;
+    let mut response = summarize_route(nav, payload, &nodes);
+    response.return_route = return_route;
+    response.waypoint_indices = waypoint_indices;
+    Some(response)
+}
+
+/// The node path of one route (no return leg, no `max_extra_meters` fallback), with the
+/// position in it of each via point and of the destination
+fn route_nodes(
+    nav: &NavigationGraph,
+    payload: &RouteRequest,
+    waypoints: &[[f64; 2]],
+    cost: Option<&CostExpression>,
+    max_snap_meters: f64,
+) -> Option<(Vec<NodeIndex>, Vec<usize>)> {
+    let (start, edges, stop_indices) = route_path(nav, payload, waypoints, cost, max_snap_meters)?;
+    Some((nav.nodes_along(start, &edges), stop_indices))
+}
+
+/// `route_nodes` as the start node and the edges walked from it. Position `i` in the node
+/// path is the node reached after `i` edges.
+fn route_path(
+    nav: &NavigationGraph,
+    payload: &RouteRequest,
+    waypoints: &[[f64; 2]],
+    cost: Option<&CostExpression>,
+    max_snap_meters: f64,
+) -> Option<(NodeIndex, Vec<EdgeIndex>, Vec<usize>)> {
+    // 1. Snap input coordinates to nearest Graph Nodes
+    let stops = snap_stops(nav, payload, waypoints, max_snap_meters)?;
+
+    // 2. Calculate Route (Weighted A*)
+    let options = route_options(payload);
+    let mut edges = Vec::new();
+    let mut stop_indices = Vec::new();
+    for (i, leg) in stops.windows(2).enumerate() {
+        // The heading only matters when setting off, not when leaving a via point
+        let leg_options = if i == 0 { options } else { RouteOptions { heading_degrees: None, ..options } };
+        edges.extend(route_leg(nav, leg[0], leg[1], &leg_options, cost, payload.corridor_width_meters)?);
+        stop_indices.push(edges.len());
+    }
+    Some((stops[0], edges, stop_indices))
+}
+
+/// `[lon, lat]` points as a WKT `LINESTRING`, or `LINESTRING EMPTY` without points
+fn wkt_linestring(coordinates: &[[f64; 2]]) -> String {
+    if coordinates.is_empty() {
+        return "LINESTRING EMPTY".to_string();
+    }
+    let points: Vec<String> = coordinates.iter().map(|[lon, lat]| format!("{lon} {lat}")).collect();
+    format!("LINESTRING({})", points.join(", "))
+}
+
+/// Snaps origin, `waypoints` and destination to graph nodes, in travel order; `None` if any
+/// point is farther than `max_snap_meters` from the graph
+fn snap_stops(nav: &NavigationGraph, payload: &RouteRequest, waypoints: &[[f64; 2]], max_snap_meters: f64) -> Option<Vec<NodeIndex>> {
+    let snap = |point: &[f64; 2]| {
+        let nearest = nav.find_nearest_node_within(point[0], point[1], max_snap_meters)?;
+        if payload.avoid_dead_ends {
+            nav.find_nearest_through_node(point[0], point[1], DEAD_END_SNAP_RADIUS_METERS)
+        } else {
+            Some(nearest)
+        }
+    };
+    let start_node = match payload.heading_degrees {
+        Some(heading) => snap(&payload.origin)
+            .and_then(|_| nav.find_departure_node(payload.origin[0], payload.origin[1], heading)),
+        None => snap(&payload.origin),
+    }?;
+    let mut stops = vec![start_node];
+    for point in waypoints.iter().chain([&payload.destination]) {
+        stops.push(snap(point)?);
+    }
+    Some(stops)
+}
+
+/// Edges walked on one leg. With a corridor width, the search keeps to that band around the
+/// straight line, doubling it up to `CORRIDOR_RETRIES` times if nothing is found inside.
+fn route_leg(
+    nav: &NavigationGraph,
+    from: NodeIndex,
+    to: NodeIndex,
+    options: &RouteOptions,
+    cost: Option<&CostExpression>,
+    corridor_width_meters: Option<f64>,
+) -> Option<Vec<EdgeIndex>> {
+    let search = |options: &RouteOptions| match cost {
+        Some(expr) => nav.route_edges_with_cost(from, to, options, &|edge| expr.eval(edge, options.alpha)),
+        None => nav.route_edges(from, to, options),
+    };
+    let Some(width) = corridor_width_meters else { return search(options).map(|(_, path)| path) };
+
+    let (a, b) = (nav.graph[from], nav.graph[to]);
+    (0..=CORRIDOR_RETRIES).find_map(|attempt| {
+        let corridor = Corridor { from: [a.lat, a.lon], to: [b.lat, b.lon], width_meters: width * 2f64.powi(attempt) };
+        search(&RouteOptions { corridor: Some(corridor), ..*options }).map(|(_, path)| path)
+    })
+}
+
+fn route_options(payload: &RouteRequest) -> RouteOptions {
+    RouteOptions {
+        // Filled in by `normalize_request` for client requests, like `service_penalty`
+        alpha: payload.alpha.unwrap_or_default(),
+        night: payload.night,
+        heading_degrees: payload.heading_degrees,
+        minimize_crossings: payload.minimize_crossings,
+        prefer_shade: payload.prefer_shade,
+        openness: payload.openness,
+        prefer_busy: payload.prefer_busy,
+        service_penalty: payload.service_penalty.unwrap_or_default(),
+        heuristic_weight: payload.heuristic_weight.unwrap_or(1.0),
+        // Set per leg by `route_leg`
+        corridor: None,
+        avoid_flood: payload.avoid_flood,
+        avoid_unpaved: payload.avoid_unpaved,
+        min_smoothness: payload.min_smoothness,
+        profile: payload.profile,
+    }
+}
+
+/// Geometry, distance, risk, ETA and streets of the route along `nodes`
+fn summarize_route(nav: &NavigationGraph, payload: &RouteRequest, nodes: &[NodeIndex]) -> RouteResponse {
+    let g = &nav.graph;
+    let speeds = SpeedTable::default().with_overrides(&payload.walking_speeds_kmh);
+    let mut coordinates = Vec::new();
+    let mut real_distance = 0.0;
+    let mut total_safety_score = 0.0;
+    let mut total_risk = 0.0;
+    let mut eta_seconds = 0.0;
+    let mut edge_count = 0;
+    let mut crossings = 0;
+    let mut streets: Vec<String> = Vec::new();
+
+    // Reconstruct path geometry and stats; callers wanting just the numbers skip the geometry
+    let with_geometry = payload.geometry != Some(false);
+    for (i, &node_idx) in nodes.iter().enumerate() {
+        if with_geometry {
+            let node_data = g[node_idx];
+            coordinates.push([node_data.lon, node_data.lat]); // GeoJSON is [Lon, Lat]
+        }
+
+        // Calculate distance and safety from edges
+        if i > 0 {
+            let prev_idx = nodes[i-1];
+            if let Some(edge) = g.find_edge(prev_idx, node_idx) {
+                let weight = g[edge];
+                real_distance += weight.distance_meters;
+                total_safety_score += weight.safety_score;
+                total_risk += weight.distance_meters * weight.safety_score as f64;
+                eta_seconds += speeds.edge_seconds(&weight);
+                edge_count += 1;
+                crossings += usize::from(weight.crossing.is_some());
+                // Unnamed connectors (footpaths, crossings) don't break up a street
+                if let Some(name) = nav.name_of(edge).filter(|&n| streets.last().is_none_or(|last| last != n)) {
+                    streets.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    let avg_safety = if edge_count > 0 { total_safety_score / edge_count as f32 } else { 0.0 };
+    // Between the requested points; capped at the route's length, since snapping can bring
+    // the ends closer together, so the detour is never negative
+    let straight_line_distance = haversine_meters(payload.origin, payload.destination).min(real_distance);
+    let tile_geometry = payload.quantize.filter(|_| with_geometry).map(|q| {
+        let points: Vec<[f64; 2]> = coordinates.iter().map(|&[lon, lat]| [lat, lon]).collect();
+        tiles::quantize(&points, q.extent.get())
+    });
+
+    let geometry = match payload.geometry_format {
+        GeometryFormat::GeoJson => RouteGeometry::GeoJson(GeoJsonLineString {
+            r#type: "LineString".to_string(),
+            coordinates,
+        }),
+        GeometryFormat::Wkt => RouteGeometry::Wkt(wkt_linestring(&coordinates)),
+    };
+
+    RouteResponse {
+        geometry,
+        total_distance: real_distance,
+        straight_line_distance,
+        detour_meters: real_distance - straight_line_distance,
+        average_safety: avg_safety,
+        total_risk,
+        eta_seconds,
+        crossings,
+        streets,
+        // Single-leg routes end at the destination; `compute_route` fills in via points
+        waypoint_indices: vec![nodes.len() - 1],
+        tile_geometry,
+        steps: payload.steps.then(|| {
+            let language = payload.language.as_deref().map_or(Language::English, Language::from_code);
+            route_steps(nav, nodes, language)
+        }),
+        elevation_profile: payload.elevation_profile.then(|| elevation_profile(nav, nodes)).flatten(),
+        return_route: None,
+        fell_back_to_fastest: false,
+        applied: applied_options(payload),
+    }
+}
+
+/// `(distance from the start, elevation)` at every node of the path. OSM tags elevation on few
+/// nodes, so the rest are interpolated by distance between tagged ones, and held level before
+/// the first and after the last. `None` if no node along the path is tagged.
+fn elevation_profile(nav: &NavigationGraph, nodes: &[NodeIndex]) -> Option<Vec<(f64, f32)>> {
+    let g = &nav.graph;
+    let mut distances = vec![0.0];
+    for pair in nodes.windows(2) {
+        let step = g.find_edge(pair[0], pair[1]).map_or(0.0, |edge| g[edge].distance_meters);
+        distances.push(distances.last().copied().unwrap_or(0.0) + step);
+    }
+    let known: Vec<(f64, f32)> = nodes.iter()
+        .zip(&distances)
+        .filter_map(|(&node, &distance)| Some((distance, nav.elevation_of(node)?)))
+        .collect();
+    let (&first, &last) = (known.first()?, known.last()?);
+
+    let profile = distances.iter()
+        .map(|&distance| {
+            // First tagged sample at or beyond this distance, and the one before it
+            let next = known.partition_point(|&(d, _)| d < distance);
+            let elevation = match (next.checked_sub(1).map(|i| known[i]), known.get(next)) {
+                (_, Some(&(d, ele))) if d == distance => ele,
+                (Some((d0, e0)), Some(&(d1, e1))) => e0 + (e1 - e0) * ((distance - d0) / (d1 - d0)) as f32,
+                (None, _) => first.1,
+                (_, None) => last.1,
+            };
+            (distance, elevation)
+        })
+        .collect();
+    Some(profile)
+}
+
+/// Turn-by-turn steps along a node path. Consecutive edges on the same street stay one step
+/// until the bearing changes by `TURN_THRESHOLD_DEGREES` or more, so gentle curves and
+/// nodes along a straight street don't produce instructions.
+fn route_steps(nav: &NavigationGraph, nodes: &[NodeIndex], language: Language) -> Vec<RouteStep> {
+    let g = &nav.graph;
+    let mut steps: Vec<RouteStep> = Vec::new();
+    let mut last_bearing: Option<f64> = None;
+    for pair in nodes.windows(2) {
+        let Some(edge) = g.find_edge(pair[0], pair[1]) else { continue };
+        let (a, b) = (g[pair[0]], g[pair[1]]);
+        let (from, distance) = ([a.lat, a.lon], g[edge].distance_meters);
+        let street = nav.name_of(edge);
+        let bearing = bearing_degrees(from, [b.lat, b.lon]);
+        let turn = last_bearing.map(|last| (bearing - last + 540.0).rem_euclid(360.0) - 180.0);
+
+        match steps.last_mut() {
+            Some(step) if step.street.as_deref() == street && turn.is_none_or(|t| t.abs() < TURN_THRESHOLD_DEGREES) => {
+                step.distance_meters += distance;
+            }
+            _ => {
+                let maneuver = match turn {
+                    _ if steps.is_empty() => Maneuver::Depart,
+                    Some(turn) => Maneuver::from_turn(turn),
+                    None => Maneuver::Continue,
+                };
+                steps.push(RouteStep {
+                    maneuver,
+                    street: street.map(str::to_string),
+                    instruction: language.instruction(maneuver, street),
+                    distance_meters: distance,
+                    location: from,
+                });
+            }
+        }
+        if distance >= MIN_BEARING_EDGE_METERS {
+            last_bearing = Some(bearing);
+        }
+    }
+    steps
+}
+
+fn applied_options(payload: &RouteRequest) -> AppliedOptions {
+    let options = route_options(payload).clamped();
+    AppliedOptions {
+        options,
+        max_incline_percent: options.profile.max_incline_percent(),
+        custom_cost: payload.cost_expression.is_some(),
+    }
+}
+
+/// Of several candidate destinations (e.g. nearby shelters), finds the one whose route has
+/// the lowest `average_safety`, breaking ties by distance. Candidates that snap onto the
+/// origin's node are skipped: a route without edges has no risk and would always win.
+async fn safest_of(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SafestOfRequest>,
+) -> Result<Json<SafestOfResponse>, (StatusCode, String)> {
+    if payload.candidates.len() > MAX_SAFEST_OF_CANDIDATES {
+        return Err((StatusCode::BAD_REQUEST, format!("at most {MAX_SAFEST_OF_CANDIDATES} candidates are allowed")));
+    }
+
+    let graph = state.graph_for(None, Some(payload.origin)).await?;
+    let alpha = payload.alpha.unwrap_or(state.config.default_alpha);
+    let (max_snap_meters, service_penalty) = (state.config.max_snap_meters, state.config.service_penalty);
+    // One search per candidate: off the async workers
+    tokio::task::spawn_blocking(move || safest_candidate(&graph.nav, &payload, alpha, service_penalty, max_snap_meters))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("candidate search failed: {e}")))?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "no candidate is reachable from the origin".to_string()))
+}
+
+/// The searches behind `safest_of`; `None` if no candidate is reachable
+fn safest_candidate(
+    nav: &NavigationGraph,
+    payload: &SafestOfRequest,
+    alpha: f64,
+    service_penalty: f64,
+    max_snap_meters: f64,
+) -> Option<SafestOfResponse> {
+    payload.candidates.iter()
+        .enumerate()
+        .filter_map(|(index, &destination)| {
+            let request = RouteRequest {
+                origin: payload.origin,
+                destination,
+                alpha: Some(alpha),
+                service_penalty: Some(service_penalty),
+                ..Default::default()
+            };
+            let route = compute_route(nav, &request, &[], None, max_snap_meters)?;
+            (route.total_distance > 0.0).then_some(SafestOfResponse { index, destination, route })
+        })
+        .min_by(|a, b| {
+            a.route.average_safety.total_cmp(&b.route.average_safety)
+                .then(a.route.total_distance.total_cmp(&b.route.total_distance))
+        })
+}
+
+/// Walking distances between every source and every target, optionally with the paths.
+/// One Dijkstra search per source serves all of its targets.
+async fn route_matrix(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MatrixRequest>,
+) -> Result<Json<MatrixResponse>, (StatusCode, String)> {
+    if payload.sources.len() > MAX_MATRIX_POINTS || payload.targets.len() > MAX_MATRIX_POINTS {
+        return Err((StatusCode::BAD_REQUEST, format!("at most {MAX_MATRIX_POINTS} sources and {MAX_MATRIX_POINTS} targets are allowed")));
+    }
+
+    let graph = state.graph_for(None, payload.sources.first().copied()).await?;
+    let options = RouteOptions {
+        alpha: payload.alpha.unwrap_or(state.config.default_alpha),
+        service_penalty: state.config.service_penalty,
+        ..Default::default()
+    };
+    let max_snap_meters = state.config.max_snap_meters;
+    // One search per source: off the async workers
+    tokio::task::spawn_blocking(move || matrix(&graph.nav, &payload, &options, max_snap_meters))
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("matrix search failed: {e}")))
+}
+
+/// The searches behind `route_matrix`; points farther than `max_snap_meters` from the graph
+/// get null cells
+fn matrix(nav: &NavigationGraph, payload: &MatrixRequest, options: &RouteOptions, max_snap_meters: f64) -> MatrixResponse {
+    let g = &nav.graph;
+    let snap = |point: &[f64; 2]| nav.find_nearest_node_within(point[0], point[1], max_snap_meters);
+    let targets: Vec<_> = payload.targets.iter().map(snap).collect();
+
+    let mut distances = Vec::new();
+    let mut geometries = Vec::new();
+    for source in &payload.sources {
+        let start = snap(source);
+        let tree = start.map(|start| nav.route_tree(start, options));
+        let paths: Vec<_> = targets.iter()
+            .map(|&target| tree.as_ref().zip(target).and_then(|(tree, target)| tree.edges_to(g, target)))
+            .collect();
+
+        distances.push(paths.iter()
+            .map(|path| path.as_ref().map(|edges| edges.iter().fold(0.0, |sum, &e| sum + g[e].distance_meters)))
+            .collect());
+        if payload.include_geometry {
+            geometries.push(paths.iter()
+                .map(|path| {
+                    let (start, edges) = (start?, path.as_ref()?);
+                    let nodes = std::iter::once(start).chain(edges.iter().filter_map(|&e| Some(g.edge_endpoints(e)?.1)));
+                    Some(GeoJsonLineString {
+                        r#type: "LineString".to_string(),
+                        coordinates: nodes.map(|n| [g[n].lon, g[n].lat]).collect(),
+                    })
+                })
+                .collect());
+        }
+    }
+
+    MatrixResponse { distances, geometries: payload.include_geometry.then_some(geometries) }
+}
+
+/// Costs and walking distances from many origins to one destination (everyone heading to
+/// the same venue), from a single Dijkstra search backwards from the destination
+async fn many_to_one(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ManyToOneRequest>,
+) -> Result<Json<ManyToOneResponse>, (StatusCode, String)> {
+    if payload.origins.len() > MAX_MANY_TO_ONE_ORIGINS {
+        return Err((StatusCode::BAD_REQUEST, format!("at most {MAX_MANY_TO_ONE_ORIGINS} origins are allowed")));
+    }
+
+    let graph = state.graph_for(None, Some(payload.destination)).await?;
+    let options = RouteOptions {
+        alpha: payload.alpha.unwrap_or(state.config.default_alpha),
+        service_penalty: state.config.service_penalty,
+        ..Default::default()
+    };
+    let max_snap_meters = state.config.max_snap_meters;
+    // A search over the whole graph: off the async workers
+    tokio::task::spawn_blocking(move || to_one(&graph.nav, &payload, &options, max_snap_meters))
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("many-to-one search failed: {e}")))
+}
+
+/// The search behind `many_to_one`; points farther than `max_snap_meters` from the graph
+/// get nulls
+fn to_one(nav: &NavigationGraph, payload: &ManyToOneRequest, options: &RouteOptions, max_snap_meters: f64) -> ManyToOneResponse {
+    let snap = |point: [f64; 2]| nav.find_nearest_node_within(point[0], point[1], max_snap_meters);
+    let tree = snap(payload.destination).map(|end| nav.route_tree_to(end, options));
+
+    let (mut costs, mut distances) = (Vec::new(), Vec::new());
+    for &origin in &payload.origins {
+        let start = tree.as_ref().zip(snap(origin));
+        costs.push(start.and_then(|(tree, start)| tree.cost_to(start)));
+        distances.push(start.and_then(|(tree, start)| {
+            Some(tree.edges_to(&nav.graph, start)?.iter().fold(0.0, |sum, &e| sum + nav.graph[e].distance_meters))
+        }));
+    }
+    ManyToOneResponse { costs, distances }
+}
+
+/// Checks the safety model still ranks canonical streets sensibly under the current
+/// configuration: `ok`, or `503` naming the first ordering it breaks
+async fn self_test(State(state): State<Arc<AppState>>) -> (StatusCode, String) {
+    match state.safety_map.self_test() {
+        Ok(()) => (StatusCode::OK, "ok".to_string()),
+        Err(failure) => (StatusCode::SERVICE_UNAVAILABLE, failure),
+    }
+}
+
+/// Snaps a point to the nearest edge and explains how its safety score was derived
+async fn explain_edge(
+    State(state): State<Arc<AppState>>,
+    Query(point): Query<PointQuery>,
+) -> Result<Json<EdgeExplainResponse>, (StatusCode, String)> {
+    let graph = state.graph_for(None, Some([point.lat, point.lon])).await?;
+    let g = &graph.nav.graph;
+
+    let not_found = || (StatusCode::NOT_FOUND, "no edge near this point".to_string());
+    let edge_idx = graph.nav.find_nearest_edge(point.lat, point.lon).ok_or_else(not_found)?;
+    let (a, b) = g.edge_endpoints(edge_idx).ok_or_else(not_found)?;
+    let edge = g[edge_idx];
+
+    Ok(Json(EdgeExplainResponse {
+        geometry: GeoJsonLineString {
+            r#type: "LineString".to_string(),
+            coordinates: vec![[g[a].lon, g[a].lat], [g[b].lon, g[b].lat]],
+        },
+        distance_meters: edge.distance_meters,
+        safety_score: edge.safety_score,
+        breakdown: edge.risk,
+        way_id: edge.way_id,
+    }))
+}
+
+/// Cheap pre-flight check: do both points snap onto the graph, and can they reach each other?
+async fn validate_points(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ValidateRequest>,
+) -> Result<Json<ValidateResponse>, (StatusCode, String)> {
+    let graph = state.graph_for(None, Some(payload.origin)).await?;
+    let nav = &graph.nav;
+    let max_snap = state.config.max_snap_meters;
+
+    let origin = nav.snap(payload.origin[0], payload.origin[1]);
+    let destination = nav.snap(payload.destination[0], payload.destination[1]);
+
+    let check = |snap: Option<(_, f64)>| SnapCheck {
+        snap_distance_meters: snap.map(|(_, d)| d),
+        within_radius: snap.is_some_and(|(_, d)| d <= max_snap),
+    };
+
+    let same_component = match (origin, destination) {
+        (Some((a, _)), Some((b, _))) => nav.component_of(a) == nav.component_of(b),
+        _ => false,
+    };
+
+    let (origin, destination) = (check(origin), check(destination));
+    Ok(Json(ValidateResponse {
+        valid: origin.within_radius && destination.within_radius && same_component,
+        origin,
+        destination,
+        same_component,
+    }))
+}
+
+/// Location-based risk at any point, not just on streets, for sampling a risk overlay
+async fn point_risk(
+    State(state): State<Arc<AppState>>,
+    Query(point): Query<PointQuery>,
+) -> Result<Json<PointRiskResponse>, (StatusCode, String)> {
+    if !((-90.0..=90.0).contains(&point.lat) && (-180.0..=180.0).contains(&point.lon)) {
+        return Err((StatusCode::BAD_REQUEST, "lat/lon out of range".to_string()));
+    }
+    Ok(Json(state.location_risk([point.lat, point.lon])))
+}
+
+/// `GET /risk` sampled over a grid of cells covering a bbox, for rendering a heatmap
+async fn risk_grid(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RiskGridRequest>,
+) -> Result<Json<RiskGridResponse>, (StatusCode, String)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+    let [min_lon, min_lat, max_lon, max_lat] = payload.bbox;
+    if !((-180.0..=180.0).contains(&min_lon) && (-180.0..=180.0).contains(&max_lon)
+        && (-90.0..=90.0).contains(&min_lat) && (-90.0..=90.0).contains(&max_lat))
+    {
+        return Err(bad_request("bbox out of range".to_string()));
+    }
+    if !(min_lon < max_lon && min_lat < max_lat) {
+        return Err(bad_request("bbox minimum must be below its maximum".to_string()));
+    }
+    if !(payload.resolution_meters > 0.0 && payload.resolution_meters.is_finite()) {
+        return Err(bad_request("resolution_meters must be positive".to_string()));
+    }
+
+    // Width measured along the middle of the box, where it is representative
+    let mid_lat = (min_lat + max_lat) / 2.0;
+    let width = haversine_meters([mid_lat, min_lon], [mid_lat, max_lon]);
+    let height = haversine_meters([min_lat, min_lon], [max_lat, min_lon]);
+    let cols = ((width / payload.resolution_meters).ceil() as usize).max(1);
+    let rows = ((height / payload.resolution_meters).ceil() as usize).max(1);
+    if rows.saturating_mul(cols) > MAX_RISK_GRID_CELLS {
+        return Err(bad_request(format!(
+            "{rows}x{cols} cells exceed the limit of {MAX_RISK_GRID_CELLS}; use a coarser resolution or a smaller bbox"
+        )));
+    }
+
+    let (cell_lon, cell_lat) = ((max_lon - min_lon) / cols as f64, (max_lat - min_lat) / rows as f64);
+    let risk = (0..rows)
+        .map(|row| {
+            let lat = max_lat - (row as f64 + 0.5) * cell_lat;
+            (0..cols).map(|col| state.location_risk([lat, min_lon + (col as f64 + 0.5) * cell_lon]).risk).collect()
+        })
+        .collect();
+    Ok(Json(RiskGridResponse { rows, cols, risk }))
+}
+
+/// Which connected component a point snaps into, for debugging "no route" results
+async fn component_of_point(
+    State(state): State<Arc<AppState>>,
+    Query(point): Query<PointQuery>,
+) -> Result<Json<ComponentResponse>, (StatusCode, String)> {
+    let graph = state.graph_for(None, Some([point.lat, point.lon])).await?;
+    let nav = &graph.nav;
+    let not_found = || (StatusCode::NOT_FOUND, "no node near this point".to_string());
+    let (node, snap_distance_meters) = nav.snap(point.lat, point.lon).ok_or_else(not_found)?;
+    let component = nav.component_of(node).ok_or_else(not_found)?;
+
+    Ok(Json(ComponentResponse {
+        component,
+        size: nav.component_size(component),
+        is_largest: nav.largest_component() == Some(component),
+        snap_distance_meters,
+    }))
+}
+
+/// Finds streets by (partial) name, best matches first
+async fn search_streets(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(10).min(50);
+    let graph = state.graph_for(query.region.as_deref(), None).await?;
+    let results = graph.nav.search_names(&query.name, limit)
+        .into_iter()
+        .map(|m| SearchResult { name: m.name.to_string(), location: [m.lat, m.lon] })
+        .collect();
+    Ok(Json(results))
+}
+
+/// Dumps the graph's edges for viewing in a GIS tool; large graphs need a `bbox`
+async fn export_graph(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let graph = state.graph_for(query.region.as_deref(), None).await?;
+    let nav = &graph.nav;
+    let bounds = query.bbox.as_deref()
+        .map(parse_bbox)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if bounds.is_none() && nav.graph.edge_count() > MAX_EXPORT_EDGES_WITHOUT_BBOX {
+        return Err((StatusCode::BAD_REQUEST, "graph too large to export whole; pass bbox=min_lon,min_lat,max_lon,max_lat".to_string()));
+    }
+
+    let features = nav.undirected_edges(bounds)
+        .filter_map(|e| {
+            let (a, b) = nav.graph.edge_endpoints(e)?;
+            let edge = nav.graph[e];
+            Some(GeoJsonFeature {
+                r#type: "Feature".to_string(),
+                geometry: GeoJsonLineString {
+                    r#type: "LineString".to_string(),
+                    coordinates: vec![[nav.graph[a].lon, nav.graph[a].lat], [nav.graph[b].lon, nav.graph[b].lat]],
+                },
+                properties: EdgeProperties { safety_score: edge.safety_score, highway: edge.highway },
+            })
+        })
+        .collect();
+
+    Ok(with_etag(&headers, &graph.etag, Json(GeoJsonFeatureCollection {
+        r#type: "FeatureCollection".to_string(),
+        features,
+    })))
+}
+
+/// Parses a `min_lon,min_lat,max_lon,max_lat` bounding box
+fn parse_bbox(value: &str) -> anyhow::Result<Rect> {
+    let parts: Vec<f64> = value.split(',')
+        .map(|p| p.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| anyhow::anyhow!("expected bbox=min_lon,min_lat,max_lon,max_lat, got `{value}`"))?;
+    let &[min_lon, min_lat, max_lon, max_lat] = parts.as_slice() else {
+        anyhow::bail!("expected bbox=min_lon,min_lat,max_lon,max_lat, got `{value}`");
+    };
+    anyhow::ensure!(min_lon <= max_lon && min_lat <= max_lat, "bbox minimum exceeds its maximum");
+    Ok(Rect::new(Coord { x: min_lon, y: min_lat }, Coord { x: max_lon, y: max_lat }))
+}
+
+/// Addresses near a point, nearest first: a lightweight reverse geocoder for labelling routes
+async fn nearby_addresses(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AddressQuery>,
+) -> Result<Json<Vec<AddressResult>>, (StatusCode, String)> {
+    let radius = query.radius_meters.unwrap_or(DEFAULT_ADDRESS_RADIUS_METERS);
+    if !(radius > 0.0 && radius <= MAX_ADDRESS_RADIUS_METERS) {
+        return Err((StatusCode::BAD_REQUEST, format!("radius_meters must be in (0, {MAX_ADDRESS_RADIUS_METERS}]")));
+    }
+
+    let graph = state.graph_for(None, Some([query.lat, query.lon])).await?;
+    let results = graph.nav.addresses_near(query.lat, query.lon, radius)
+        .into_iter()
+        .map(|(address, distance_meters)| AddressResult { address: address.to_string(), distance_meters })
+        .collect();
+    Ok(Json(results))
+}
+
+/// The nearest amenities of one type (police, hospital, ...), for safety context along a route
+async fn nearby_amenities(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NearbyQuery>,
+) -> Result<Json<Vec<NearbyResult>>, (StatusCode, String)> {
+    let k = query.k.unwrap_or(DEFAULT_NEARBY_COUNT);
+    if !(1..=MAX_NEARBY_COUNT).contains(&k) {
+        return Err((StatusCode::BAD_REQUEST, format!("k must be between 1 and {MAX_NEARBY_COUNT}")));
+    }
+
+    let graph = state.graph_for(None, Some([query.lat, query.lon])).await?;
+    let results = graph.nav.amenities_near(query.lat, query.lon, &query.kind, k)
+        .into_iter()
+        .map(|(amenity, distance_meters)| NearbyResult {
+            name: amenity.name.clone(),
+            location: [amenity.lat, amenity.lon],
+            distance_meters,
+        })
+        .collect();
+    Ok(Json(results))
+}
+
+async fn graph_stats(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RegionQuery>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let graph = state.graph_for(query.region.as_deref(), None).await?;
+    let nav = &graph.nav;
+    Ok(with_etag(&headers, &graph.etag, Json(StatsResponse {
+        nodes: nav.graph.node_count(),
+        edges: nav.graph.edge_count(),
+        components: nav.component_count(),
+        memory_estimate_bytes: nav.memory_estimate_bytes(),
+    })))
+}
+
+/// Build version and the defaults route requests fall back on
+async fn version(State(state): State<Arc<AppState>>) -> Json<VersionResponse> {
+    Json(VersionResponse { version: env!("CARGO_PKG_VERSION"), default_alpha: state.config.default_alpha })
+}
+
+/// Machine-readable contract of the main endpoints, for client code generators
+async fn openapi() -> Json<serde_json::Value> {
+    Json(openapi_document())
+}
+
+/// OpenAPI 3.1 description of the routing endpoints. The schemas are derived from the request
+/// and response types the handlers use, so they can't drift apart.
+fn openapi_document() -> serde_json::Value {
+    let mut generator = SchemaSettings::draft2020_12()
+        .with(|settings| settings.definitions_path = "/components/schemas".into())
+        .into_generator();
+    let route_request = generator.subschema_for::<RouteRequest>();
+    let route_response = generator.subschema_for::<RouteResponse>();
+    let alternatives_request = generator.subschema_for::<AlternativesRequest>();
+    let alternatives_response = generator.subschema_for::<AlternativesResponse>();
+    let pareto_response = generator.subschema_for::<Vec<ParetoRoute>>();
+    let matrix_request = generator.subschema_for::<MatrixRequest>();
+    let matrix_response = generator.subschema_for::<MatrixResponse>();
+    let many_to_one_request = generator.subschema_for::<ManyToOneRequest>();
+    let many_to_one_response = generator.subschema_for::<ManyToOneResponse>();
+    let nearby_response = generator.subschema_for::<Vec<NearbyResult>>();
+    let version_response = generator.subschema_for::<VersionResponse>();
+    let error_response = generator.subschema_for::<ErrorResponse>();
+
+    let body = |schema| json!({ "required": true, "content": { "application/json": { "schema": schema } } });
+    let ok = |schema| json!({ "description": "OK", "content": { "application/json": { "schema": schema } } });
+    let error = json!({ "description": "Invalid request or no route", "content": { "text/plain": { "schema": { "type": "string" } } } });
+    // Route errors are JSON, with a `code` telling "no path" from "too far from any road" apart
+    let route_error = |description| json!({ "description": description, "content": { "application/json": { "schema": error_response } } });
+    let route_errors = json!({
+        "400": route_error("Invalid request"),
+        "404": route_error("No route: a point couldn't be snapped, the points aren't connected, or no path fits the options"),
+        "503": route_error("The region's graph couldn't be loaded"),
+    });
+    let query = |name, required, schema, description| {
+        json!({ "name": name, "in": "query", "required": required, "schema": schema, "description": description })
+    };
+    let number = json!({ "type": "number" });
+    let string = json!({ "type": "string" });
+    let with_ok = |mut errors: serde_json::Value, response| {
+        errors["200"] = response;
+        errors
+    };
+    // `/pareto` runs its searches on a blocking task, which may fail
+    let mut search_errors = route_errors.clone();
+    search_errors["500"] = route_error("The search failed");
+
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": "SafeWalk routing API", "version": env!("CARGO_PKG_VERSION") },
+        "paths": {
+            "/route": {
+                "post": {
+                    "summary": "Safest walking route between two points",
+                    "requestBody": body(route_request.clone()),
+                    "responses": with_ok(route_errors.clone(), ok(route_response.clone())),
+                },
+                "get": {
+                    "summary": "Shareable form of POST /route with the basic parameters",
+                    "parameters": [
+                        query("from", true, string.clone(), "Origin as `lat,lon`"),
+                        query("to", true, string.clone(), "Destination as `lat,lon`"),
+                        query("alpha", false, number.clone(), "Safety preference"),
+                        query("region", false, string.clone(), "Graph to route on"),
+                    ],
+                    "responses": with_ok(route_errors.clone(), ok(route_response)),
+                },
+            },
+            "/routes": {
+                "post": {
+                    "summary": "Distinct alternative routes",
+                    "requestBody": body(alternatives_request),
+                    "responses": with_ok(route_errors, ok(alternatives_response)),
+                },
+            },
+            "/pareto": {
+                "post": {
+                    "summary": "Distance/risk trade-off routes over a sweep of alpha",
+                    "requestBody": body(route_request),
+                    "responses": with_ok(search_errors, ok(pareto_response)),
+                },
+            },
+            "/matrix": {
+                "post": {
+                    "summary": "Walking distances between every source and target",
+                    "requestBody": body(matrix_request),
+                    "responses": { "200": ok(matrix_response), "4XX": error },
+                },
+            },
+            "/many_to_one": {
+                "post": {
+                    "summary": "Route costs from many origins to one destination",
+                    "requestBody": body(many_to_one_request),
+                    "responses": { "200": ok(many_to_one_response), "4XX": error },
+                },
+            },
+            "/nearby": {
+                "get": {
+                    "summary": "Nearest amenities of one type",
+                    "parameters": [
+                        query("lat", true, number.clone(), "Latitude"),
+                        query("lon", true, number.clone(), "Longitude"),
+                        query("type", true, string, "`amenity` value, e.g. police"),
+                        query("k", false, json!({ "type": "integer", "minimum": 1, "maximum": MAX_NEARBY_COUNT }), "How many to return"),
+                    ],
+                    "responses": { "200": ok(nearby_response), "4XX": error },
+                },
+            },
+            "/version": {
+                "get": { "summary": "Build version and request defaults", "responses": { "200": ok(version_response) } },
+            },
+            "/health": {
+                "get": {
+                    "summary": "Liveness probe",
+                    "responses": { "200": { "description": "OK", "content": { "text/plain": { "schema": { "type": "string" } } } } },
+                },
+            },
+        },
+        "components": { "schemas": generator.take_definitions(true) },
+    })
+}
+
+/// The loaded regions with their extents, default region first
+async fn list_regions(State(state): State<Arc<AppState>>) -> Json<Vec<RegionSummary>> {
+    Json(state.regions.iter().map(summarize_region).collect())
+}
+
+fn summarize_region(region: &Region) -> RegionSummary {
+    let graph = region.loaded();
+    RegionSummary {
+        id: region.id.clone(),
+        bounds: region.bounds().map(|b| [b.min().x, b.min().y, b.max().x, b.max().y]),
+        loaded: graph.is_some(),
+        nodes: graph.as_ref().map(|g| g.nav.graph.node_count()),
+        edges: graph.as_ref().map(|g| g.nav.graph.edge_count()),
+        builds: region.builds.load(Ordering::SeqCst),
+    }
+}
+
+/// Tags a response that only changes when the graph does; answers `304 Not Modified`
+/// if the client already holds this version (`If-None-Match`)
+fn with_etag(headers: &HeaderMap, etag: &str, body: impl IntoResponse) -> Response {
+    let fresh = headers.get_all(header::IF_NONE_MATCH).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+
+    if fresh {
+        (StatusCode::NOT_MODIFIED, [(header::ETAG, etag.to_string())]).into_response()
+    } else {
+        ([(header::ETAG, etag.to_string())], body).into_response()
+    }
+}
+
+/// Identifies one loaded graph: changes whenever the graph is (re)built
+fn graph_etag(nav: &NavigationGraph) -> String {
+    let mut hasher = DefaultHasher::new();
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().hash(&mut hasher);
+    nav.graph.node_count().hash(&mut hasher);
+    nav.graph.edge_count().hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Rebuilds region graphs from their PBFs (e.g. after an OSM update) without downtime:
+/// requests keep using the old graphs until the new ones are swapped in. Reloads every
+/// loaded region unless `?region=` names one. Needs `SAFEWALK_ADMIN_TOKEN`.
+async fn reload_graph(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RegionQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<RegionSummary>>, (StatusCode, String)> {
+    check_admin_token(&state.config, &headers)?;
+    let targets: Vec<usize> = match query.region.as_deref() {
+        Some(id) => {
+            let region = state.regions.select(Some(id), None)?;
+            vec![state.regions.iter().position(|r| std::ptr::eq(r, region)).expect("region from this registry")]
+        }
+        // Regions nobody has used yet stay unloaded
+        None => state.regions.iter().enumerate().filter(|(_, r)| r.loaded().is_some()).map(|(i, _)| i).collect(),
+    };
+    let Some(_reloading) = ReloadGuard::acquire(&state.reloading) else {
+        return Err((StatusCode::CONFLICT, "a reload is already in progress".to_string()));
+    };
+    // Keep first-use builds of these regions from installing a graph alongside ours
+    let mut building = Vec::new();
+    for &i in &targets {
+        building.push(state.regions.regions[i].building.lock().await);
+    }
+
+    let worker = state.clone();
+    let incidents = state.incidents.read().unwrap().clone();
+    let applied = incidents.len();
+    let loaded = tokio::task::spawn_blocking(move || {
+        targets.into_iter()
+            .map(|i| {
+                let region = &worker.regions.regions[i];
+                worker.load_graph(&region.pbf_path, &incidents).map(|nav| (i, nav))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    }).await;
+    let graphs = match loaded {
+        Ok(Ok(graphs)) => graphs,
+        Ok(Err(e)) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("reload failed: {e}"))),
+    };
+
+    // Swap only once every requested region built, so a failure changes nothing
+    let mut summaries = Vec::new();
+    for (i, nav) in graphs {
+        let region = &state.regions.regions[i];
+        let graph = region.install(nav, applied);
+        tracing::info!(region = %region.id, nodes = graph.nav.graph.node_count(), edges = graph.nav.graph.edge_count(), "graph reloaded");
+        let summary = summarize_region(region);
+        summaries.push(summary);
+    }
+    drop(building);
+    // Cached answers describe the old graphs
+    state.invalidate_routes();
+    *state.deep_health.lock().unwrap() = None;
+    Ok(Json(summaries))
+}
+
+/// Adds a CSV batch of incidents (`lat,lon,severity[,timestamp]`) at runtime: the rows that
+/// parse are added together and applied to every loaded graph, malformed ones are reported.
+/// Graphs loaded later include them too. Needs `SAFEWALK_ADMIN_TOKEN`.
+async fn import_incidents(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ImportResponse>, (StatusCode, String)> {
+    check_admin_token(&state.config, &headers)?;
+    let (batch, errors) = incidents::parse_csv(&body);
+    if batch.is_empty() {
+        return Ok(Json(ImportResponse { imported: 0, errors }));
+    }
+    // A reload snapshots the incident list when it starts; don't add to it underneath
+    let Some(_reloading) = ReloadGuard::acquire(&state.reloading) else {
+        return Err((StatusCode::CONFLICT, "a reload is in progress, retry when it finishes".to_string()));
+    };
+
+    let imported = batch.len();
+    let total = {
+        let mut incidents = state.incidents.write().unwrap();
+        incidents.extend(batch);
+        *state.incident_layer.write().unwrap() = Arc::new(IncidentLayer::new(&incidents));
+        incidents.len()
+    };
+    for region in state.regions.iter() {
+        // Lazy loads read the list under this lock, so a graph either includes the batch or gets it here
+        let _building = region.building.lock().await;
+        let Some(graph) = region.loaded().filter(|g| g.incidents_applied < total) else { continue };
+        let (worker, missing) = (state.clone(), state.incidents.read().unwrap()[graph.incidents_applied..total].to_vec());
+        // Copy-on-write, so requests keep routing on the current graph meanwhile. Regions are
+        // updated one at a time, so at most one graph is held twice.
+        let updated = tokio::task::spawn_blocking(move || {
+            let mut nav = graph.nav.clone();
+            nav.add_incidents(&IncidentLayer::new(&missing), &worker.safety_map);
+            nav
+        }).await;
+        match updated {
+            Ok(nav) => {
+                region.replace(nav, total);
+            }
+            Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("applying incidents failed: {e}"))),
+        }
+    }
+
+    // Cached answers describe the old risk scores
+    state.invalidate_routes();
+    tracing::info!(imported, rejected = errors.len(), "incidents imported");
+    Ok(Json(ImportResponse { imported, errors }))
+}
+
+/// Admin endpoints are disabled unless a token is configured, and then require
+/// `Authorization: Bearer <token>`
+fn check_admin_token(config: &ServerConfig, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(expected) = &config.admin_token else {
+        return Err((StatusCode::FORBIDDEN, "admin endpoints are disabled (set SAFEWALK_ADMIN_TOKEN)".to_string()));
+    };
+    let given = headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if !given.is_some_and(|given| constant_time_eq(given.as_bytes(), expected.as_bytes())) {
+        return Err((StatusCode::UNAUTHORIZED, "missing or invalid admin token".to_string()));
+    }
+    Ok(())
+}
+
+/// Compares every byte instead of stopping at the first difference, so response times
+/// don't reveal how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && std::hint::black_box(a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y))) == 0
+}
+
+/// Holds `AppState::reloading` for one reload or incident import; released on drop, so a
+/// failed or cancelled request doesn't block the next one
+struct ReloadGuard<'a>(&'a AtomicBool);
+
+impl<'a> ReloadGuard<'a> {
+    /// `None` if a reload or import is already running
+    fn acquire(flag: &'a AtomicBool) -> Option<Self> {
+        (!flag.swap(true, Ordering::SeqCst)).then_some(Self(flag))
+    }
+}
+
+impl Drop for ReloadGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Health check that proves the graph can actually route: probes a path between two
+/// pseudo-randomly chosen nodes of the largest component. Results are cached briefly.
+async fn deep_health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let cached = state.deep_health.lock().unwrap().as_ref()
+        .filter(|(at, _)| at.elapsed() < DEEP_HEALTH_TTL)
+        .map(|(_, report)| report.clone());
+    let report = match cached {
+        Some(report) => report,
+        None => {
+            // Routing off the async workers, and without the lock: concurrent checks may
+            // probe twice, but never queue up behind one another
+            let worker = state.clone();
+            let report = tokio::task::spawn_blocking(move || probe_regions(&worker.regions))
+                .await
+                .unwrap_or_else(|e| DeepHealth { healthy: false, detail: format!("probe failed: {e}") });
+            *state.deep_health.lock().unwrap() = Some((Instant::now(), report.clone()));
+            report
+        }
+    };
+
+    let status = if report.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
+
+/// Probes every loaded region; healthy only if all of them are
+fn probe_regions(regions: &RegionRegistry) -> DeepHealth {
+    let reports: Vec<_> = regions.iter()
+        .filter_map(|r| Some((r.id.as_str(), probe_route(&r.loaded()?.nav))))
+        .collect();
+    if let [(_, report)] = reports.as_slice() {
+        return report.clone();
+    }
+    DeepHealth {
+        healthy: reports.iter().all(|(_, r)| r.healthy),
+        detail: reports.iter().map(|(id, r)| format!("{id}: {}", r.detail)).collect::<Vec<_>>().join("; "),
+    }
+}
+
+fn probe_route(nav: &NavigationGraph) -> DeepHealth {
+    let unhealthy = |detail: &str| DeepHealth { healthy: false, detail: detail.to_string() };
+
+    let Some(component) = nav.largest_component() else {
+        return unhealthy("graph is empty");
+    };
+    let nodes: Vec<_> = nav.component_nodes(component).collect();
+    if nodes.len() < 2 {
+        return unhealthy("largest component has fewer than two nodes");
+    }
+
+    // Clock-derived picks are random enough to vary the probe between checks
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos() as usize).unwrap_or(0);
+    let first = seed % nodes.len();
+    let second = (first + 1 + (seed / 7) % (nodes.len() - 1)) % nodes.len(); // never == first
+    let (start, end) = (nodes[first], nodes[second]);
+
+    match nav.route(start, end, &RouteOptions::default()) {
+        Some((_, path)) => DeepHealth {
+            healthy: true,
+            detail: format!("routed {} nodes across a component of {}", path.len(), nodes.len()),
+        },
+        None => unhealthy("no route between two nodes of the largest component"),
+    }
+}
+
+#[cfg(test)]
+mod osm_fixture;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_fixture::{FixtureFile, OsmFixture};
+
+    /// `[lat, lon]` of node `id` of `town`: a 3x3 grid 0.001° (~100 m) apart, numbered
+    /// 1-9 row by row from the south-west corner
+    fn at(id: i64) -> [f64; 2] {
+        let i = id - 1;
+        [30.34 + (i / 3) as f64 * 0.001, 76.37 + (i % 3) as f64 * 0.001]
+    }
+
+    /// Three named residential streets running east-west, linked by footways
+    fn town() -> OsmFixture {
+        let mut osm = OsmFixture::default();
+        for id in 1..=9 {
+            osm.node(id, at(id)[0], at(id)[1], &[]);
+        }
+        osm.way(100, &[1, 2, 3], &[("highway", "residential"), ("name", "Mall Road")])
+            .way(101, &[4, 5, 6], &[("highway", "residential"), ("name", "Lower Mall")])
+            .way(102, &[7, 8, 9], &[("highway", "residential"), ("name", "Upper Mall")])
+            .way(103, &[1, 4, 7], &[("highway", "footway")])
+            .way(104, &[2, 5, 8], &[("highway", "footway")])
+            .way(105, &[3, 6, 9], &[("highway", "footway")]);
+        osm
+    }
+
+    /// Server state with `osm` as its only region; keep the file alive while routing
+    fn state(osm: &OsmFixture, config: ServerConfig) -> (FixtureFile, Arc<AppState>) {
+        let file = osm.write();
+        let region = RegionConfig { id: "default".to_string(), pbf_path: file.path().to_string(), bbox: None };
+        let config = ServerConfig { regions: vec![region], ..config };
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        (file, Arc::new(build_state(config, safety_map).unwrap()))
+    }
+
+    fn request(origin: i64, destination: i64) -> RouteRequest {
+        RouteRequest { origin: at(origin), destination: at(destination), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn warmed_routes_are_cache_hits() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let trips = vec![request(1, 9), RouteRequest { alpha: Some(0.0), ..request(3, 7) }];
+
+        let Json(summary) = warmup_routes(State(state.clone()), Json(trips.clone())).await.unwrap();
+        assert_eq!((summary.requested, summary.computed, summary.cached), (2, 2, 2));
+        for trip in trips {
+            let response = calculate_route(State(state.clone()), Json(trip)).await.into_response();
+            assert_eq!(response.headers()[CACHE_HEADER], "HIT");
+        }
+        let response = calculate_route(State(state), Json(request(1, 3))).await.into_response();
+        assert_eq!(response.headers()[CACHE_HEADER], "MISS");
+    }
+
+    #[tokio::test]
+    async fn polyline_waypoints_are_visited_in_order() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        // Along Mall Road, but by way of the far corner of the grid
+        let trip = RouteRequest { waypoints_polyline: Some(polyline::encode(&[at(7)])), ..request(1, 3) };
+
+        let response = serve_route(&state, trip).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let coordinates = route["geometry"]["coordinates"].as_array().unwrap();
+        let visited = route["waypoint_indices"][0].as_u64().unwrap() as usize;
+        let [lon, lat] = [0, 1].map(|i| coordinates[visited][i].as_f64().unwrap());
+        assert!(haversine_meters([lat, lon], at(7)) < 0.1);
+        assert!(route["total_distance"].as_f64().unwrap() > 500.0, "a detour, not the direct 200 m");
+
+        let garbled = RouteRequest { waypoints_polyline: Some("_p~iF~ps|U_".to_string()), ..request(1, 3) };
+        assert_eq!(serve_route(&state, garbled).await.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_generated_or_echoed() {
+        use tower::ServiceExt;
+
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let get = |id: Option<&str>| {
+            let mut request = Request::get("/health");
+            if let Some(id) = id {
+                request = request.header(REQUEST_ID_HEADER, id);
+            }
+            app(state.clone()).oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let generated = get(None).await.unwrap();
+        assert!(!generated.headers()[REQUEST_ID_HEADER].is_empty());
+        let echoed = get(Some("abc-123")).await.unwrap();
+        assert_eq!(echoed.headers()[REQUEST_ID_HEADER], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn safest_of_prefers_a_farther_safe_candidate() {
+        // East of the origin a fast unlit road, west a short unlit street, north a lit footway
+        // about 2 km long: the safest on average, though its length gives it the most total risk
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.37, &[])
+            .node(2, 30.34, 76.371, &[])
+            .node(3, 30.341, 76.37, &[])
+            .node(4, 30.36, 76.37, &[])
+            .node(5, 30.34, 76.369, &[])
+            .way(100, &[1, 2], &[("highway", "primary"), ("sidewalk", "both"), ("maxspeed", "80"), ("lit", "no")])
+            .way(101, &[1, 3, 4], &[("highway", "footway"), ("lit", "yes")])
+            .way(102, &[1, 5], &[("highway", "residential"), ("lit", "no")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let safest = |candidates: Vec<[f64; 2]>| {
+            let state = state.clone();
+            async move {
+                let request = SafestOfRequest { origin: [30.34, 76.37], candidates, alpha: Some(2.0) };
+                safest_of(State(state), Json(request)).await.unwrap().0
+            }
+        };
+
+        let winner = safest(vec![[30.34, 76.37], [30.34, 76.371], [30.34, 76.369], [30.36, 76.37]]).await;
+        assert_eq!(winner.index, 3, "neither the origin itself nor the nearer, riskier streets");
+        let street = safest(vec![[30.34, 76.369]]).await;
+        assert!(winner.route.total_risk > street.route.total_risk, "ranked by average, not total risk");
+
+        // Both ends of the footway are equally safe on average; the nearer one wins
+        assert_eq!(safest(vec![[30.36, 76.37], [30.341, 76.37]]).await.index, 1);
+    }
+
+    #[test]
+    fn nearby_coordinates_share_a_cache_key() {
+        let config = ServerConfig { coordinate_decimals: 6, ..ServerConfig::default() };
+        let key = |origin: [f64; 2]| {
+            let mut request = RouteRequest { origin, destination: at(9), ..Default::default() };
+            normalize_request(&mut request, &config);
+            cache_key(&request, 0)
+        };
+        assert_eq!(key([30.3400001, 76.3700004]), key([30.3400004, 76.3699998]));
+        assert_ne!(key([30.340001, 76.37]), key([30.34, 76.37]));
+    }
+
+    #[tokio::test]
+    async fn matrix_leaves_off_graph_points_null() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let far = [30.5, 76.5];
+        let request = MatrixRequest { sources: vec![at(1), far], targets: vec![at(3), far], alpha: Some(0.0), include_geometry: false };
+
+        let Json(matrix) = route_matrix(State(state), Json(request)).await.unwrap();
+        assert!(matrix.distances[0][0].is_some_and(|d| (d - haversine_meters(at(1), at(3))).abs() < 1.0));
+        assert_eq!([matrix.distances[0][1], matrix.distances[1][0], matrix.distances[1][1]], [None; 3]);
+    }
+
+    #[tokio::test]
+    async fn one_way_trip_has_no_return_route() {
+        // Steps that may only be walked from 1 to 2
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.34, 76.37, &[])
+            .node(2, 30.34, 76.371, &[])
+            .way(100, &[1, 2], &[("highway", "steps"), ("oneway:foot", "yes")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let trip = RouteRequest { origin: [30.34, 76.37], destination: [30.34, 76.371], return_route: true, ..Default::default() };
+
+        let response = serve_route(&state, trip).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(route["total_distance"].as_f64().unwrap() > 90.0);
+        assert!(route.get("return_route").is_none());
+    }
+
+    #[tokio::test]
+    async fn reload_swaps_in_the_rebuilt_graph() {
+        let config = ServerConfig { admin_token: Some("secret".to_string()), ..ServerConfig::default() };
+        let (file, state) = state(&town(), config);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        // The town shrinks to Mall Road alone
+        let mut osm = OsmFixture::default();
+        for id in 1..=3 {
+            osm.node(id, at(id)[0], at(id)[1], &[]);
+        }
+        osm.way(100, &[1, 2, 3], &[("highway", "residential"), ("name", "Mall Road")]);
+        std::fs::copy(osm.write().path(), file.path()).unwrap();
+
+        let query = Query(RegionQuery { region: None });
+        let Json(summaries) = reload_graph(State(state.clone()), query, headers).await.unwrap();
+        assert_eq!((summaries[0].nodes, summaries[0].edges, summaries[0].builds), (Some(3), Some(4), 2));
+        assert_eq!(state.graph_for(None, None).await.ok().unwrap().nav.graph.node_count(), 3);
+        assert!(!state.reloading.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn concurrent_first_requests_build_once() {
+        let (town, far) = (town().write(), town().write());
+        let region = |id: &str, file: &FixtureFile, bbox| RegionConfig { id: id.to_string(), pbf_path: file.path().to_string(), bbox };
+        let config = ServerConfig {
+            regions: vec![region("default", &town, None), region("hills", &far, Some([77.0, 31.0, 77.1, 31.1]))],
+            ..ServerConfig::default()
+        };
+        let state = Arc::new(build_state(config, SafetyMap::new(SafetyConfig::default()).unwrap()).unwrap());
+
+        let requests: Vec<_> = (0..4)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move { state.graph_for(Some("hills"), None).await.is_ok() })
+            })
+            .collect();
+        for request in requests {
+            assert!(request.await.unwrap());
+        }
+        assert_eq!(state.regions.regions[1].builds.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn detour_is_the_distance_beyond_the_straight_line() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        // Off the graph at both ends, and diagonally across the grid
+        let trip = RouteRequest { origin: [30.3399, 76.3699], destination: at(9), ..Default::default() };
+
+        let response = serve_route(&state, trip.clone()).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let [total, straight, detour] = ["total_distance", "straight_line_distance", "detour_meters"].map(|k| route[k].as_f64().unwrap());
+        assert!((straight - haversine_meters(trip.origin, trip.destination)).abs() < 0.01, "{straight}");
+        assert!((detour - (total - straight)).abs() < 1e-6);
+        assert!(detour > 0.0, "a grid walk is longer than the diagonal");
+    }
+
+    #[tokio::test]
+    async fn detour_budget_falls_back_to_the_shortest_route() {
+        // 200 m along a fast unlit road, or 400 m around it on a lit footway
+        let mut osm = OsmFixture::default();
+        for id in 1..=6 {
+            osm.node(id, at(id)[0], at(id)[1], &[]);
+        }
+        osm.way(100, &[1, 2, 3], &[("highway", "primary"), ("sidewalk", "both"), ("maxspeed", "80"), ("lit", "no")])
+            .way(101, &[1, 4, 5, 6, 3], &[("highway", "footway"), ("lit", "yes")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+        let route = |max_extra_meters| {
+            let trip = RouteRequest { alpha: Some(10.0), max_extra_meters, ..request(1, 3) };
+            compute_route(&graph.nav, &trip, &[], None, state.config.max_snap_meters).unwrap()
+        };
+
+        let safer = route(Some(500.0));
+        assert!(!safer.fell_back_to_fastest && safer.total_distance > 350.0, "{}", safer.total_distance);
+        let fastest = route(Some(100.0));
+        assert!(fastest.fell_back_to_fastest && fastest.total_distance < 250.0, "{}", fastest.total_distance);
+    }
+
+    #[tokio::test]
+    async fn debug_route_lists_the_edges_walked() {
+        let config = ServerConfig { admin_token: Some("secret".to_string()), ..ServerConfig::default() };
+        let (_file, state) = state(&town(), config);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+
+        let response = debug_route(State(state), headers, Json(request(1, 3))).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let debug: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(debug["osm_ids"], json!([1, 2, 3]));
+        assert_eq!(debug["edges"].as_array().unwrap().len(), 2);
+        assert_eq!(debug["edges"][0]["way_id"], 100);
+    }
+
+    #[tokio::test]
+    async fn safest_of_defaults_to_the_server_alpha() {
+        let config = ServerConfig { default_alpha: 3.5, ..ServerConfig::default() };
+        let (_file, state) = state(&town(), config);
+        let request: SafestOfRequest = serde_json::from_value(json!({ "origin": at(1), "candidates": [at(9)] })).unwrap();
+        assert_eq!(request.alpha, None);
+
+        let Json(winner) = safest_of(State(state), Json(request)).await.unwrap();
+        assert_eq!(winner.route.applied.options.alpha, 3.5);
+    }
+
+    #[tokio::test]
+    async fn matrix_defaults_to_the_server_alpha() {
+        // From 1 to 3 a short fast road, or a longer lit residential street
+        let mut osm = OsmFixture::default();
+        for id in 1..=6 {
+            osm.node(id, at(id)[0], at(id)[1], &[]);
+        }
+        osm.way(100, &[1, 2, 3], &[("highway", "primary"), ("sidewalk", "both"), ("maxspeed", "80"), ("lit", "no")])
+            .way(101, &[1, 4, 5, 6, 3], &[("highway", "residential"), ("lit", "yes")]);
+        let (_file, state) = state(&osm, ServerConfig { default_alpha: 10.0, ..ServerConfig::default() });
+        let distance = |body: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                let request: MatrixRequest = serde_json::from_value(body).unwrap();
+                route_matrix(State(state), Json(request)).await.unwrap().0.distances[0][0].unwrap()
+            }
+        };
+
+        let shortest = distance(json!({ "sources": [at(1)], "targets": [at(3)], "alpha": 0.0 })).await;
+        assert!((shortest - haversine_meters(at(1), at(3))).abs() < 1.0, "{shortest} m");
+        let safest = distance(json!({ "sources": [at(1)], "targets": [at(3)] })).await;
+        assert!(safest > 2.0 * shortest, "alpha 10 takes the residential street: {safest} m");
+    }
+
+    #[tokio::test]
+    async fn applied_options_echo_the_profile_and_defaults() {
+        let config = ServerConfig { default_alpha: 1.5, ..ServerConfig::default() };
+        let (_file, state) = state(&town(), config);
+        let trip = RouteRequest { profile: Profile::Wheelchair, prefer_shade: -2.0, ..request(1, 9) };
+
+        let response = serve_route(&state, trip).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let applied = &route["applied"];
+        assert_eq!(applied["profile"], "wheelchair");
+        assert_eq!(applied["max_incline_percent"], 12.0);
+        assert_eq!(applied["alpha"], 1.5, "the server default; profiles don't imply an alpha");
+        assert_eq!(applied["prefer_shade"], 0.0);
+        assert_eq!(applied["custom_cost"], false);
+    }
+
+    #[tokio::test]
+    async fn pareto_routes_dominate_none_of_each_other() {
+        // Three ways from 1 to 3: a short fast road, a middle residential street, a long lit footway
+        let mut osm = OsmFixture::default();
+        for id in 1..=9 {
+            osm.node(id, at(id)[0], at(id)[1], &[]);
+        }
+        osm.way(100, &[1, 2, 3], &[("highway", "primary"), ("sidewalk", "both"), ("maxspeed", "80"), ("lit", "no")])
+            .way(101, &[1, 4, 5, 6, 3], &[("highway", "residential"), ("lit", "yes")])
+            .way(102, &[4, 7, 8, 9, 6], &[("highway", "footway"), ("lit", "yes")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+
+        let frontier = pareto_frontier(&graph.nav, &request(1, 3), state.config.max_snap_meters);
+        assert!(frontier.len() >= 2, "{} routes", frontier.len());
+        for (i, a) in frontier.iter().enumerate() {
+            for b in &frontier[i + 1..] {
+                let dominates = |x: &ParetoRoute, y: &ParetoRoute| x.total_distance <= y.total_distance && x.total_risk <= y.total_risk;
+                assert!(!dominates(a, b) && !dominates(b, a), "alpha {} vs {}", a.alpha, b.alpha);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn point_risk_is_raised_only_near_incidents() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let incident = Incident { lat: at(5)[0], lon: at(5)[1], severity: 1.0, timestamp: None };
+        *state.incident_layer.write().unwrap() = Arc::new(IncidentLayer::new(&[incident]));
+
+        let risk = |point: [f64; 2]| point_risk(State(state.clone()), Query(PointQuery { lat: point[0], lon: point[1] }));
+        let near = risk(at(5)).await.unwrap().0;
+        assert!(near.incidents > 0.0 && near.risk == near.incidents && near.raster.is_none());
+        // Node 1 is ~150 m from the incident, beyond INCIDENT_RADIUS_METERS
+        assert_eq!(risk(at(1)).await.unwrap().0.risk, 0.0);
+    }
+
+    #[tokio::test]
+    async fn risk_grid_peaks_at_the_incident_cell() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let incident = Incident { lat: at(5)[0], lon: at(5)[1], severity: 1.0, timestamp: None };
+        *state.incident_layer.write().unwrap() = Arc::new(IncidentLayer::new(&[incident]));
+
+        let bbox = [76.3702, 30.3403, 76.3722, 30.3423];
+        let request = RiskGridRequest { bbox, resolution_meters: 25.0 };
+        let grid = risk_grid(State(state), Json(request)).await.unwrap().0;
+        let (row, col) = (0..grid.rows)
+            .flat_map(|row| (0..grid.cols).map(move |col| (row, col)))
+            .max_by(|&(r1, c1), &(r2, c2)| grid.risk[r1][c1].total_cmp(&grid.risk[r2][c2]))
+            .unwrap();
+
+        // Rows run from the north edge
+        let (cell_lon, cell_lat) = ((bbox[2] - bbox[0]) / grid.cols as f64, (bbox[3] - bbox[1]) / grid.rows as f64);
+        let north = bbox[3] - row as f64 * cell_lat;
+        let west = bbox[0] + col as f64 * cell_lon;
+        assert!((north - cell_lat..north).contains(&incident.lat), "peak in row {row}");
+        assert!((west..west + cell_lon).contains(&incident.lon), "peak in column {col}");
+        assert_eq!(grid.risk[0][0], 0.0, "the corners are beyond the incident radius");
+    }
+
+    #[tokio::test]
+    async fn straight_street_is_one_step() {
+        let mut osm = OsmFixture::default();
+        for id in 1..=10 {
+            osm.node(id, 30.34, 76.37 + id as f64 * 0.0003, &[]);
+        }
+        osm.way(100, &(1..=10).collect::<Vec<_>>(), &[("highway", "residential"), ("name", "Mall Road")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+        let nav = &graph.nav;
+
+        let request = RouteRequest { origin: [30.34, 76.3703], destination: [30.34, 76.373], ..Default::default() };
+        let (nodes, _) = route_nodes(nav, &request, &[], None, state.config.max_snap_meters).unwrap();
+        assert_eq!(nodes.len(), 10);
+        let steps = route_steps(nav, &nodes, Language::English);
+        assert_eq!(steps.len(), 1);
+        assert!(matches!(steps[0].maneuver, Maneuver::Depart));
+        assert_eq!(steps[0].street.as_deref(), Some("Mall Road"));
+        let length = haversine_meters([30.34, 76.3703], [30.34, 76.373]);
+        assert!((steps[0].distance_meters - length).abs() < 1.0, "{} m", steps[0].distance_meters);
+    }
+
+    #[tokio::test]
+    async fn hindi_steps_are_translated() {
+        assert_eq!(Language::Hindi.instruction(Maneuver::Left, Some("Mall Road")), "Mall Road पर बाएँ मुड़ें");
+        assert_eq!(Language::Hindi.instruction(Maneuver::Right, None), "दाएँ मुड़ें");
+        assert!(matches!(Language::from_code("hi-IN"), Language::Hindi));
+        assert!(matches!(Language::from_code("xx"), Language::English), "unknown codes fall back to English");
+
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let instructions = |language: &str| {
+            let request = RouteRequest { steps: true, language: Some(language.to_string()), ..request(1, 9) };
+            let state = state.clone();
+            async move {
+                let response = serve_route(&state, request).await.into_response();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                route["steps"].as_array().unwrap().iter().map(|step| step["instruction"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+            }
+        };
+
+        let (english, hindi) = (instructions("en").await, instructions("hi").await);
+        assert!(english.len() >= 2, "1 to 9 turns at least once: {english:?}");
+        assert_eq!(hindi.len(), english.len());
+        for (english, hindi) in english.iter().zip(&hindi) {
+            assert!(hindi.chars().any(|c| ('\u{0900}'..='\u{097f}').contains(&c)), "{hindi} is in Devanagari");
+            assert_ne!(hindi, english);
+        }
+        assert_eq!(instructions("xx").await, english);
+    }
+
+    #[tokio::test]
+    async fn footpath_counts_its_road_crossings() {
+        // A footpath running north crosses two east-west streets at crossing nodes 2 and 4
+        let mut osm = OsmFixture::default();
+        osm.node(1, 30.340, 76.370, &[])
+            .node(2, 30.341, 76.370, &[("highway", "crossing"), ("crossing", "marked")])
+            .node(3, 30.342, 76.370, &[])
+            .node(4, 30.343, 76.370, &[("highway", "crossing"), ("crossing", "traffic_signals")])
+            .node(5, 30.344, 76.370, &[])
+            .node(10, 30.341, 76.369, &[])
+            .node(11, 30.341, 76.371, &[])
+            .node(12, 30.343, 76.369, &[])
+            .node(13, 30.343, 76.371, &[])
+            .way(100, &[1, 2, 3, 4, 5], &[("highway", "footway")])
+            .way(101, &[10, 2, 11], &[("highway", "residential"), ("name", "Mall Road")])
+            .way(102, &[12, 4, 13], &[("highway", "residential"), ("name", "Lower Mall")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let crossings = |origin: [f64; 2], destination: [f64; 2]| {
+            let state = state.clone();
+            async move {
+                let request = RouteRequest { origin, destination, ..Default::default() };
+                let response = serve_route(&state, request).await.into_response();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()["crossings"].as_u64().unwrap()
+            }
+        };
+
+        assert_eq!(crossings([30.340, 76.370], [30.344, 76.370]).await, 2);
+        assert_eq!(crossings([30.340, 76.370], [30.342, 76.370]).await, 1);
+        assert_eq!(crossings([30.341, 76.369], [30.341, 76.371]).await, 0, "walking along the street crosses nothing");
+    }
+
+    #[tokio::test]
+    async fn waypoint_indices_mark_each_via_point_and_the_destination() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let trip = RouteRequest { waypoints_polyline: Some(polyline::encode(&[at(7), at(9)])), ..request(1, 3) };
+
+        let response = serve_route(&state, trip).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let coordinates = route["geometry"]["coordinates"].as_array().unwrap();
+        let indices: Vec<usize> = route["waypoint_indices"].as_array().unwrap().iter().map(|i| i.as_u64().unwrap() as usize).collect();
+
+        // 1 -> 4 -> 7 -> 8 -> 9 -> 6 -> 3
+        assert_eq!(indices, [2, 4, 6]);
+        assert_eq!(indices[2], coordinates.len() - 1);
+        for (&index, stop) in indices.iter().zip([at(7), at(9), at(3)]) {
+            let [lon, lat] = [0, 1].map(|i| coordinates[index][i].as_f64().unwrap());
+            assert!(haversine_meters([lat, lon], stop) < 0.1, "index {index}");
+        }
+    }
+
+    #[tokio::test]
+    async fn total_risk_grows_with_length() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+        let route = |destination| compute_route(&graph.nav, &request(1, destination), &[], None, state.config.max_snap_meters).unwrap();
+
+        // One and two blocks along the same street: same risk per meter, twice the exposure
+        let (one, two) = (route(2), route(3));
+        assert!((one.average_safety - two.average_safety).abs() < 1e-6);
+        assert!(two.total_risk > one.total_risk);
+        assert!((two.total_risk / one.total_risk - two.total_distance / one.total_distance).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn missing_certificate_fails_with_its_path() {
+        let tls = TlsConfig { cert_path: "/nonexistent/cert.pem".to_string(), key_path: "/nonexistent/key.pem".to_string() };
+        let error = load_tls(&tls).await.err().unwrap().to_string();
+        assert!(error.contains("/nonexistent/cert.pem") && error.contains("/nonexistent/key.pem"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn waypoints_over_the_limit_are_rejected() {
+        let (_file, state) = state(&town(), ServerConfig { max_waypoints: 2, ..ServerConfig::default() });
+        let with_stops = |stops: &[i64]| {
+            let points: Vec<[f64; 2]> = stops.iter().map(|&id| at(id)).collect();
+            RouteRequest { waypoints_polyline: Some(polyline::encode(&points)), ..request(1, 3) }
+        };
+
+        assert_eq!(serve_route(&state, with_stops(&[4, 7])).await.into_response().status(), StatusCode::OK);
+        let response = serve_route(&state, with_stops(&[4, 7, 8])).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("at most 2 waypoints"));
+    }
+
+    #[tokio::test]
+    async fn slow_requests_are_warned_about() {
+        use tower::ServiceExt;
+
+        /// Collects the formatted log lines
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let logs = Captured::default();
+        let writer = logs.clone();
+        // The test runtime is single-threaded, so the subscriber covers the whole request
+        let _subscriber = tracing::subscriber::set_default(
+            tracing_subscriber::fmt().with_writer(move || writer.clone()).with_ansi(false).finish(),
+        );
+
+        let call = |threshold: Option<Duration>| {
+            let app = Router::new()
+                .route("/", get(|| async { tokio::time::sleep(Duration::from_millis(20)).await }))
+                .layer(middleware::from_fn_with_state(threshold, log_slow_requests));
+            app.oneshot(Request::get("/").body(Body::empty()).unwrap())
+        };
+        call(Some(Duration::from_secs(60))).await.unwrap();
+        call(None).await.unwrap();
+        assert!(!String::from_utf8_lossy(&logs.0.lock().unwrap()).contains("slow request"));
+
+        call(Some(Duration::from_millis(5))).await.unwrap();
+        let logged = String::from_utf8_lossy(&logs.0.lock().unwrap()).to_string();
+        assert!(logged.contains("WARN") && logged.contains("slow request") && logged.contains("status=200"), "{logged}");
+    }
+
+    #[tokio::test]
+    async fn errors_answer_with_their_status_and_code() {
+        let point = || SnappedPoint { node: Some(0), component: Some(0), beyond_max_snap: false };
+        let cases = [
+            (SafewalkError::NoRoute { origin: point(), destination: point() }, StatusCode::NOT_FOUND, "no_route"),
+            (SafewalkError::SnapFailed { origin: point(), destination: point() }, StatusCode::NOT_FOUND, "snap_failed"),
+            (SafewalkError::DisconnectedEndpoints { origin: point(), destination: point() }, StatusCode::NOT_FOUND, "disconnected_endpoints"),
+            (SafewalkError::InvalidInput("bad".to_string()), StatusCode::BAD_REQUEST, "invalid_input"),
+            (SafewalkError::GraphNotLoaded("gone".to_string()), StatusCode::SERVICE_UNAVAILABLE, "graph_not_loaded"),
+            (SafewalkError::Internal("oops".to_string()), StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        ];
+        for (error, status, code) in cases {
+            let response = error.into_response();
+            assert_eq!(response.status(), status, "{code}");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], code);
+            assert_eq!(body["origin"].is_object(), status == StatusCode::NOT_FOUND, "{code}");
+        }
+
+        // Handlers beyond /route answer in the same shape
+        let (_file, state) = state(&town(), ServerConfig::default());
+        let request = AlternativesRequest { route: request(1, 3), alternatives: 0, seed: None };
+        let response = calculate_alternatives(State(state), Json(request)).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["code"], "invalid_input");
+    }
+
+    #[tokio::test]
+    async fn openapi_document_describes_route() {
+        let response = openapi().await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(document["paths"]["/route"]["post"]["requestBody"].is_object());
+        let error_schema = &document["paths"]["/route"]["post"]["responses"]["404"]["content"]["application/json"]["schema"];
+        assert!(error_schema.is_object());
+        for path in ["/routes", "/pareto"] {
+            let not_found = &document["paths"][path]["post"]["responses"]["404"]["content"]["application/json"]["schema"];
+            assert_eq!(not_found, error_schema, "{path} answers 404 with the JSON error");
+        }
+        let reference = error_schema["$ref"].as_str().unwrap();
+        let name = reference.strip_prefix("#/components/schemas/").unwrap();
+        assert!(document["components"]["schemas"][name].is_object(), "{reference} resolves");
+    }
+
+    #[tokio::test]
+    async fn many_to_one_costs_match_single_routes() {
+        let (_file, state) = state(&town(), ServerConfig::default());
+        // The last origin lies some 20 km north of the town, beyond any snapping radius
+        let origins = vec![at(1), at(5), at(9), at(3), [30.52, 76.37]];
+        let request = ManyToOneRequest { origins: origins.clone(), destination: at(3), alpha: Some(2.0) };
+        let Json(response) = many_to_one(State(state.clone()), Json(request)).await.unwrap();
+
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+        let nav = &graph.nav;
+        let options = RouteOptions { alpha: 2.0, service_penalty: state.config.service_penalty, ..Default::default() };
+        let max_snap = state.config.max_snap_meters;
+        let end = nav.find_nearest_node_within(at(3)[0], at(3)[1], max_snap).unwrap();
+        for (i, origin) in origins[..4].iter().enumerate() {
+            let start = nav.find_nearest_node_within(origin[0], origin[1], max_snap).unwrap();
+            let (cost, _) = nav.route(start, end, &options).unwrap();
+            assert!((response.costs[i].unwrap() - cost).abs() < 1e-6, "origin {i}");
+        }
+        assert_eq!(response.costs[3], Some(0.0));
+        assert_eq!(response.distances[3], Some(0.0));
+        assert_eq!((response.costs[4], response.distances[4]), (None, None));
+    }
+
+    #[tokio::test]
+    async fn elevation_profile_spans_the_route() {
+        // A street climbing from node 1 to 3, with only the middle node untagged
+        let mut osm = OsmFixture::default();
+        osm.node(1, at(1)[0], at(1)[1], &[("ele", "250")])
+            .node(2, at(2)[0], at(2)[1], &[])
+            .node(3, at(3)[0], at(3)[1], &[("ele", "260")])
+            .way(100, &[1, 2, 3], &[("highway", "residential")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+
+        let trip = RouteRequest { elevation_profile: true, ..request(1, 3) };
+        let route = compute_route(&graph.nav, &trip, &[], None, state.config.max_snap_meters).unwrap();
+        let profile = route.elevation_profile.unwrap();
+        assert_eq!(profile.len(), 3);
+        assert_eq!(profile[0], (0.0, 250.0));
+        let (end, ele) = profile[2];
+        assert!((end - route.total_distance).abs() < 1e-6 && ele == 260.0);
+        assert!((profile[1].1 - 255.0).abs() < 0.1, "interpolated halfway");
+    }
+
+    #[tokio::test]
+    async fn narrow_corridor_forces_the_direct_route() {
+        // A risky road straight from 1 to 3, and lit footways around by the far side of the grid
+        let mut osm = OsmFixture::default();
+        for id in 1..=9 {
+            osm.node(id, at(id)[0], at(id)[1], &[]);
+        }
+        osm.way(100, &[1, 2, 3], &[("highway", "primary"), ("sidewalk", "both"), ("lit", "no"), ("maxspeed", "80")])
+            .way(101, &[1, 4, 7, 8, 9, 6, 3], &[("highway", "footway"), ("lit", "yes")]);
+        let (_file, state) = state(&osm, ServerConfig::default());
+        let graph = state.graph_for(None, None).await.ok().unwrap();
+        let nav = &graph.nav;
+        let (from, to) = (nav.find_nearest_node(at(1)[0], at(1)[1]).unwrap(), nav.find_nearest_node(at(3)[0], at(3)[1]).unwrap());
+        let options = RouteOptions { alpha: 10.0, ..Default::default() };
+        let length = |width| {
+            let edges = route_leg(nav, from, to, &options, None, width).unwrap();
+            path_distance_and_risk(nav, &edges).0
+        };
+
+        let direct = haversine_meters(at(1), at(3));
+        assert!(length(Some(2000.0)) > 2.0 * direct, "a wide corridor keeps the safe detour");
+        assert!((length(Some(50.0)) - direct).abs() < 1.0, "the detour lies outside a 50 m corridor");
+
+        let negative = RouteRequest { corridor_width_meters: Some(-1.0), ..request(1, 3) };
+        assert_eq!(pareto_routes(State(state.clone()), Json(negative)).await.into_response().status(), StatusCode::BAD_REQUEST);
+        let auto = AutoRouteRequest { origin: at(1), destination: at(3), max_detour_ratio: 1.5, corridor_width_meters: Some(0.0), region: None };
+        assert_eq!(calculate_auto_route(State(state), Json(auto)).await.into_response().status(), StatusCode::BAD_REQUEST);
     }
 }
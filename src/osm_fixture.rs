@@ -0,0 +1,235 @@
+//! Writes small OSM PBF files for tests, so loaders can be exercised without a real extract.
+//!
+//! Only what `osmpbf` needs to read the data back is written: uncompressed blobs, one
+//! primitive group per element type, dense nodes and no metadata.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tags as `(key, value)` pairs
+type Tags = Vec<(String, String)>;
+
+/// Relation members as `(kind, id, role)`
+type Members = Vec<(Member, i64, String)>;
+
+/// Relation member kinds, numbered as in the PBF format
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub enum Member {
+    Node = 0,
+    Way = 1,
+    Relation = 2,
+}
+
+/// Nodes, ways and relations to write, in insertion order
+#[derive(Default)]
+pub struct OsmFixture {
+    nodes: Vec<(i64, f64, f64, Tags)>,
+    ways: Vec<(i64, Vec<i64>, Tags)>,
+    relations: Vec<(i64, Members, Tags)>,
+}
+
+/// A written fixture; the file is removed on drop
+pub struct FixtureFile(PathBuf);
+
+impl FixtureFile {
+    pub fn path(&self) -> &str {
+        self.0.to_str().expect("temp paths are UTF-8")
+    }
+}
+
+impl Drop for FixtureFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[allow(dead_code)]
+impl OsmFixture {
+    pub fn node(&mut self, id: i64, lat: f64, lon: f64, tags: &[(&str, &str)]) -> &mut Self {
+        self.nodes.push((id, lat, lon, owned(tags)));
+        self
+    }
+
+    pub fn way(&mut self, id: i64, refs: &[i64], tags: &[(&str, &str)]) -> &mut Self {
+        self.ways.push((id, refs.to_vec(), owned(tags)));
+        self
+    }
+
+    /// `members` are `(kind, id, role)`
+    pub fn relation(&mut self, id: i64, members: &[(Member, i64, &str)], tags: &[(&str, &str)]) -> &mut Self {
+        let members = members.iter().map(|&(kind, id, role)| (kind, id, role.to_string())).collect();
+        self.relations.push((id, members, owned(tags)));
+        self
+    }
+
+    /// Writes the PBF to a fresh file in the temp directory
+    pub fn write(&self) -> FixtureFile {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("safewalk-fixture-{}-{}.osm.pbf", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+
+        let mut header = Vec::new();
+        bytes_field(&mut header, 4, b"OsmSchema-V0.6");
+        bytes_field(&mut header, 4, b"DenseNodes");
+        let mut file = Vec::new();
+        blob(&mut file, "OSMHeader", &header);
+        blob(&mut file, "OSMData", &self.primitive_block());
+        std::fs::write(&path, file).expect("temp dir is writable");
+        FixtureFile(path)
+    }
+
+    fn primitive_block(&self) -> Vec<u8> {
+        let mut strings = StringTable::default();
+        let mut groups = Vec::new();
+
+        if !self.nodes.is_empty() {
+            let (mut ids, mut lats, mut lons, mut keys_vals) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+            let (mut last_id, mut last_lat, mut last_lon) = (0, 0, 0);
+            for (id, lat, lon, tags) in &self.nodes {
+                let (lat, lon) = ((lat * 1e7).round() as i64, (lon * 1e7).round() as i64);
+                sint(&mut ids, id - last_id);
+                sint(&mut lats, lat - last_lat);
+                sint(&mut lons, lon - last_lon);
+                (last_id, last_lat, last_lon) = (*id, lat, lon);
+                for (key, value) in tags {
+                    varint(&mut keys_vals, strings.id(key));
+                    varint(&mut keys_vals, strings.id(value));
+                }
+                varint(&mut keys_vals, 0);
+            }
+            let mut dense = Vec::new();
+            bytes_field(&mut dense, 1, &ids);
+            bytes_field(&mut dense, 8, &lats);
+            bytes_field(&mut dense, 9, &lons);
+            bytes_field(&mut dense, 10, &keys_vals);
+            let mut group = Vec::new();
+            bytes_field(&mut group, 2, &dense);
+            groups.push(group);
+        }
+
+        if !self.ways.is_empty() {
+            let mut group = Vec::new();
+            for (id, refs, tags) in &self.ways {
+                let mut way = Vec::new();
+                varint_field(&mut way, 1, *id as u64);
+                tag_fields(&mut way, &mut strings, tags);
+                let mut packed = Vec::new();
+                let mut last = 0;
+                for &node in refs {
+                    sint(&mut packed, node - last);
+                    last = node;
+                }
+                bytes_field(&mut way, 8, &packed);
+                bytes_field(&mut group, 3, &way);
+            }
+            groups.push(group);
+        }
+
+        if !self.relations.is_empty() {
+            let mut group = Vec::new();
+            for (id, members, tags) in &self.relations {
+                let mut relation = Vec::new();
+                varint_field(&mut relation, 1, *id as u64);
+                tag_fields(&mut relation, &mut strings, tags);
+                let (mut roles, mut ids, mut types) = (Vec::new(), Vec::new(), Vec::new());
+                let mut last = 0;
+                for (kind, member, role) in members {
+                    varint(&mut roles, strings.id(role));
+                    sint(&mut ids, member - last);
+                    last = *member;
+                    varint(&mut types, *kind as u64);
+                }
+                bytes_field(&mut relation, 8, &roles);
+                bytes_field(&mut relation, 9, &ids);
+                bytes_field(&mut relation, 10, &types);
+                bytes_field(&mut group, 4, &relation);
+            }
+            groups.push(group);
+        }
+
+        let mut block = Vec::new();
+        let mut table = Vec::new();
+        for s in &strings.strings {
+            bytes_field(&mut table, 1, s.as_bytes());
+        }
+        bytes_field(&mut block, 1, &table);
+        for group in &groups {
+            bytes_field(&mut block, 2, group);
+        }
+        block
+    }
+}
+
+/// Deduplicated strings of one block; index 0 is reserved (empty) as the format requires
+struct StringTable {
+    strings: Vec<String>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self { strings: vec![String::new()] }
+    }
+}
+
+impl StringTable {
+    fn id(&mut self, s: &str) -> u64 {
+        let index = self.strings.iter().position(|t| t == s).unwrap_or_else(|| {
+            self.strings.push(s.to_string());
+            self.strings.len() - 1
+        });
+        index as u64
+    }
+}
+
+fn owned(tags: &[(&str, &str)]) -> Tags {
+    tags.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+/// Appends a `BlobHeader` (with its length prefix) and an uncompressed `Blob`
+fn blob(out: &mut Vec<u8>, kind: &str, data: &[u8]) {
+    let mut blob = Vec::new();
+    bytes_field(&mut blob, 1, data);
+    varint_field(&mut blob, 2, data.len() as u64);
+    let mut header = Vec::new();
+    bytes_field(&mut header, 1, kind.as_bytes());
+    varint_field(&mut header, 3, blob.len() as u64);
+    out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&blob);
+}
+
+/// Packed `keys` (field 2) and `vals` (field 3) of a way or relation
+fn tag_fields(out: &mut Vec<u8>, strings: &mut StringTable, tags: &Tags) {
+    let (mut keys, mut vals) = (Vec::new(), Vec::new());
+    for (key, value) in tags {
+        varint(&mut keys, strings.id(key));
+        varint(&mut vals, strings.id(value));
+    }
+    bytes_field(out, 2, &keys);
+    bytes_field(out, 3, &vals);
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Zigzag-encoded `sint64`
+fn sint(out: &mut Vec<u8>, value: i64) {
+    varint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    varint(out, field << 3);
+    varint(out, value);
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(out, (field << 3) | 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
@@ -0,0 +1,82 @@
+//! Google's encoded polyline format (precision 5), as produced by most mobile map SDKs.
+//! Points are `[lat, lon]` like everywhere else in the crate.
+
+const PRECISION: f64 = 1e5;
+
+/// Decodes a polyline string into points; fails on characters outside the format's
+/// alphabet or a value cut off mid-way
+pub fn decode(encoded: &str) -> anyhow::Result<Vec<[f64; 2]>> {
+    let mut bytes = encoded.bytes().enumerate().peekable();
+    let mut points = Vec::new();
+    let (mut lat, mut lon) = (0i64, 0i64);
+
+    while bytes.peek().is_some() {
+        lat += decode_value(&mut bytes)?;
+        lon += decode_value(&mut bytes)?;
+        points.push([lat as f64 / PRECISION, lon as f64 / PRECISION]);
+    }
+
+    Ok(points)
+}
+
+/// Reads one zig-zag encoded value of 5-bit chunks
+fn decode_value(bytes: &mut impl Iterator<Item = (usize, u8)>) -> anyhow::Result<i64> {
+    let mut result: i64 = 0;
+    let mut shift = 0;
+    loop {
+        let (pos, byte) = bytes.next().ok_or_else(|| anyhow::anyhow!("polyline ends in the middle of a value"))?;
+        anyhow::ensure!((63..=126).contains(&byte), "invalid polyline character at position {pos}");
+        anyhow::ensure!(shift < 60, "polyline value too long at position {pos}");
+
+        let chunk = (byte - 63) as i64;
+        result |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+    // The lowest bit carries the sign
+    Ok(if result & 1 == 1 { !(result >> 1) } else { result >> 1 })
+}
+
+/// Encodes points into a polyline string (coordinates are rounded to 5 decimals)
+pub fn encode(points: &[[f64; 2]]) -> String {
+    let mut encoded = String::new();
+    let (mut prev_lat, mut prev_lon) = (0i64, 0i64);
+
+    for point in points {
+        let lat = (point[0] * PRECISION).round() as i64;
+        let lon = (point[1] * PRECISION).round() as i64;
+        encode_value(lat - prev_lat, &mut encoded);
+        encode_value(lon - prev_lon, &mut encoded);
+        (prev_lat, prev_lon) = (lat, lon);
+    }
+
+    encoded
+}
+
+fn encode_value(value: i64, out: &mut String) {
+    let mut v = if value < 0 { !(value << 1) } else { value << 1 };
+    while v >= 0x20 {
+        out.push((((v & 0x1f) | 0x20) as u8 + 63) as char);
+        v >>= 5;
+    }
+    out.push((v as u8 + 63) as char);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        // The example from Google's format documentation
+        let points = [[38.5, -120.2], [40.7, -120.95], [43.252, -126.453]];
+        let encoded = encode(&points);
+        assert_eq!(encoded, "_p~iF~ps|U_ulLnnqC_mqNvxq`@");
+        assert_eq!(decode(&encoded).unwrap(), points);
+
+        assert!(decode("_p~iF~ps|U_").is_err(), "cut off mid-value");
+        assert!(decode("_p~iF ps|U").is_err(), "outside the alphabet");
+    }
+}
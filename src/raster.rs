@@ -0,0 +1,136 @@
+//! Location-based risk from a raster layer (population density, crime heat maps, ...).
+//!
+//! Rasters are single-band GeoTIFFs in WGS84 lon/lat, north up, georeferenced by the
+//! `ModelTiepoint` and `ModelPixelScale` tags. Pixel values are rescaled linearly from the
+//! raster's own range onto the configured risk bounds. Points outside the raster and
+//! `GDAL_NODATA` pixels have no data, so the raster adds no risk there.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType;
+use crate::safety::RiskSource;
+
+pub struct RasterRiskSource {
+    width: usize,
+    height: usize,
+    /// `[lon, lat]` of the north-west corner of the first pixel
+    origin: [f64; 2],
+    /// Pixel width and height in degrees
+    pixel_size: [f64; 2],
+    /// Rescaled risk per pixel, rows from north to south, NaN for no data
+    risk: Vec<f32>,
+}
+
+impl RasterRiskSource {
+    /// Wraps `width x height` pixel values (rows from north to south, NaN for no data),
+    /// rescaling them onto `min_risk..=max_risk`
+    pub fn new(width: usize, height: usize, origin: [f64; 2], pixel_size: [f64; 2], values: Vec<f32>, min_risk: f32, max_risk: f32) -> Self {
+        let (low, high) = values.iter().filter(|v| v.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &v| (low.min(v), high.max(v)));
+        let risk = values.into_iter()
+            .map(|v| match v.is_finite() {
+                true if high > low => min_risk + (v - low) / (high - low) * (max_risk - min_risk),
+                // A flat raster carries no information beyond "everything is equally risky"
+                true => min_risk,
+                false => f32::NAN,
+            })
+            .collect();
+        Self { width, height, origin, pixel_size, risk }
+    }
+
+    /// Loads a single-band GeoTIFF, rescaling its values onto `min_risk..=max_risk`
+    pub fn load(path: impl AsRef<Path>, min_risk: f32, max_risk: f32) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let context = |e: tiff::TiffError| anyhow::anyhow!("cannot read risk raster {}: {e}", path.display());
+        let file = File::open(path).map_err(|e| anyhow::anyhow!("cannot open risk raster {}: {e}", path.display()))?;
+        let mut decoder = Decoder::new(BufReader::new(file)).map_err(context)?;
+
+        anyhow::ensure!(
+            matches!(decoder.colortype().map_err(context)?, ColorType::Gray(_)),
+            "risk raster {} must have a single band", path.display()
+        );
+        let (width, height) = decoder.dimensions().map_err(context)?;
+        let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).map_err(context)?;
+        let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag).map_err(context)?;
+        anyhow::ensure!(
+            scale.len() >= 2 && tiepoint.len() >= 6 && scale[0] > 0.0 && scale[1] > 0.0,
+            "risk raster {} has an invalid pixel scale or tie point", path.display()
+        );
+        // The tie point maps raster position (i, j) to (lon, lat); walk back to pixel (0, 0)
+        let origin = [tiepoint[3] - tiepoint[0] * scale[0], tiepoint[4] + tiepoint[1] * scale[1]];
+        let no_data = match decoder.find_tag(Tag::GdalNodata).map_err(context)? {
+            Some(value) => value.into_string().ok().and_then(|s| s.trim_end_matches('\0').trim().parse::<f64>().ok()),
+            None => None,
+        };
+
+        let values: Vec<f64> = match decoder.read_image().map_err(context)? {
+            DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U64(v) => v.into_iter().map(|x| x as f64).collect(),
+            DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I64(v) => v.into_iter().map(|x| x as f64).collect(),
+            DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::F64(v) => v,
+        };
+        anyhow::ensure!(
+            values.len() == width as usize * height as usize,
+            "risk raster {} doesn't hold one value per pixel", path.display()
+        );
+        let values = values.into_iter()
+            .map(|v| if Some(v) == no_data { f32::NAN } else { v as f32 })
+            .collect();
+        Ok(Self::new(width as usize, height as usize, origin, [scale[0], scale[1]], values, min_risk, max_risk))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl RiskSource for RasterRiskSource {
+    fn risk_at(&self, point: [f64; 2]) -> Option<f32> {
+        let col = ((point[1] - self.origin[0]) / self.pixel_size[0]).floor();
+        let row = ((self.origin[1] - point[0]) / self.pixel_size[1]).floor();
+        if !(0.0..self.width as f64).contains(&col) || !(0.0..self.height as f64).contains(&row) {
+            return None;
+        }
+        Some(self.risk[row as usize * self.width + col as usize]).filter(|risk| !risk.is_nan())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safety::{SafetyConfig, SafetyMap};
+    use crate::test_graphs::grid;
+
+    #[test]
+    fn streets_outside_the_raster_keep_their_tag_risk() {
+        // grid(3) spans lon 76.370..76.372, the 2x2 raster lon 76.3702..76.3722; the
+        // south-east pixel has no data
+        let mut nav = grid(3);
+        let raster = RasterRiskSource::new(2, 2, [76.3702, 30.3428], [0.001, 0.001], vec![0.0, 2.0, 1.0, f32::NAN], 0.1, 0.9);
+        nav.apply_location_risk(&[&raster], &SafetyMap::new(SafetyConfig::default()).unwrap());
+
+        let score = |a: u32, b: u32| {
+            let edge = nav.graph.find_edge(a.into(), b.into()).unwrap();
+            nav.graph[edge].safety_score
+        };
+        // Midpoint at lon 76.370, west of the raster
+        assert_eq!(score(0, 3), 0.1);
+        // Midpoint in the south-west pixel, rescaled to 0.5 and added to the tag risk
+        assert!((score(3, 4) - 0.6).abs() < 1e-6, "{}", score(3, 4));
+        // Midpoint in the no-data pixel
+        assert_eq!(score(4, 5), 0.1);
+    }
+}
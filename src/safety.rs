@@ -1,17 +1,184 @@
 use std::collections::HashMap;
+use serde::Serialize;
+use crate::graph::{Crossing, CrossingType, Surface};
 
-pub struct SafetyMap;
+/// Flat risk assigned to foot-ferry links
+const FERRY_RISK: f32 = 0.3;
+
+/// Every tag `calculate_edge_risk` reads; ways agreeing on all of them score the same
+const RISK_TAGS: [&str; 8] = ["route", "highway", "lit", "sidewalk", "surface", "foot", "maxspeed", "lanes"];
+
+/// Values of `RISK_TAGS` for one way, `None` where untagged
+type RiskKey = [Option<String>; RISK_TAGS.len()];
+
+/// A canonical way's tags, as key/value pairs
+type TagSet = &'static [(&'static str, &'static str)];
+
+/// Orderings any sane configuration keeps, as (riskier tags, safer tags), checked by `self_test`
+const SELF_TEST_ORDERINGS: [(TagSet, TagSet); 4] = [
+    (&[("highway", "footway"), ("lit", "no")], &[("highway", "footway"), ("lit", "yes")]),
+    (&[("highway", "primary"), ("lit", "no")], &[("highway", "footway"), ("lit", "yes")]),
+    (&[("highway", "primary"), ("sidewalk", "no")], &[("highway", "primary"), ("sidewalk", "both")]),
+    (
+        &[("highway", "primary"), ("lit", "yes"), ("sidewalk", "both"), ("surface", "asphalt"), ("foot", "designated"), ("maxspeed", "20")],
+        &[("highway", "footway")],
+    ),
+];
+
+/// Per-feature contributions to an edge's tag-based risk
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RiskBreakdown {
+    pub baseline: f32,
+    pub lit_delta: f32,
+    pub sidewalk_delta: f32,
+    pub surface_delta: f32,
+    pub foot_delta: f32,
+    pub speed_delta: f32,
+    pub lanes_delta: f32,
+    /// Raise up to the road type's floor (`SafetyConfig::road_floors`), 0 if already above it
+    pub floor_delta: f32,
+}
+
+impl RiskBreakdown {
+    /// Baseline plus every delta, before clamping
+    pub fn total(&self) -> f32 {
+        self.baseline
+            + self.lit_delta
+            + self.sidewalk_delta
+            + self.surface_delta
+            + self.foot_delta
+            + self.speed_delta
+            + self.lanes_delta
+            + self.floor_delta
+    }
+}
+
+/// Location-based risk, independent of street tags (reported incidents, raster layers, ...)
+pub trait RiskSource: Sync {
+    /// Risk this source assigns to `point` (`[lat, lon]`), `None` where it has no data
+    fn risk_at(&self, point: [f64; 2]) -> Option<f32>;
+}
+
+/// Tunable parameters of the safety model
+#[derive(Debug, Clone)]
+pub struct SafetyConfig {
+    /// Lower bound every risk score is clamped to
+    pub min_risk: f32,
+    /// Upper bound every risk score is clamped to
+    pub max_risk: f32,
+    /// Blend weight `w` of the tag-based score against location-based risk (incidents, rasters):
+    /// `w * tag + (1 - w) * location`. `None` adds location risk on top of the tag score.
+    pub tag_weight: Option<f32>,
+    /// Lowest tag score per `highway` value, whatever lighting, sidewalks and the like take
+    /// off: a well-equipped primary road still carries primary-road traffic
+    pub road_floors: Vec<(String, f32)>,
+    /// Risk taken off lit ways (`lit=yes|24/7|automatic|good`)
+    pub lit_bonus: f32,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        let road_floors = [("motorway", 0.5), ("trunk", 0.5), ("primary", 0.5), ("secondary", 0.4), ("tertiary", 0.3)]
+            .into_iter()
+            .map(|(highway, floor)| (highway.to_string(), floor))
+            .collect();
+        Self { min_risk: 0.05, max_risk: 1.0, tag_weight: None, road_floors, lit_bonus: 0.2 }
+    }
+}
+
+impl SafetyConfig {
+    /// Reads overrides from `SAFEWALK_RISK_MIN` / `SAFEWALK_RISK_MAX` / `SAFEWALK_TAG_WEIGHT` /
+    /// `SAFEWALK_ROAD_RISK_FLOORS` / `SAFEWALK_LIT_BONUS`, falling back to defaults
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        if let Ok(v) = std::env::var("SAFEWALK_RISK_MIN") {
+            config.min_risk = v.parse().map_err(|_| anyhow::anyhow!("SAFEWALK_RISK_MIN is not a number: {v}"))?;
+        }
+        if let Ok(v) = std::env::var("SAFEWALK_RISK_MAX") {
+            config.max_risk = v.parse().map_err(|_| anyhow::anyhow!("SAFEWALK_RISK_MAX is not a number: {v}"))?;
+        }
+        if let Ok(v) = std::env::var("SAFEWALK_TAG_WEIGHT") {
+            config.tag_weight = Some(v.parse().map_err(|_| anyhow::anyhow!("SAFEWALK_TAG_WEIGHT is not a number: {v}"))?);
+        }
+        if let Ok(v) = std::env::var("SAFEWALK_ROAD_RISK_FLOORS") {
+            config.road_floors = parse_road_floors(&v)?;
+        }
+        if let Ok(v) = std::env::var("SAFEWALK_LIT_BONUS") {
+            config.lit_bonus = v.parse().map_err(|_| anyhow::anyhow!("SAFEWALK_LIT_BONUS is not a number: {v}"))?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        // Negative risk would make edge costs shrink below distance and break A*
+        anyhow::ensure!(
+            self.min_risk >= 0.0 && self.min_risk < self.max_risk && self.max_risk.is_finite(),
+            "invalid risk bounds: need 0 <= min ({}) < max ({})",
+            self.min_risk,
+            self.max_risk
+        );
+        if let Some(w) = self.tag_weight {
+            anyhow::ensure!((0.0..=1.0).contains(&w), "tag weight must be between 0 and 1, got {w}");
+        }
+        anyhow::ensure!(self.lit_bonus.is_finite(), "lit bonus must be a number, got {}", self.lit_bonus);
+        for (highway, floor) in &self.road_floors {
+            anyhow::ensure!(floor.is_finite() && *floor >= 0.0, "risk floor of {highway} must be a non-negative number, got {floor}");
+        }
+        Ok(())
+    }
+}
+
+pub struct SafetyMap {
+    config: SafetyConfig,
+}
 
 impl SafetyMap {
-    pub fn new() -> Self {
-        Self
+    pub fn new(config: SafetyConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        Ok(Self { config })
+    }
+
+    pub fn config(&self) -> &SafetyConfig {
+        &self.config
+    }
+
+    /// Clamps a raw score into the configured risk bounds
+    pub fn clamp_risk(&self, score: f32) -> f32 {
+        score.clamp(self.config.min_risk, self.config.max_risk)
+    }
+
+    /// Final risk of an edge from its tag-based score and the location-based risk around it,
+    /// blended by `SafetyConfig::tag_weight` (or summed without one). Without location data
+    /// the blend assumes `min_risk` there, while the sum adds nothing.
+    pub fn blend_risk(&self, tag: f32, location: Option<f32>) -> f32 {
+        match self.config.tag_weight {
+            Some(w) => self.clamp_risk(w * tag + (1.0 - w) * location.unwrap_or(self.config.min_risk)),
+            None => self.clamp_risk(tag + location.unwrap_or(0.0)),
+        }
+    }
+
+    /// Share of additional location-based risk that reaches a blended score
+    pub fn location_share(&self) -> f32 {
+        self.config.tag_weight.map_or(1.0, |w| 1.0 - w)
     }
 
-    pub fn calculate_edge_risk(&self, tags: &HashMap<&str, &str>) -> f32 {
+    /// Scores a way's tags, keeping each feature's contribution separate.
+    /// Use `clamp_risk(breakdown.total())` for the final score.
+    /// Reading a new tag here means adding it to `RISK_TAGS`, or `RiskCache` will mix up ways.
+    pub fn calculate_edge_risk(&self, tags: &HashMap<&str, &str>) -> RiskBreakdown {
+        let mut breakdown = RiskBreakdown::default();
+
+        // Ferries have no street-level features to score; a crewed crossing is fairly safe
+        if tags.get("route") == Some(&"ferry") {
+            breakdown.baseline = FERRY_RISK;
+            return breakdown;
+        }
+
         // 1. BASELINE RISK
         let highway_type = tags.get("highway").copied().unwrap_or("");
         
-        let mut score: f32 = match highway_type {
+        breakdown.baseline = match highway_type {
             "pedestrian" | "footway" | "path" | "steps" => 0.1, 
             "living_street" | "residential" => 0.3, 
             "service" => 0.5,
@@ -22,36 +189,297 @@ impl SafetyMap {
 
         // 2. FEATURE WEIGHTS
         if let Some(lit) = tags.get("lit") {
-            match *lit {
-                "yes" | "24/7" | "automatic" | "good" => score -= 0.2,
-                "no" => score += 0.3,
-                _ => {}
-            }
+            breakdown.lit_delta = match *lit {
+                "yes" | "24/7" | "automatic" | "good" => -self.config.lit_bonus,
+                "no" => 0.3,
+                _ => 0.0,
+            };
         }
 
         if let Some(sidewalk) = tags.get("sidewalk") {
-            match *sidewalk {
-                "both" | "yes" | "separate" | "left" | "right" => score -= 0.2,
-                "no" | "none" => score += 0.2,
-                _ => {}
-            }
+            breakdown.sidewalk_delta = match *sidewalk {
+                "both" | "yes" | "separate" | "left" | "right" => -0.2,
+                "no" | "none" => 0.2,
+                _ => 0.0,
+            };
         }
 
         if let Some(surface) = tags.get("surface") {
-            match *surface {
-                "paved" | "asphalt" | "concrete" | "paving_stones" => score -= 0.05,
-                "unpaved" | "dirt" | "earth" | "gravel" | "mud" => score += 0.1,
-                _ => {}
+            breakdown.surface_delta = match Surface::from_tag(surface) {
+                Surface::Paved => -0.05,
+                Surface::Unpaved => 0.1,
+                Surface::Unknown => 0.0,
+            };
+        }
+
+        if tags.get("foot") == Some(&"designated") {
+            breakdown.foot_delta = -0.1;
+        }
+
+        // Traffic speed: walking beside fast traffic is riskier
+        if let Some(speed_kmh) = tags.get("maxspeed").and_then(|v| parse_maxspeed(v)) {
+            breakdown.speed_delta = match speed_kmh {
+                s if s <= 20.0 => -0.05,
+                s if s <= 30.0 => 0.0,
+                s if s <= 50.0 => 0.05,
+                s if s <= 70.0 => 0.15,
+                _ => 0.25,
+            };
+        }
+
+        // Road width: every lane beyond a two-lane street adds exposure when crossing or walking alongside
+        if let Some(lanes) = tags.get("lanes").and_then(|v| parse_lanes(v)) {
+            breakdown.lanes_delta = (lanes.saturating_sub(2) as f32 * 0.05).min(0.3);
+        }
+
+        // 3. FLOOR: no amount of bonuses makes a motor road as safe as a footway
+        if let Some((_, floor)) = self.config.road_floors.iter().find(|(highway, _)| highway == highway_type) {
+            breakdown.floor_delta = (floor - breakdown.total()).max(0.0);
+        }
+
+        breakdown
+    }
+
+    /// Scores a few canonical tag sets the way edges are scored (without location risk) and
+    /// checks they keep their expected order, e.g. a lit footway below an unlit primary road.
+    /// Catches configurations that flatten the model, such as risk bounds too close together
+    /// or a tag weight of 0. Returns the first violated ordering.
+    pub fn self_test(&self) -> Result<(), String> {
+        let describe = |tags: &[(&str, &str)]| tags.iter().map(|(k, v)| format!("{k}={v}")).collect::<Vec<_>>().join(" ");
+        let score = |tags: &[(&str, &str)]| {
+            let tags: HashMap<&str, &str> = tags.iter().copied().collect();
+            self.blend_risk(self.clamp_risk(self.calculate_edge_risk(&tags).total()), Some(0.0))
+        };
+        for (riskier, safer) in SELF_TEST_ORDERINGS {
+            let (high, low) = (score(riskier), score(safer));
+            if high <= low {
+                return Err(format!(
+                    "expected `{}` ({high}) to score riskier than `{}` ({low})",
+                    describe(riskier),
+                    describe(safer)
+                ));
             }
         }
+        Ok(())
+    }
+
+    /// Adjusts an edge's risk for the road crossing it steps onto, if any.
+    /// Signalized crossings are a mild improvement; uncontrolled ones a clear hazard.
+    /// A refuge island (two shorter crossings instead of one) lowers the risk further.
+    pub fn crossing_risk(&self, base: f32, crossing: Option<Crossing>) -> f32 {
+        let Some(crossing) = crossing else { return base };
+        let delta = match crossing.kind {
+            CrossingType::TrafficSignals => -0.1,
+            CrossingType::Uncontrolled => 0.3,
+        };
+        let island = if crossing.island { -0.1 } else { 0.0 };
+        self.clamp_risk(base + delta + island)
+    }
+}
 
-        if let Some(foot) = tags.get("foot") {
-            if *foot == "designated" {
-                score -= 0.1;
+/// Memoizes `calculate_edge_risk` by the values of `RISK_TAGS`. A city has millions of ways
+/// but only a few thousand distinct combinations, so a graph build scores each once.
+/// Building the key walks the way's tags once and hashes them with FNV-1a, which is what
+/// makes a lookup cheaper than just scoring the way (compare with `cargo bench edge_risk`).
+/// Only a combination seen for the first time is copied.
+#[derive(Default)]
+pub struct RiskCache {
+    /// Hash of the tag values -> the combinations sharing that hash, with their scores
+    entries: HashMap<u64, Vec<(RiskKey, RiskBreakdown)>>,
+}
+
+impl RiskCache {
+    pub fn calculate_edge_risk(&mut self, safety_map: &SafetyMap, tags: &HashMap<&str, &str>) -> RiskBreakdown {
+        let mut key = [None; RISK_TAGS.len()];
+        for (tag, value) in tags {
+            if let Some(i) = RISK_TAGS.iter().position(|t| t == tag) {
+                key[i] = Some(*value);
             }
         }
 
-        // 3. CLAMPING
-        score.clamp(0.05, 1.0)
+        let bucket = self.entries.entry(fnv1a(&key)).or_default();
+        let cached = bucket.iter()
+            .find(|(stored, _)| stored.iter().zip(&key).all(|(stored, value)| stored.as_deref() == *value));
+        if let Some((_, breakdown)) = cached {
+            return *breakdown;
+        }
+        let breakdown = safety_map.calculate_edge_risk(tags);
+        bucket.push((key.map(|value| value.map(str::to_string)), breakdown));
+        breakdown
+    }
+
+    /// Distinct tag combinations scored so far
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// FNV-1a over the tag values, with a separator after each so `("ab", "")` and `("a", "b")` differ
+fn fnv1a(values: &[Option<&str>]) -> u64 {
+    const PRIME: u64 = 0x100000001b3;
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for value in values {
+        for byte in value.map(str::as_bytes).unwrap_or(b"\x01") {
+            hash = (hash ^ *byte as u64).wrapping_mul(PRIME);
+        }
+        hash = (hash ^ 0xff).wrapping_mul(PRIME);
+    }
+    hash
+}
+
+/// Parses `highway=floor` lists such as `primary=0.5,secondary=0.4`; empty disables floors
+fn parse_road_floors(value: &str) -> anyhow::Result<Vec<(String, f32)>> {
+    value.split(',').map(str::trim).filter(|e| !e.is_empty())
+        .map(|entry| {
+            let (highway, floor) = entry.split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("SAFEWALK_ROAD_RISK_FLOORS entries must look like `highway=floor`, got `{entry}`"))?;
+            let floor = floor.trim().parse()
+                .map_err(|_| anyhow::anyhow!("SAFEWALK_ROAD_RISK_FLOORS: `{floor}` is not a number"))?;
+            Ok((highway.trim().to_string(), floor))
+        })
+        .collect()
+}
+
+/// Parses an OSM `lanes` value. Multi-valued tags (`2;3`) use the first entry;
+/// anything non-numeric yields `None`.
+fn parse_lanes(value: &str) -> Option<u32> {
+    value.split(';').next()?.trim().parse().ok()
+}
+
+/// Parses an OSM `maxspeed` value into km/h.
+/// Handles bare numbers (km/h), `km/h`/`kmh`/`mph` suffixes, and the `walk`/`none` keywords.
+/// Zone codes like `IN:urban` are not resolved and yield `None`.
+fn parse_maxspeed(value: &str) -> Option<f32> {
+    let value = value.trim();
+    match value {
+        "walk" => return Some(7.0),
+        "none" => return Some(130.0),
+        _ => {}
+    }
+
+    let (number, factor) = if let Some(n) = value.strip_suffix("mph") {
+        (n, 1.609)
+    } else if let Some(n) = value.strip_suffix("km/h").or_else(|| value.strip_suffix("kmh")) {
+        (n, 1.0)
+    } else {
+        (value, 1.0)
+    };
+
+    number.trim().parse::<f32>().ok().map(|n| n * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(tags: &[(&str, &str)]) -> f32 {
+        let map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let tags: HashMap<&str, &str> = tags.iter().copied().collect();
+        map.clamp_risk(map.calculate_edge_risk(&tags).total())
+    }
+
+    #[test]
+    fn faster_traffic_scores_riskier() {
+        let slow = score(&[("highway", "residential"), ("maxspeed", "20")]);
+        let fast = score(&[("highway", "residential"), ("maxspeed", "80")]);
+        assert!(fast > slow, "maxspeed=80 ({fast}) should be riskier than maxspeed=20 ({slow})");
+        assert_eq!(parse_maxspeed("30 mph"), Some(30.0 * 1.609));
+        assert_eq!(parse_maxspeed("walk"), Some(7.0));
+        assert_eq!(parse_maxspeed("IN:urban"), None);
+    }
+
+    #[test]
+    fn breakdown_adds_up_to_the_score() {
+        let map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let tags: HashMap<&str, &str> =
+            [("highway", "residential"), ("lit", "no"), ("sidewalk", "both"), ("surface", "asphalt"), ("foot", "designated")].into();
+        let b = map.calculate_edge_risk(&tags);
+        assert_eq!((b.baseline, b.lit_delta, b.sidewalk_delta, b.surface_delta, b.foot_delta), (0.3, 0.3, -0.2, -0.05, -0.1));
+        let sum = b.baseline + b.lit_delta + b.sidewalk_delta + b.surface_delta + b.foot_delta + b.speed_delta + b.lanes_delta + b.floor_delta;
+        assert_eq!(b.total(), sum);
+        assert!((map.clamp_risk(b.total()) - 0.25).abs() < 1e-6);
+
+        // A lit footway's deltas take it below the minimum, which the clamp restores
+        let tags: HashMap<&str, &str> = [("highway", "footway"), ("lit", "yes")].into();
+        let b = map.calculate_edge_risk(&tags);
+        assert!(b.total() < 0.0);
+        assert_eq!(map.clamp_risk(b.total()), SafetyConfig::default().min_risk);
+    }
+
+    #[test]
+    fn wider_bounds_let_risk_exceed_one() {
+        let tags: HashMap<&str, &str> = [("highway", "primary"), ("lit", "no"), ("sidewalk", "no"), ("maxspeed", "80")].into();
+        let default = SafetyMap::new(SafetyConfig::default()).unwrap();
+        assert_eq!(default.clamp_risk(default.calculate_edge_risk(&tags).total()), 1.0);
+
+        let wide = SafetyMap::new(SafetyConfig { min_risk: 0.0, max_risk: 2.0, ..SafetyConfig::default() }).unwrap();
+        assert!(wide.clamp_risk(wide.calculate_edge_risk(&tags).total()) > 1.0);
+
+        assert!(SafetyMap::new(SafetyConfig { min_risk: 1.0, max_risk: 0.5, ..SafetyConfig::default() }).is_err());
+    }
+
+    #[test]
+    fn tag_weight_blends_tag_and_location_risk() {
+        let blended = SafetyMap::new(SafetyConfig { tag_weight: Some(0.25), ..SafetyConfig::default() }).unwrap();
+        assert!((blended.blend_risk(0.2, Some(0.6)) - (0.25 * 0.2 + 0.75 * 0.6)).abs() < 1e-6);
+        assert_eq!(blended.location_share(), 0.75);
+
+        let summed = SafetyMap::new(SafetyConfig::default()).unwrap();
+        assert!((summed.blend_risk(0.2, Some(0.6)) - 0.8).abs() < 1e-6);
+        assert_eq!(summed.blend_risk(0.7, Some(0.6)), 1.0, "clamped to max_risk");
+        assert_eq!(summed.blend_risk(0.2, None), 0.2, "no location data adds nothing");
+    }
+
+    #[test]
+    fn equipped_primary_road_scores_above_a_footway() {
+        let equipped = [
+            ("highway", "primary"), ("lit", "yes"), ("sidewalk", "both"), ("surface", "asphalt"),
+            ("foot", "designated"), ("maxspeed", "20"),
+        ];
+        let primary = score(&equipped);
+        let footway = score(&[("highway", "footway")]);
+        assert!(primary > footway, "equipped primary ({primary}) should be riskier than a bare footway ({footway})");
+        assert_eq!(primary, 0.5, "held at the primary floor");
+
+        let unfloored = SafetyMap::new(SafetyConfig { road_floors: Vec::new(), ..SafetyConfig::default() }).unwrap();
+        let tags: HashMap<&str, &str> = equipped.into();
+        assert!(unfloored.clamp_risk(unfloored.calculate_edge_risk(&tags).total()) < primary);
+    }
+
+    #[test]
+    fn inverted_lit_bonus_fails_the_self_test() {
+        assert_eq!(SafetyMap::new(SafetyConfig::default()).unwrap().self_test(), Ok(()));
+
+        let inverted = SafetyMap::new(SafetyConfig { lit_bonus: -0.5, ..SafetyConfig::default() }).unwrap();
+        let failure = inverted.self_test().unwrap_err();
+        assert!(failure.contains("highway=footway lit=no") && failure.contains("highway=footway lit=yes"), "{failure}");
+    }
+
+    #[test]
+    fn cached_risk_matches_uncached() {
+        let safety_map = SafetyMap::new(SafetyConfig::default()).unwrap();
+        let mut cache = RiskCache::default();
+        let ways: Vec<HashMap<&str, &str>> = vec![
+            [("highway", "footway"), ("lit", "yes")].into(),
+            [("highway", "footway"), ("lit", "no")].into(),
+            [("highway", "primary"), ("sidewalk", "both"), ("maxspeed", "50"), ("lanes", "4")].into(),
+            // Same risk tags, plus one the score ignores
+            [("highway", "footway"), ("lit", "yes"), ("name", "Mall Road")].into(),
+            [("highway", "residential"), ("surface", "gravel"), ("foot", "designated")].into(),
+            HashMap::new(),
+        ];
+
+        for _ in 0..2 {
+            for tags in &ways {
+                let (cached, uncached) = (cache.calculate_edge_risk(&safety_map, tags), safety_map.calculate_edge_risk(tags));
+                assert_eq!(cached.total(), uncached.total(), "{tags:?}");
+                assert_eq!(cached.lit_delta, uncached.lit_delta);
+            }
+        }
+        assert_eq!(cache.len(), 5, "tags outside RISK_TAGS share an entry");
     }
 }
@@ -0,0 +1,100 @@
+//! R-tree backed lookups for map features collected while loading (trees, POIs, ...).
+//! Points are `[lat, lon]` in degrees; query radii are in meters.
+
+use rstar::primitives::{GeomWithData, Line};
+use rstar::RTree;
+use crate::geo_util::{haversine_meters, point_segment_distance_meters};
+
+/// Meters per degree of latitude (and of longitude at the equator)
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Clone)]
+pub struct PointIndex<T> {
+    tree: RTree<GeomWithData<[f64; 2], T>>,
+}
+
+impl<T> PointIndex<T> {
+    pub fn new(points: Vec<([f64; 2], T)>) -> Self {
+        let entries = points.into_iter().map(|(p, data)| GeomWithData::new(p, data)).collect();
+        Self { tree: RTree::bulk_load(entries) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size() == 0
+    }
+
+    /// Items within `radius_meters` of `point`, with their distances in meters
+    pub fn within(&self, point: [f64; 2], radius_meters: f64) -> impl Iterator<Item = (&T, f64)> {
+        // Degrees shrink east-west away from the equator, so search the (larger) longitude
+        // span in degree space, then trim the candidates with the exact distance
+        let radius_degrees = radius_meters / (METERS_PER_DEGREE * point[0].to_radians().cos().max(0.01));
+
+        self.tree.locate_within_distance(point, radius_degrees * radius_degrees)
+            .map(move |entry| (&entry.data, haversine_meters(point, *entry.geom())))
+            .filter(move |&(_, d)| d <= radius_meters)
+    }
+
+    /// The `k` items nearest to `point`, nearest first, with their distances in meters
+    pub fn nearest(&self, point: [f64; 2], k: usize) -> Vec<(&T, f64)> {
+        // The tree ranks by degrees, which overstates east-west distances; its `k` nearest
+        // still bound the radius holding the true `k` nearest, so rank everything within it
+        let Some(radius) = self.tree.nearest_neighbor_iter(&point)
+            .take(k)
+            .map(|entry| haversine_meters(point, *entry.geom()))
+            .reduce(f64::max)
+        else {
+            return Vec::new();
+        };
+        let mut found: Vec<_> = self.within(point, radius).collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found.truncate(k);
+        found
+    }
+}
+
+/// Line segments (edges, ...) by position, for nearest-segment lookups
+#[derive(Clone)]
+pub struct SegmentIndex<T> {
+    tree: RTree<GeomWithData<Line<[f64; 2]>, T>>,
+}
+
+impl<T> SegmentIndex<T> {
+    /// `segments` are `(a, b, data)` with both ends `[lat, lon]`
+    pub fn new(segments: Vec<([f64; 2], [f64; 2], T)>) -> Self {
+        let entries = segments.into_iter().map(|(a, b, data)| GeomWithData::new(Line::new(a, b), data)).collect();
+        Self { tree: RTree::bulk_load(entries) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size() == 0
+    }
+
+}
+
+impl<T: Ord> SegmentIndex<T> {
+    /// The segment passing closest to `point`, with its distance in meters; ties go to the
+    /// smallest `T`
+    pub fn nearest(&self, point: [f64; 2]) -> Option<(&T, f64)> {
+        let meters = |entry: &GeomWithData<Line<[f64; 2]>, T>| {
+            let line = entry.geom();
+            point_segment_distance_meters(point, line.from, line.to)
+        };
+        // As in `PointIndex::nearest`: the nearest segment in degree space bounds the true
+        // distance, and everything within that distance lies within its degree equivalent
+        // (plus a percent for the differing meters-per-degree constants)
+        let bound = meters(self.tree.nearest_neighbor(&point)?);
+        let radius_degrees = 1.01 * bound / (METERS_PER_DEGREE * point[0].to_radians().cos().max(0.01));
+
+        self.tree.locate_within_distance(point, radius_degrees * radius_degrees)
+            .map(|entry| (&entry.data, meters(entry)))
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(b.0)))
+    }
+}
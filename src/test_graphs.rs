@@ -0,0 +1,56 @@
+//! Hand-built graphs for unit tests that don't need OSM data
+
+use crate::geo_util::haversine_meters;
+use crate::graph::{GeoNode, HighwayKind, NavigationGraph, Surface, WalkEdge};
+use crate::safety::RiskBreakdown;
+
+/// A plain paved footway edge
+pub fn edge(distance_meters: f64, risk: f32) -> WalkEdge {
+    WalkEdge {
+        distance_meters,
+        safety_score: risk,
+        base_safety: risk,
+        is_tunnel: false,
+        crossing: None,
+        name: None,
+        risk: RiskBreakdown::default(),
+        shade: 0.0,
+        enclosure: 0.0,
+        busy: 0.0,
+        flooded: false,
+        boundary_penalty: 0.0,
+        surface: Surface::Paved,
+        smoothness: None,
+        incline_percent: None,
+        highway: HighwayKind::Footway,
+        private: false,
+        way_id: None,
+        foot_closed: None,
+    }
+}
+
+/// Nodes a few meters apart, so edge lengths (not positions) decide the routes
+pub fn nodes(count: usize) -> Vec<GeoNode> {
+    (0..count).map(|i| GeoNode { lat: 30.34 + i as f64 * 1e-5, lon: 76.37 }).collect()
+}
+
+/// Both directions of each `(a, b, edge)`
+pub fn two_way(edges: &[(usize, usize, WalkEdge)]) -> Vec<(usize, usize, WalkEdge)> {
+    edges.iter().flat_map(|&(a, b, e)| [(a, b, e), (b, a, e)]).collect()
+}
+
+/// `n` x `n` grid of two-way footways with risk 0.1, 0.001° (~100 m) apart, numbered row by
+/// row from the south-west corner
+pub fn grid(n: usize) -> NavigationGraph {
+    let nodes: Vec<GeoNode> = (0..n * n)
+        .map(|i| GeoNode { lat: 30.34 + (i / n) as f64 * 0.001, lon: 76.37 + (i % n) as f64 * 0.001 })
+        .collect();
+    let mut edges = Vec::new();
+    for i in 0..n * n {
+        for j in [(i % n + 1 < n).then_some(i + 1), (i + n < n * n).then_some(i + n)].into_iter().flatten() {
+            let d = haversine_meters([nodes[i].lat, nodes[i].lon], [nodes[j].lat, nodes[j].lon]);
+            edges.push((i, j, edge(d, 0.1)));
+        }
+    }
+    NavigationGraph::from_edges(&nodes, &two_way(&edges))
+}
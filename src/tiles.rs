@@ -0,0 +1,61 @@
+//! Quantizing geometry onto a vector-tile grid (as in Mapbox Vector Tiles), so clients can
+//! put a route straight into an MVT layer without reprojecting it themselves.
+//! Input points are `[lat, lon]` like everywhere else in the crate.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Grid resolution MVT encoders use unless told otherwise
+pub const DEFAULT_EXTENT: u32 = 4096;
+
+/// Web Mercator stops here; the poles project to infinity
+const MAX_MERCATOR_LATITUDE: f64 = 85.051_128_78;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TileGeometry {
+    pub extent: u32,
+    /// Square area the grid spans, as `[min_lon, min_lat, max_lon, max_lat]`
+    pub bounds: [f64; 4],
+    /// Grid cells `[x, y]` in input order; x grows eastwards, y southwards (tile convention)
+    pub coordinates: Vec<[u32; 2]>,
+}
+
+/// Projects `points` to Web Mercator and snaps them to an `extent` x `extent` grid laid over
+/// the smallest square containing them, so every cell lies within `[0, extent]`
+pub fn quantize(points: &[[f64; 2]], extent: u32) -> TileGeometry {
+    if points.is_empty() {
+        return TileGeometry { extent, bounds: [0.0; 4], coordinates: Vec::new() };
+    }
+
+    let projected: Vec<[f64; 2]> = points.iter().map(|&p| web_mercator(p)).collect();
+    let min_x = projected.iter().map(|p| p[0]).fold(f64::INFINITY, f64::min);
+    let min_y = projected.iter().map(|p| p[1]).fold(f64::INFINITY, f64::min);
+    let max_x = projected.iter().map(|p| p[0]).fold(f64::NEG_INFINITY, f64::max);
+    let max_y = projected.iter().map(|p| p[1]).fold(f64::NEG_INFINITY, f64::max);
+
+    // A square keeps the grid's aspect ratio true; a single point gets a zero-size square
+    let side = (max_x - min_x).max(max_y - min_y);
+    let cell = |value: f64, min: f64| {
+        if side > 0.0 { ((value - min) / side * extent as f64).round().clamp(0.0, extent as f64) as u32 } else { 0 }
+    };
+    let coordinates = projected.iter().map(|p| [cell(p[0], min_x), cell(p[1], min_y)]).collect();
+
+    let [south, west] = inverse_web_mercator([min_x, min_y + side]);
+    let [north, east] = inverse_web_mercator([min_x + side, min_y]);
+    TileGeometry { extent, bounds: [west, south, east, north], coordinates }
+}
+
+/// `[lat, lon]` to Web Mercator `[x, y]` on the unit square: x from the antimeridian
+/// eastwards, y from the northern edge southwards
+fn web_mercator(point: [f64; 2]) -> [f64; 2] {
+    let lat = point[0].clamp(-MAX_MERCATOR_LATITUDE, MAX_MERCATOR_LATITUDE).to_radians();
+    let x = (point[1] + 180.0) / 360.0;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0;
+    [x, y]
+}
+
+fn inverse_web_mercator(point: [f64; 2]) -> [f64; 2] {
+    let lon = point[0] * 360.0 - 180.0;
+    let lat = (std::f64::consts::PI * (1.0 - 2.0 * point[1])).sinh().atan().to_degrees();
+    [lat, lon]
+}
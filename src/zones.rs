@@ -0,0 +1,229 @@
+//! Polygon zones loaded from GeoJSON (flood areas, risk hotspots, ...).
+//!
+//! Each feature needs a `Polygon` or `MultiPolygon` geometry and a `zone` property naming
+//! its kind, e.g. `{"zone": "flood"}`. Other geometry types and unknown kinds fail the
+//! load, so a typo doesn't silently disable a zone. An optional `multiplier` property
+//! scales the risk of edges in the zone, e.g. `{"zone": "risk", "multiplier": 1.5}`.
+//!
+//! Boundary zones (a campus, a gated estate, ...) discourage routes that exit and re-enter:
+//! every edge with one end inside and one outside costs an extra `penalty_meters`
+//! (default [`DEFAULT_BOUNDARY_PENALTY_METERS`]), e.g. `{"zone": "boundary", "penalty_meters": 300}`.
+
+use std::path::Path;
+use geo::{BoundingRect, Coord, Intersects, Line, LineString, MultiPolygon, Point, Polygon, Rect};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneKind {
+    /// Known to flood; impassable when the request sets `avoid_flood`
+    Flood,
+    /// Only scales the risk of edges inside it (see `Zone::multiplier`)
+    Risk,
+    /// Charges edges crossing its outline `Zone::penalty_meters`
+    Boundary,
+}
+
+/// Penalty of a boundary zone without a `penalty_meters` property
+pub const DEFAULT_BOUNDARY_PENALTY_METERS: f64 = 200.0;
+
+#[derive(Debug, Clone)]
+pub struct Zone {
+    pub kind: ZoneKind,
+    pub area: MultiPolygon,
+    /// Factor applied to the tag-based risk of edges in the zone (1.0 = unchanged)
+    pub multiplier: f32,
+    /// Extra cost for edges crossing the outline; only used by boundary zones
+    pub penalty_meters: f64,
+    bounds: Rect,
+}
+
+impl Zone {
+    /// True if the segment `a`-`b` (both `[lat, lon]`) touches or crosses the zone
+    pub fn intersects_segment(&self, a: [f64; 2], b: [f64; 2]) -> bool {
+        let line = Line::new(Coord { x: a[1], y: a[0] }, Coord { x: b[1], y: b[0] });
+        line.intersects(&self.bounds) && self.area.intersects(&line)
+    }
+
+    /// True if exactly one of `a` and `b` (both `[lat, lon]`) lies in the zone
+    pub fn separates(&self, a: [f64; 2], b: [f64; 2]) -> bool {
+        let contains = |p: [f64; 2]| self.area.intersects(&Point::new(p[1], p[0]));
+        self.intersects_segment(a, b) && contains(a) != contains(b)
+    }
+}
+
+/// All zones of one GeoJSON file
+#[derive(Debug, Clone, Default)]
+pub struct ZoneLayer {
+    zones: Vec<Zone>,
+}
+
+impl ZoneLayer {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read zone file {}: {e}", path.display()))?;
+        Self::from_geojson(&text)
+    }
+
+    /// Parses a GeoJSON `FeatureCollection`
+    pub fn from_geojson(text: &str) -> anyhow::Result<Self> {
+        let collection: FeatureCollection = serde_json::from_str(text)?;
+
+        let zones = collection.features.into_iter()
+            .enumerate()
+            .map(|(i, feature)| {
+                let area = feature.geometry.into_multi_polygon();
+                let bounds = area.bounding_rect()
+                    .ok_or_else(|| anyhow::anyhow!("zone feature {i}: polygon has no coordinates"))?;
+                let multiplier = feature.properties.multiplier.unwrap_or(1.0);
+                anyhow::ensure!(
+                    multiplier.is_finite() && multiplier > 0.0,
+                    "zone feature {i}: multiplier must be a positive number"
+                );
+                let penalty_meters = feature.properties.penalty_meters.unwrap_or(DEFAULT_BOUNDARY_PENALTY_METERS);
+                anyhow::ensure!(
+                    penalty_meters.is_finite() && penalty_meters >= 0.0,
+                    "zone feature {i}: penalty_meters must be a non-negative number"
+                );
+                Ok(Zone { kind: feature.properties.zone, area, multiplier, penalty_meters, bounds })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { zones })
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    /// True if any zone of `kind` intersects the segment `a`-`b` (both `[lat, lon]`)
+    pub fn intersects_segment(&self, kind: ZoneKind, a: [f64; 2], b: [f64; 2]) -> bool {
+        self.zones.iter().any(|z| z.kind == kind && z.intersects_segment(a, b))
+    }
+
+    /// Product of the multipliers of all zones the segment `a`-`b` intersects
+    pub fn risk_multiplier(&self, a: [f64; 2], b: [f64; 2]) -> f32 {
+        self.zones.iter()
+            .filter(|z| z.multiplier != 1.0 && z.intersects_segment(a, b))
+            .map(|z| z.multiplier)
+            .product()
+    }
+
+    /// Sum of the penalties of all boundary zones the segment `a`-`b` leaves or enters
+    pub fn boundary_penalty(&self, a: [f64; 2], b: [f64; 2]) -> f32 {
+        self.zones.iter()
+            .filter(|z| z.kind == ZoneKind::Boundary && z.separates(a, b))
+            .map(|z| z.penalty_meters)
+            .sum::<f64>() as f32
+    }
+}
+
+// --- GeoJSON subset ---
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometry,
+    properties: ZoneProperties,
+}
+
+#[derive(Deserialize)]
+struct ZoneProperties {
+    zone: ZoneKind,
+    multiplier: Option<f32>,
+    penalty_meters: Option<f64>,
+}
+
+/// Positions are `[lon, lat]`, as GeoJSON specifies
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+enum Geometry {
+    Polygon(Vec<Vec<[f64; 2]>>),
+    MultiPolygon(Vec<Vec<Vec<[f64; 2]>>>),
+}
+
+impl Geometry {
+    fn into_multi_polygon(self) -> MultiPolygon {
+        let polygons = match self {
+            Geometry::Polygon(rings) => vec![rings],
+            Geometry::MultiPolygon(polygons) => polygons,
+        };
+        MultiPolygon::new(polygons.into_iter().filter_map(polygon).collect())
+    }
+}
+
+/// First ring is the exterior, the rest are holes; empty polygons are dropped
+fn polygon(mut rings: Vec<Vec<[f64; 2]>>) -> Option<Polygon> {
+    let ring = |points: Vec<[f64; 2]>| LineString::from(points.into_iter().map(|[x, y]| Coord { x, y }).collect::<Vec<_>>());
+    if rings.is_empty() {
+        return None;
+    }
+    let exterior = ring(rings.remove(0));
+    Some(Polygon::new(exterior, rings.into_iter().map(ring).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::graph::NodeIndex;
+    use crate::graph::{NavigationGraph, RouteOptions};
+    use crate::safety::{SafetyConfig, SafetyMap};
+    use crate::test_graphs::grid;
+
+    #[test]
+    fn risk_zone_scales_only_the_edges_inside() {
+        // Covers the eastern column of the 3x3 grid, whose edges all have risk 0.1
+        let layer = ZoneLayer::from_geojson(r#"{"type": "FeatureCollection", "features": [{
+            "type": "Feature",
+            "properties": {"zone": "risk", "multiplier": 2.0},
+            "geometry": {"type": "Polygon", "coordinates": [[
+                [76.3715, 30.3395], [76.3725, 30.3395], [76.3725, 30.3425], [76.3715, 30.3425], [76.3715, 30.3395]
+            ]]}
+        }]}"#).unwrap();
+        let mut nav = grid(3);
+        nav.apply_zones(&layer, &SafetyMap::new(SafetyConfig::default()).unwrap());
+
+        let risk = |a: usize, b: usize| {
+            let edge = nav.graph.find_edge(NodeIndex::new(a), NodeIndex::new(b)).unwrap();
+            nav.graph[edge].safety_score
+        };
+        assert!((risk(0, 3) - 0.1).abs() < 1e-6, "outside: {}", risk(0, 3));
+        assert!((risk(2, 5) - 2.0 * risk(0, 3)).abs() < 1e-6, "inside: {}", risk(2, 5));
+    }
+
+    #[test]
+    fn route_stays_on_campus() {
+        // The campus covers the 3x3 grid but for a notch around node 1, the middle of the
+        // southern row: 0-1-2 leaves and re-enters it, 0-3-4-5-2 is longer but stays inside
+        let layer = ZoneLayer::from_geojson(r#"{"type": "FeatureCollection", "features": [{
+            "type": "Feature",
+            "properties": {"zone": "boundary"},
+            "geometry": {"type": "Polygon", "coordinates": [[
+                [76.3695, 30.3395], [76.3705, 30.3395], [76.3705, 30.3405], [76.3715, 30.3405], [76.3715, 30.3395],
+                [76.3725, 30.3395], [76.3725, 30.3425], [76.3695, 30.3425], [76.3695, 30.3395]
+            ]]}
+        }]}"#).unwrap();
+        let mut nav = grid(3);
+        let options = RouteOptions::default();
+        let route = |nav: &NavigationGraph| {
+            let (_, path) = nav.route(NodeIndex::new(0), NodeIndex::new(2), &options).unwrap();
+            path.iter().map(|n| n.index()).collect::<Vec<_>>()
+        };
+        assert_eq!(route(&nav), [0, 1, 2], "the shortcut, without the boundary");
+
+        nav.apply_zones(&layer, &SafetyMap::new(SafetyConfig::default()).unwrap());
+        assert_eq!(route(&nav), [0, 3, 4, 5, 2]);
+
+        // The penalty is a fixed charge, not scaled by the other options
+        let exit = &nav.graph[nav.graph.find_edge(NodeIndex::new(0), NodeIndex::new(1)).unwrap()];
+        assert_eq!(exit.boundary_penalty as f64, DEFAULT_BOUNDARY_PENALTY_METERS);
+        let shaded = RouteOptions { prefer_shade: 1.0, ..RouteOptions::default() };
+        let scaled = options.edge_cost(exit) - exit.boundary_penalty as f64;
+        assert!((shaded.edge_cost(exit) - (2.0 * scaled + exit.boundary_penalty as f64)).abs() < 1e-9);
+    }
+}